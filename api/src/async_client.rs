@@ -0,0 +1,1419 @@
+//! An async version of `Client`, enabled by the `async` feature, for use from async runtimes
+//! without blocking a thread on every request.
+//!
+//! Requests and responses use the same types as the blocking client, and the paginated
+//! iterators of the blocking client are available as `Stream`s.
+
+use chrono::{DateTime, Utc};
+use futures::stream;
+use http::Method;
+use log::debug;
+use reqwest::{
+    header::HeaderMap,
+    multipart::{Form, Part},
+    Client as HttpClient, IntoUrl, Proxy, Response as HttpResponse, Result as ReqwestResult,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    fmt::Display,
+    future::Future,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use url::Url;
+
+use crate::{
+    build_headers, id_list_query, rate_limit::RateLimiter, request_id, retry::Retrier,
+    AnnotatedComment, AuditQueryFilter, AuditQueryRequest, AuditQueryResponse, Bucket,
+    BucketFullName, BucketIdentifier, BucketStatistics, Comment, CommentFilter, CommentId,
+    CommentStatistics, CommentTimestampFilter, CommentUid, CommentsIter, CommentsIterPage,
+    CommentsIterTimerange, Config, Continuation, ContinuationKind, CreateBucketRequest,
+    CreateBucketResponse, CreateDatasetRequest, CreateDatasetResponse, CreateProjectRequest,
+    CreateProjectResponse, CreateQuota, CreateSourceRequest, CreateSourceResponse,
+    CreateUserRequest, CreateUserResponse, Dataset, DatasetFullName, DatasetIdentifier,
+    DatasetStatisticsRequestParams, Document, EmailContinuation, EmailsIter, EmailsIterPage,
+    EmptySuccess, Endpoints, Error, ForceDeleteProject, GetAnnotationsResponse,
+    GetAvailableBucketsResponse, GetAvailableDatasetsResponse, GetAvailableSourcesResponse,
+    GetAvailableUsersResponse, GetBucketResponse, GetBucketStatisticsResponse, GetCommentQuery,
+    GetCommentResponse, GetCommentsIterPageQuery, GetCurrentUserResponse, GetDatasetResponse,
+    GetEmailsIterPageQuery, GetIntegrationResponse, GetIntegrationsResponse, GetLabellingsAfter,
+    GetLabellingsInBulk, GetPredictionsResponse, GetProjectResponse, GetProjectsResponse,
+    GetQuotasResponse, GetRecentRequest, GetSourceResponse, GetStatisticsResponse,
+    GetStreamResponse, GetStreamsResponse, GetUserResponse, Integration, IntegrationFullName,
+    LabelName, LabelValidation, LabelValidationRequest, LabelValidationResponse, ModelVersion,
+    NewBucket, NewComment, NewDataset, NewEmail, NewEntities, NewIntegration, NewLabelling,
+    NewMoonForm, NewProject, NewSource, NewStream, NewUser, NoChargeQuery, PostIntegrationRequest,
+    PostIntegrationResponse, PostUserRequest, PostUserResponse, Prediction, Project, ProjectName,
+    PutCommentsRequest, PutCommentsResponse, PutEmailsRequest, PutEmailsResponse,
+    PutIntegrationRequest, PutIntegrationResponse, PutStreamRequest, PutStreamResponse,
+    QueryRequestParams, QueryResponse, Quota, RecentCommentsPage, Response, Result, Retry, Source,
+    SourceFullName, SourceId, SourceIdentifier, SourceStatisticsRequestParams, Stream,
+    StreamAdvanceRequest, StreamBatch, StreamException, StreamFetchRequest, StreamFullName,
+    StreamResetRequest, StreamSequenceId, SummaryRequestParams, SummaryResponse,
+    SyncCommentsRequest, SyncCommentsResponse, SyncRawEmailsRequest, SyncRawEmailsResponse,
+    TagStreamExceptionsRequest, TenantId, TenantQuotaKind, TransformTag, UpdateAnnotationsRequest,
+    UpdateDataset, UpdateDatasetRequest, UpdateDatasetResponse, UpdateProject,
+    UpdateProjectRequest, UpdateProjectResponse, UpdateSource, UpdateSourceRequest,
+    UpdateSourceResponse, UpdateUser, User, UserId, UserIdentifier, ValidationResponse,
+    WelcomeEmailResponse,
+};
+
+/// An async client for the Re:infer API, with the same methods as the blocking `Client`.
+#[derive(Debug)]
+pub struct AsyncClient {
+    endpoints: Endpoints,
+    http_client: HttpClient,
+    headers: HeaderMap,
+    retrier: Option<Retrier>,
+    rate_limiter: RateLimiter,
+}
+
+impl AsyncClient {
+    /// Create a new async API client.
+    pub fn new(config: Config) -> Result<AsyncClient> {
+        let http_client = build_http_client(&config)?;
+        let headers = build_headers(&config)?;
+        let endpoints = Endpoints::new(config.endpoint)?;
+        let retrier = config.retry_config.map(Retrier::new);
+        let rate_limiter = RateLimiter::new(config.rate_limit_config);
+        Ok(AsyncClient {
+            endpoints,
+            http_client,
+            headers,
+            retrier,
+            rate_limiter,
+        })
+    }
+
+    /// Get the base url for the client
+    pub fn base_url(&self) -> &Url {
+        &self.endpoints.base
+    }
+
+    /// List all visible sources.
+    pub async fn get_sources(&self) -> Result<Vec<Source>> {
+        Ok(self
+            .get::<_, GetAvailableSourcesResponse>(self.endpoints.sources.clone())
+            .await?
+            .sources)
+    }
+
+    /// Get a source by either id or name.
+    pub async fn get_user(&self, user: impl Into<UserIdentifier>) -> Result<User> {
+        Ok(match user.into() {
+            UserIdentifier::Id(user_id) => {
+                self.get::<_, GetUserResponse>(self.endpoints.user_by_id(&user_id)?)
+                    .await?
+                    .user
+            }
+        })
+    }
+
+    /// Get a source by either id or name.
+    pub async fn get_source(&self, source: impl Into<SourceIdentifier>) -> Result<Source> {
+        Ok(match source.into() {
+            SourceIdentifier::Id(source_id) => {
+                self.get::<_, GetSourceResponse>(self.endpoints.source_by_id(&source_id)?)
+                    .await?
+                    .source
+            }
+            SourceIdentifier::FullName(source_name) => {
+                self.get::<_, GetSourceResponse>(self.endpoints.source_by_name(&source_name)?)
+                    .await?
+                    .source
+            }
+        })
+    }
+
+    /// Create a new source.
+    pub async fn create_source(
+        &self,
+        source_name: &SourceFullName,
+        options: NewSource<'_>,
+    ) -> Result<Source> {
+        Ok(self
+            .put::<_, _, CreateSourceResponse>(
+                self.endpoints.source_by_name(source_name)?,
+                CreateSourceRequest { source: options },
+            )
+            .await?
+            .source)
+    }
+
+    /// Update a source.
+    pub async fn update_source(
+        &self,
+        source_name: &SourceFullName,
+        options: UpdateSource<'_>,
+    ) -> Result<Source> {
+        Ok(self
+            .post::<_, _, UpdateSourceResponse>(
+                self.endpoints.source_by_name(source_name)?,
+                UpdateSourceRequest { source: options },
+                Retry::Yes,
+            )
+            .await?
+            .source)
+    }
+
+    /// Delete a source.
+    pub async fn delete_source(&self, source: impl Into<SourceIdentifier>) -> Result<()> {
+        let source_id = match source.into() {
+            SourceIdentifier::Id(source_id) => source_id,
+            source @ SourceIdentifier::FullName(_) => self.get_source(source).await?.id,
+        };
+        self.delete(self.endpoints.source_by_id(&source_id)?).await
+    }
+
+    /// Set a quota
+    pub async fn create_quota(
+        &self,
+        target_tenant_id: &TenantId,
+        tenant_quota_kind: TenantQuotaKind,
+        options: CreateQuota,
+    ) -> Result<()> {
+        self.post(
+            self.endpoints.quota(target_tenant_id, tenant_quota_kind)?,
+            options,
+            Retry::Yes,
+        )
+        .await
+    }
+
+    /// Get quotas for current tenant
+    pub async fn get_quotas(&self) -> Result<Vec<Quota>> {
+        Ok(self
+            .get::<_, GetQuotasResponse>(self.endpoints.quotas()?)
+            .await?
+            .quotas)
+    }
+
+    /// Delete a user.
+    pub async fn delete_user(&self, user: impl Into<UserIdentifier>) -> Result<()> {
+        let UserIdentifier::Id(user_id) = user.into();
+        self.delete(self.endpoints.user_by_id(&user_id)?).await
+    }
+
+    /// Delete comments by id in a source.
+    pub async fn delete_comments(
+        &self,
+        source: impl Into<SourceIdentifier>,
+        comments: &[CommentId],
+    ) -> Result<()> {
+        let source_full_name = match source.into() {
+            source @ SourceIdentifier::Id(_) => self.get_source(source).await?.full_name(),
+            SourceIdentifier::FullName(source_full_name) => source_full_name,
+        };
+        self.delete_query(
+            self.endpoints.comments_v1(&source_full_name)?,
+            Some(&id_list_query(comments.iter().map(|uid| &uid.0))),
+        )
+        .await
+    }
+
+    /// Get a page of comments from a source.
+    pub async fn get_comments_iter_page(
+        &self,
+        source_name: &SourceFullName,
+        continuation: Option<&ContinuationKind>,
+        to_timestamp: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<CommentsIterPage> {
+        // Comments are returned from the API in increasing order of their
+        // `timestamp` field.
+        let (from_timestamp, after) = match continuation {
+            // If we have a timestamp, then this is a request for the first page of
+            // a series of comments with timestamps starting from the given time.
+            Some(ContinuationKind::Timestamp(from_timestamp)) => (Some(*from_timestamp), None),
+            // If we have a continuation, then this is a request for page n+1 of
+            // a series of comments, where the continuation came from page n.
+            Some(ContinuationKind::Continuation(after)) => (None, Some(after)),
+            // Otherwise, this is a request for the first page of a series of comments
+            // with timestamps starting from the beginning of time.
+            None => (None, None),
+        };
+        let query_params = GetCommentsIterPageQuery {
+            from_timestamp,
+            to_timestamp,
+            after,
+            limit,
+            include_markup: true,
+        };
+        self.get_query(self.endpoints.comments(source_name)?, Some(&query_params))
+            .await
+    }
+
+    /// Get a page of comments from a source.
+    pub async fn get_emails_iter_page(
+        &self,
+        bucket_name: &BucketFullName,
+        continuation: Option<&EmailContinuation>,
+        limit: usize,
+    ) -> Result<EmailsIterPage> {
+        let query_params = GetEmailsIterPageQuery {
+            continuation,
+            limit,
+        };
+        self.post(
+            self.endpoints.get_emails(bucket_name)?,
+            Some(&query_params),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    /// Get a single comment by id.
+    pub async fn get_comment<'a>(
+        &'a self,
+        source_name: &'a SourceFullName,
+        comment_id: &'a CommentId,
+    ) -> Result<Comment> {
+        let query_params = GetCommentQuery {
+            include_markup: true,
+        };
+        Ok(self
+            .get_query::<_, _, GetCommentResponse>(
+                self.endpoints.comment_by_id(source_name, comment_id)?,
+                Some(&query_params),
+            )
+            .await?
+            .comment)
+    }
+
+    pub async fn post_integration(
+        &self,
+        name: &IntegrationFullName,
+        integration: &NewIntegration,
+    ) -> Result<PostIntegrationResponse> {
+        self.request(
+            Method::POST,
+            self.endpoints.integration(name)?,
+            Some(PostIntegrationRequest {
+                integration: integration.clone(),
+            }),
+            None::<()>,
+            Retry::IfUnprocessed,
+        )
+        .await
+    }
+
+    pub async fn put_integration(
+        &self,
+        name: &IntegrationFullName,
+        integration: &NewIntegration,
+    ) -> Result<PutIntegrationResponse> {
+        self.request(
+            Method::PUT,
+            self.endpoints.integration(name)?,
+            Some(PutIntegrationRequest {
+                integration: integration.clone(),
+            }),
+            None::<()>,
+            Retry::IfUnprocessed,
+        )
+        .await
+    }
+
+    pub async fn put_comments(
+        &self,
+        source_name: &SourceFullName,
+        comments: &[NewComment],
+        no_charge: bool,
+    ) -> Result<PutCommentsResponse> {
+        self.request(
+            Method::PUT,
+            self.endpoints.put_comments(source_name)?,
+            Some(PutCommentsRequest { comments }),
+            Some(NoChargeQuery { no_charge }),
+            Retry::IfUnprocessed,
+        )
+        .await
+    }
+
+    pub async fn put_stream(
+        &self,
+        dataset_name: &DatasetFullName,
+        stream: &NewStream,
+    ) -> Result<PutStreamResponse> {
+        self.put(
+            self.endpoints.streams(dataset_name)?,
+            Some(PutStreamRequest { stream }),
+        )
+        .await
+    }
+
+    pub async fn get_audit_events(
+        &self,
+        minimum_timestamp: Option<DateTime<Utc>>,
+        maximum_timestamp: Option<DateTime<Utc>>,
+        continuation: Option<Continuation>,
+    ) -> Result<AuditQueryResponse> {
+        self.post::<_, _, AuditQueryResponse>(
+            self.endpoints.audit_events_query()?,
+            AuditQueryRequest {
+                continuation,
+                filter: AuditQueryFilter {
+                    timestamp: CommentTimestampFilter {
+                        minimum: minimum_timestamp,
+                        maximum: maximum_timestamp,
+                    },
+                },
+            },
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn get_validation(
+        &self,
+        dataset_name: &DatasetFullName,
+        model_version: &ModelVersion,
+    ) -> Result<ValidationResponse> {
+        self.get::<_, ValidationResponse>(self.endpoints.validation(dataset_name, model_version)?)
+            .await
+    }
+
+    pub async fn get_label_validation(
+        &self,
+        label: &LabelName,
+        dataset_name: &DatasetFullName,
+        model_version: &ModelVersion,
+    ) -> Result<LabelValidation> {
+        Ok(self
+            .post::<_, _, LabelValidationResponse>(
+                self.endpoints
+                    .label_validation(dataset_name, model_version)?,
+                LabelValidationRequest {
+                    label: label.clone(),
+                },
+                Retry::Yes,
+            )
+            .await?
+            .label_validation)
+    }
+
+    pub async fn sync_comments(
+        &self,
+        source_name: &SourceFullName,
+        comments: &[NewComment],
+        no_charge: bool,
+    ) -> Result<SyncCommentsResponse> {
+        self.request(
+            Method::POST,
+            self.endpoints.sync_comments(source_name)?,
+            Some(SyncCommentsRequest { comments }),
+            Some(NoChargeQuery { no_charge }),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn sync_raw_emails(
+        &self,
+        source_name: &SourceFullName,
+        documents: &[Document],
+        transform_tag: &TransformTag,
+        include_comments: bool,
+        no_charge: bool,
+    ) -> Result<SyncRawEmailsResponse> {
+        self.request(
+            Method::POST,
+            self.endpoints.sync_comments_raw_emails(source_name)?,
+            Some(SyncRawEmailsRequest {
+                documents,
+                transform_tag,
+                include_comments,
+            }),
+            Some(NoChargeQuery { no_charge }),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn put_emails(
+        &self,
+        bucket_name: &BucketFullName,
+        emails: &[NewEmail],
+        no_charge: bool,
+    ) -> Result<PutEmailsResponse> {
+        self.request(
+            Method::PUT,
+            self.endpoints.put_emails(bucket_name)?,
+            Some(PutEmailsRequest { emails }),
+            Some(NoChargeQuery { no_charge }),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn post_user(&self, user_id: &UserId, user: UpdateUser) -> Result<PostUserResponse> {
+        self.post(
+            self.endpoints.post_user(user_id)?,
+            PostUserRequest { user: &user },
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn get_integrations(&self) -> Result<Vec<Integration>> {
+        Ok(self
+            .get::<_, GetIntegrationsResponse>(self.endpoints.integrations()?)
+            .await?
+            .integrations)
+    }
+
+    pub async fn get_integration(&self, name: &IntegrationFullName) -> Result<Integration> {
+        Ok(self
+            .get::<_, GetIntegrationResponse>(self.endpoints.integration(name)?)
+            .await?
+            .integration)
+    }
+
+    pub async fn get_datasets(&self) -> Result<Vec<Dataset>> {
+        Ok(self
+            .get::<_, GetAvailableDatasetsResponse>(self.endpoints.datasets.clone())
+            .await?
+            .datasets)
+    }
+
+    pub async fn get_dataset<IdentifierT>(&self, dataset: IdentifierT) -> Result<Dataset>
+    where
+        IdentifierT: Into<DatasetIdentifier>,
+    {
+        Ok(match dataset.into() {
+            DatasetIdentifier::Id(dataset_id) => {
+                self.get::<_, GetDatasetResponse>(self.endpoints.dataset_by_id(&dataset_id)?)
+                    .await?
+                    .dataset
+            }
+            DatasetIdentifier::FullName(dataset_name) => {
+                self.get::<_, GetDatasetResponse>(self.endpoints.dataset_by_name(&dataset_name)?)
+                    .await?
+                    .dataset
+            }
+        })
+    }
+
+    /// Create a dataset.
+    pub async fn create_dataset(
+        &self,
+        dataset_name: &DatasetFullName,
+        options: NewDataset<'_>,
+    ) -> Result<Dataset> {
+        Ok(self
+            .put::<_, _, CreateDatasetResponse>(
+                self.endpoints.dataset_by_name(dataset_name)?,
+                CreateDatasetRequest { dataset: options },
+            )
+            .await?
+            .dataset)
+    }
+
+    /// Update a dataset.
+    pub async fn update_dataset(
+        &self,
+        dataset_name: &DatasetFullName,
+        options: UpdateDataset<'_>,
+    ) -> Result<Dataset> {
+        Ok(self
+            .post::<_, _, UpdateDatasetResponse>(
+                self.endpoints.dataset_by_name(dataset_name)?,
+                UpdateDatasetRequest { dataset: options },
+                Retry::Yes,
+            )
+            .await?
+            .dataset)
+    }
+
+    pub async fn delete_dataset<IdentifierT>(&self, dataset: IdentifierT) -> Result<()>
+    where
+        IdentifierT: Into<DatasetIdentifier>,
+    {
+        let dataset_id = match dataset.into() {
+            DatasetIdentifier::Id(dataset_id) => dataset_id,
+            dataset @ DatasetIdentifier::FullName(_) => self.get_dataset(dataset).await?.id,
+        };
+        self.delete(self.endpoints.dataset_by_id(&dataset_id)?)
+            .await
+    }
+
+    /// Get labellings for a given a dataset and a list of comment UIDs.
+    pub async fn get_labellings<'a>(
+        &self,
+        dataset_name: &DatasetFullName,
+        comment_uids: impl Iterator<Item = &'a CommentUid>,
+    ) -> Result<Vec<AnnotatedComment>> {
+        Ok(self
+            .get_query::<_, _, GetAnnotationsResponse>(
+                self.endpoints.get_labellings(dataset_name)?,
+                Some(&id_list_query(comment_uids.into_iter().map(|id| &id.0))),
+            )
+            .await?
+            .results)
+    }
+
+    /// Get reviewed comments in bulk
+    pub async fn get_labellings_in_bulk(
+        &self,
+        dataset_name: &DatasetFullName,
+        query_parameters: GetLabellingsInBulk<'_>,
+    ) -> Result<GetAnnotationsResponse> {
+        self.get_query::<_, _, GetAnnotationsResponse>(
+            self.endpoints.get_labellings(dataset_name)?,
+            Some(&query_parameters),
+        )
+        .await
+    }
+
+    /// Update labellings for a given a dataset and comment UID.
+    pub async fn update_labelling(
+        &self,
+        dataset_name: &DatasetFullName,
+        comment_uid: &CommentUid,
+        labelling: Option<&[NewLabelling]>,
+        entities: Option<&NewEntities>,
+        moon_forms: Option<&[NewMoonForm]>,
+    ) -> Result<AnnotatedComment> {
+        self.post::<_, _, AnnotatedComment>(
+            self.endpoints.post_labelling(dataset_name, comment_uid)?,
+            UpdateAnnotationsRequest {
+                labelling,
+                entities,
+                moon_forms,
+            },
+            Retry::Yes,
+        )
+        .await
+    }
+
+    /// Get predictions for a given a dataset, a model version, and a list of comment UIDs.
+    pub async fn get_comment_predictions<'a>(
+        &self,
+        dataset_name: &DatasetFullName,
+        model_version: &ModelVersion,
+        comment_uids: impl Iterator<Item = &'a CommentUid>,
+    ) -> Result<Vec<Prediction>> {
+        Ok(self
+            .post::<_, _, GetPredictionsResponse>(
+                self.endpoints
+                    .get_comment_predictions(dataset_name, model_version)?,
+                json!({
+                    "threshold": "auto",
+                    "uids": comment_uids.into_iter().map(|id| id.0.as_str()).collect::<Vec<_>>(),
+                }),
+                Retry::Yes,
+            )
+            .await?
+            .predictions)
+    }
+
+    pub async fn get_streams(&self, dataset_name: &DatasetFullName) -> Result<Vec<Stream>> {
+        Ok(self
+            .get::<_, GetStreamsResponse>(self.endpoints.streams(dataset_name)?)
+            .await?
+            .streams)
+    }
+
+    pub async fn get_recent_comments(
+        &self,
+        dataset_name: &DatasetFullName,
+        filter: &CommentFilter,
+        limit: usize,
+        continuation: Option<&Continuation>,
+    ) -> Result<RecentCommentsPage> {
+        self.post::<_, _, RecentCommentsPage>(
+            self.endpoints.recent_comments(dataset_name)?,
+            GetRecentRequest {
+                limit,
+                filter,
+                continuation,
+            },
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn get_current_user(&self) -> Result<User> {
+        Ok(self
+            .get::<_, GetCurrentUserResponse>(self.endpoints.current_user.clone())
+            .await?
+            .user)
+    }
+
+    pub async fn get_users(&self) -> Result<Vec<User>> {
+        Ok(self
+            .get::<_, GetAvailableUsersResponse>(self.endpoints.users.clone())
+            .await?
+            .users)
+    }
+
+    pub async fn create_user(&self, user: NewUser<'_>) -> Result<User> {
+        Ok(self
+            .put::<_, _, CreateUserResponse>(
+                self.endpoints.users.clone(),
+                CreateUserRequest { user },
+            )
+            .await?
+            .user)
+    }
+
+    pub async fn dataset_summary(
+        &self,
+        dataset_name: &DatasetFullName,
+        params: &SummaryRequestParams,
+    ) -> Result<SummaryResponse> {
+        self.post::<_, _, SummaryResponse>(
+            self.endpoints.dataset_summary(dataset_name)?,
+            serde_json::to_value(params).expect("summary params serialization error"),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn query_dataset(
+        &self,
+        dataset_name: &DatasetFullName,
+        params: &QueryRequestParams,
+    ) -> Result<QueryResponse> {
+        self.post::<_, _, QueryResponse>(
+            self.endpoints.query_dataset(dataset_name)?,
+            serde_json::to_value(params).expect("query params serialization error"),
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn send_welcome_email(&self, user_id: UserId) -> Result<()> {
+        self.post::<_, _, WelcomeEmailResponse>(
+            self.endpoints.welcome_email(&user_id)?,
+            json!({}),
+            Retry::IfUnprocessed,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_bucket_statistics(
+        &self,
+        bucket_name: &BucketFullName,
+    ) -> Result<BucketStatistics> {
+        Ok(self
+            .get::<_, GetBucketStatisticsResponse>(self.endpoints.bucket_statistics(bucket_name)?)
+            .await?
+            .statistics)
+    }
+
+    pub async fn get_dataset_statistics(
+        &self,
+        dataset_name: &DatasetFullName,
+        params: &DatasetStatisticsRequestParams,
+    ) -> Result<CommentStatistics> {
+        Ok(self
+            .post::<_, _, GetStatisticsResponse>(
+                self.endpoints.dataset_statistics(dataset_name)?,
+                serde_json::to_value(params)
+                    .expect("dataset statistics params serialization error"),
+                Retry::Yes,
+            )
+            .await?
+            .statistics)
+    }
+
+    pub async fn get_source_statistics(
+        &self,
+        source_name: &SourceFullName,
+        params: &SourceStatisticsRequestParams,
+    ) -> Result<CommentStatistics> {
+        Ok(self
+            .post::<_, _, GetStatisticsResponse>(
+                self.endpoints.source_statistics(source_name)?,
+                serde_json::to_value(params).expect("source statistics params serialization error"),
+                Retry::Yes,
+            )
+            .await?
+            .statistics)
+    }
+
+    /// Create a new bucket.
+    pub async fn create_bucket(
+        &self,
+        bucket_name: &BucketFullName,
+        options: NewBucket<'_>,
+    ) -> Result<Bucket> {
+        Ok(self
+            .put::<_, _, CreateBucketResponse>(
+                self.endpoints.bucket_by_name(bucket_name)?,
+                CreateBucketRequest { bucket: options },
+            )
+            .await?
+            .bucket)
+    }
+
+    pub async fn get_buckets(&self) -> Result<Vec<Bucket>> {
+        Ok(self
+            .get::<_, GetAvailableBucketsResponse>(self.endpoints.buckets.clone())
+            .await?
+            .buckets)
+    }
+
+    pub async fn get_bucket<IdentifierT>(&self, bucket: IdentifierT) -> Result<Bucket>
+    where
+        IdentifierT: Into<BucketIdentifier>,
+    {
+        Ok(match bucket.into() {
+            BucketIdentifier::Id(bucket_id) => {
+                self.get::<_, GetBucketResponse>(self.endpoints.bucket_by_id(&bucket_id)?)
+                    .await?
+                    .bucket
+            }
+            BucketIdentifier::FullName(bucket_name) => {
+                self.get::<_, GetBucketResponse>(self.endpoints.bucket_by_name(&bucket_name)?)
+                    .await?
+                    .bucket
+            }
+        })
+    }
+
+    pub async fn delete_bucket<IdentifierT>(&self, bucket: IdentifierT) -> Result<()>
+    where
+        IdentifierT: Into<BucketIdentifier>,
+    {
+        let bucket_id = match bucket.into() {
+            BucketIdentifier::Id(bucket_id) => bucket_id,
+            bucket @ BucketIdentifier::FullName(_) => self.get_bucket(bucket).await?.id,
+        };
+        self.delete(self.endpoints.bucket_by_id(&bucket_id)?).await
+    }
+
+    pub async fn fetch_stream_comments(
+        &self,
+        stream_name: &StreamFullName,
+        size: u32,
+    ) -> Result<StreamBatch> {
+        self.post(
+            self.endpoints.stream_fetch(stream_name)?,
+            StreamFetchRequest { size },
+            Retry::Yes,
+        )
+        .await
+    }
+
+    pub async fn get_stream(&self, stream_name: &StreamFullName) -> Result<Stream> {
+        Ok(self
+            .get::<_, GetStreamResponse>(self.endpoints.stream(stream_name)?)
+            .await?
+            .stream)
+    }
+
+    pub async fn advance_stream(
+        &self,
+        stream_name: &StreamFullName,
+        sequence_id: StreamSequenceId,
+    ) -> Result<()> {
+        self.post::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.stream_advance(stream_name)?,
+            StreamAdvanceRequest { sequence_id },
+            Retry::Yes,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn reset_stream(
+        &self,
+        stream_name: &StreamFullName,
+        to_comment_created_at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.post::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.stream_reset(stream_name)?,
+            StreamResetRequest {
+                to_comment_created_at,
+            },
+            Retry::Yes,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn tag_stream_exceptions(
+        &self,
+        stream_name: &StreamFullName,
+        exceptions: &[StreamException<'_>],
+    ) -> Result<()> {
+        self.put::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.stream_exceptions(stream_name)?,
+            TagStreamExceptionsRequest { exceptions },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Gets a project.
+    pub async fn get_project(&self, project_name: &ProjectName) -> Result<Project> {
+        let response = self
+            .get::<_, GetProjectResponse>(self.endpoints.project_by_name(project_name)?)
+            .await?;
+        Ok(response.project)
+    }
+
+    /// Gets all projects.
+    pub async fn get_projects(&self) -> Result<Vec<Project>> {
+        let response = self
+            .get::<_, GetProjectsResponse>(self.endpoints.projects.clone())
+            .await?;
+        Ok(response.projects)
+    }
+
+    /// Creates a new project.
+    pub async fn create_project(
+        &self,
+        project_name: &ProjectName,
+        options: NewProject<'_>,
+        user_ids: &[UserId],
+    ) -> Result<Project> {
+        Ok(self
+            .put::<_, _, CreateProjectResponse>(
+                self.endpoints.project_by_name(project_name)?,
+                CreateProjectRequest {
+                    project: options,
+                    user_ids,
+                },
+            )
+            .await?
+            .project)
+    }
+
+    /// Updates an existing project.
+    pub async fn update_project(
+        &self,
+        project_name: &ProjectName,
+        options: UpdateProject<'_>,
+    ) -> Result<Project> {
+        Ok(self
+            .post::<_, _, UpdateProjectResponse>(
+                self.endpoints.project_by_name(project_name)?,
+                UpdateProjectRequest { project: options },
+                Retry::Yes,
+            )
+            .await?
+            .project)
+    }
+
+    /// Deletes an existing project.
+    pub async fn delete_project(
+        &self,
+        project_name: &ProjectName,
+        force_delete: ForceDeleteProject,
+    ) -> Result<()> {
+        let endpoint = self.endpoints.project_by_name(project_name)?;
+        match force_delete {
+            ForceDeleteProject::No => self.delete(endpoint).await?,
+            ForceDeleteProject::Yes => {
+                self.delete_query(endpoint, Some(&json!({ "force": true })))
+                    .await?
+            }
+        };
+        Ok(())
+    }
+
+    pub async fn put_comment_audio(
+        &self,
+        source_id: &SourceId,
+        comment_id: &CommentId,
+        audio_path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let audio_path = audio_path.as_ref();
+        let audio = tokio::fs::read(audio_path)
+            .await
+            .map_err(|source| Error::Unknown {
+                message: "PUT comment audio operation failed".to_owned(),
+                source: source.into(),
+            })?;
+        let mut part = Part::bytes(audio);
+        if let Some(file_name) = audio_path.file_name() {
+            part = part.file_name(file_name.to_string_lossy().into_owned());
+        }
+        let url = self.endpoints.comment_audio(source_id, comment_id)?;
+        let http_response = self
+            .send_rate_limited(|| {
+                self.http_client
+                    .put(url)
+                    .headers(self.headers.clone())
+                    .multipart(Form::new().part("file", part))
+                    .send()
+            })
+            .await
+            .map_err(|source| Error::ReqwestError {
+                message: "PUT comment audio operation failed".to_owned(),
+                source,
+            })?;
+        let status = http_response.status();
+        let request_id = request_id(http_response.headers());
+        http_response
+            .json::<Response<EmptySuccess>>()
+            .await
+            .map_err(Error::BadJsonResponse)?
+            .into_result(status, request_id)?;
+        Ok(())
+    }
+
+    /// Stream all comments for a given dataset query, one page at a time.
+    pub fn get_dataset_query_iter<'a>(
+        &'a self,
+        dataset_name: &'a DatasetFullName,
+        params: &'a mut QueryRequestParams,
+    ) -> impl futures::Stream<Item = Result<Vec<AnnotatedComment>>> + 'a {
+        stream::unfold(Some(params), move |params| async move {
+            let params = params?;
+            Some(match self.query_dataset(dataset_name, params).await {
+                Ok(page) => {
+                    params.continuation = page.continuation;
+                    let done = params.continuation.is_none();
+                    (Ok(page.results), if done { None } else { Some(params) })
+                }
+                Err(error) => (Err(error), Some(params)),
+            })
+        })
+    }
+
+    /// Stream all comments in a source, one page at a time.
+    pub fn get_comments_iter<'a>(
+        &'a self,
+        source_name: &'a SourceFullName,
+        page_size: Option<usize>,
+        timerange: CommentsIterTimerange,
+    ) -> impl futures::Stream<Item = Result<Vec<Comment>>> + 'a {
+        self.comments_stream(
+            source_name,
+            page_size,
+            timerange.to,
+            timerange.from.map(ContinuationKind::Timestamp),
+        )
+    }
+
+    /// Stream the comments in a source which come after a given continuation.
+    pub fn get_comments_iter_after<'a>(
+        &'a self,
+        source_name: &'a SourceFullName,
+        page_size: Option<usize>,
+        to_timestamp: Option<DateTime<Utc>>,
+        after: Continuation,
+    ) -> impl futures::Stream<Item = Result<Vec<Comment>>> + 'a {
+        self.comments_stream(
+            source_name,
+            page_size,
+            to_timestamp,
+            Some(ContinuationKind::Continuation(after)),
+        )
+    }
+
+    fn comments_stream<'a>(
+        &'a self,
+        source_name: &'a SourceFullName,
+        page_size: Option<usize>,
+        to_timestamp: Option<DateTime<Utc>>,
+        continuation: Option<ContinuationKind>,
+    ) -> impl futures::Stream<Item = Result<Vec<Comment>>> + 'a {
+        let page_size = page_size.unwrap_or(CommentsIter::DEFAULT_PAGE_SIZE);
+        // The state is `None` once all pages have been returned.
+        stream::unfold(Some(continuation), move |continuation| async move {
+            let continuation = continuation?;
+            let response = self
+                .get_comments_iter_page(source_name, continuation.as_ref(), to_timestamp, page_size)
+                .await;
+            Some(match response {
+                Ok(page) => (
+                    Ok(page.comments),
+                    page.continuation
+                        .map(|continuation| Some(ContinuationKind::Continuation(continuation))),
+                ),
+                Err(error) => (Err(error), Some(continuation)),
+            })
+        })
+    }
+
+    /// Stream all emails in a bucket, one page at a time.
+    pub fn get_emails_iter<'a>(
+        &'a self,
+        bucket_name: &'a BucketFullName,
+        page_size: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Vec<NewEmail>>> + 'a {
+        let page_size = page_size.unwrap_or(EmailsIter::DEFAULT_PAGE_SIZE);
+        stream::unfold(Some(None), move |continuation| async move {
+            let continuation: Option<EmailContinuation> = continuation?;
+            let response = self
+                .get_emails_iter_page(bucket_name, continuation.as_ref(), page_size)
+                .await;
+            Some(match response {
+                Ok(page) => (Ok(page.emails), page.continuation.map(Some)),
+                Err(error) => (Err(error), Some(continuation)),
+            })
+        })
+    }
+
+    /// Stream all reviewed comments in a source, one page at a time.
+    pub fn get_labellings_iter<'a>(
+        &'a self,
+        dataset_name: &'a DatasetFullName,
+        source_id: &'a SourceId,
+        return_predictions: bool,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Vec<AnnotatedComment>>> + 'a {
+        self.labellings_stream(dataset_name, source_id, return_predictions, None, limit)
+    }
+
+    /// Stream the reviewed comments in a source which come after a given pagination token.
+    pub fn get_labellings_iter_after<'a>(
+        &'a self,
+        dataset_name: &'a DatasetFullName,
+        source_id: &'a SourceId,
+        return_predictions: bool,
+        after: GetLabellingsAfter,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Vec<AnnotatedComment>>> + 'a {
+        self.labellings_stream(
+            dataset_name,
+            source_id,
+            return_predictions,
+            Some(after),
+            limit,
+        )
+    }
+
+    fn labellings_stream<'a>(
+        &'a self,
+        dataset_name: &'a DatasetFullName,
+        source_id: &'a SourceId,
+        return_predictions: bool,
+        after: Option<GetLabellingsAfter>,
+        limit: Option<usize>,
+    ) -> impl futures::Stream<Item = Result<Vec<AnnotatedComment>>> + 'a {
+        stream::unfold(Some(after), move |after| async move {
+            let after = after?;
+            let response = self
+                .get_labellings_in_bulk(
+                    dataset_name,
+                    GetLabellingsInBulk {
+                        source_id,
+                        return_predictions: &return_predictions,
+                        after: &after,
+                        limit: &limit,
+                    },
+                )
+                .await;
+            Some(match response {
+                Ok(page) => {
+                    if after == page.after && !page.results.is_empty() {
+                        panic!("Labellings API did not increment pagination continuation");
+                    }
+                    let next = if page.results.is_empty() {
+                        None
+                    } else {
+                        Some(page.after)
+                    };
+                    (Ok(page.results), next)
+                }
+                Err(error) => (Err(error), Some(after)),
+            })
+        })
+    }
+
+    async fn get<LocationT, SuccessT>(&self, url: LocationT) -> Result<SuccessT>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        for<'de> SuccessT: Deserialize<'de>,
+    {
+        self.request(Method::GET, url, None::<()>, None::<()>, Retry::Yes)
+            .await
+    }
+
+    async fn get_query<LocationT, QueryT, SuccessT>(
+        &self,
+        url: LocationT,
+        query: Option<&QueryT>,
+    ) -> Result<SuccessT>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        QueryT: Serialize,
+        for<'de> SuccessT: Deserialize<'de>,
+    {
+        self.request(Method::GET, url, None::<()>, Some(query), Retry::Yes)
+            .await
+    }
+
+    async fn delete<LocationT>(&self, url: LocationT) -> Result<()>
+    where
+        LocationT: IntoUrl + Display + Clone,
+    {
+        self.delete_query::<LocationT, ()>(url, None).await
+    }
+
+    async fn delete_query<LocationT, QueryT>(
+        &self,
+        url: LocationT,
+        query: Option<&QueryT>,
+    ) -> Result<()>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        QueryT: Serialize,
+    {
+        debug!("Attempting DELETE `{}`", url);
+
+        let attempts = AtomicUsize::new(0);
+        let http_response = self
+            .with_retries(
+                || {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+
+                    let mut request = self
+                        .http_client
+                        .delete(url.clone())
+                        .headers(self.headers.clone());
+                    if let Some(query) = query {
+                        request = request.query(query);
+                    }
+                    request.send()
+                },
+                Retry::Yes,
+            )
+            .await
+            .map_err(|source| Error::ReqwestError {
+                source,
+                message: "DELETE operation failed.".to_owned(),
+            })?;
+        let status = http_response.status();
+        let request_id = request_id(http_response.headers());
+        http_response
+            .json::<Response<EmptySuccess>>()
+            .await
+            .map_err(Error::BadJsonResponse)?
+            .into_result(status, request_id)
+            .map_or_else(
+                // Ignore 404 not found if the request had to be re-tried - assume the target
+                // object was deleted on a previous incomplete request.
+                |error| {
+                    if attempts.load(Ordering::SeqCst) > 1
+                        && status == reqwest::StatusCode::NOT_FOUND
+                    {
+                        Ok(())
+                    } else {
+                        Err(error)
+                    }
+                },
+                |_| Ok(()),
+            )
+    }
+
+    async fn post<LocationT, RequestT, SuccessT>(
+        &self,
+        url: LocationT,
+        request: RequestT,
+        retry: Retry,
+    ) -> Result<SuccessT>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        RequestT: Serialize,
+        for<'de> SuccessT: Deserialize<'de>,
+    {
+        self.request(Method::POST, url, Some(request), None::<()>, retry)
+            .await
+    }
+
+    async fn put<LocationT, RequestT, SuccessT>(
+        &self,
+        url: LocationT,
+        request: RequestT,
+    ) -> Result<SuccessT>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        RequestT: Serialize,
+        for<'de> SuccessT: Deserialize<'de>,
+    {
+        self.request(Method::PUT, url, Some(request), None::<()>, Retry::Yes)
+            .await
+    }
+
+    async fn request<LocationT, RequestT, SuccessT, QueryT>(
+        &self,
+        method: Method,
+        url: LocationT,
+        body: Option<RequestT>,
+        query: Option<QueryT>,
+        retry: Retry,
+    ) -> Result<SuccessT>
+    where
+        LocationT: IntoUrl + Display + Clone,
+        RequestT: Serialize,
+        QueryT: Serialize,
+        for<'de> SuccessT: Deserialize<'de>,
+    {
+        debug!("Attempting {} `{}`", method, url);
+        let do_request = || {
+            let request = self
+                .http_client
+                .request(method.clone(), url.clone())
+                .headers(self.headers.clone());
+            let request = match &query {
+                Some(query) => request.query(query),
+                None => request,
+            };
+            let request = match &body {
+                Some(body) => request.json(body),
+                None => request,
+            };
+            request.send()
+        };
+
+        let http_response = self
+            .with_retries(do_request, retry)
+            .await
+            .map_err(|source| Error::ReqwestError {
+                source,
+                message: format!("{method} operation failed."),
+            })?;
+
+        let status = http_response.status();
+        let request_id = request_id(http_response.headers());
+
+        http_response
+            .json::<Response<SuccessT>>()
+            .await
+            .map_err(Error::BadJsonResponse)?
+            .into_result(status, request_id)
+    }
+
+    async fn with_retries<FutureT>(
+        &self,
+        send_request: impl Fn() -> FutureT,
+        retry: Retry,
+    ) -> ReqwestResult<HttpResponse>
+    where
+        FutureT: Future<Output = ReqwestResult<HttpResponse>>,
+    {
+        let send_request = || self.send_rate_limited(&send_request);
+        match &self.retrier {
+            Some(retrier) => retrier.with_retries_async(send_request, retry).await,
+            None => send_request().await,
+        }
+    }
+
+    async fn send_rate_limited<FutureT>(
+        &self,
+        send_request: impl FnOnce() -> FutureT,
+    ) -> ReqwestResult<HttpResponse>
+    where
+        FutureT: Future<Output = ReqwestResult<HttpResponse>>,
+    {
+        let wait = self.rate_limiter.reserve();
+        if !wait.is_zero() {
+            debug!("Rate limited - waiting {:?} before sending request.", wait);
+            tokio::time::sleep(wait).await;
+        }
+        let result = send_request().await;
+        if let Ok(response) = &result {
+            self.rate_limiter
+                .observe(response.status(), response.headers());
+        }
+        result
+    }
+}
+
+fn build_http_client(config: &Config) -> Result<HttpClient> {
+    let mut builder = HttpClient::builder()
+        .gzip(true)
+        .danger_accept_invalid_certs(config.accept_invalid_certificates);
+    if let Some(proxy) = config.proxy.clone() {
+        builder = builder.proxy(Proxy::all(proxy).map_err(Error::BuildHttpClient)?);
+    }
+    builder.build().map_err(Error::BuildHttpClient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncClient;
+    use crate::{CommentsIterTimerange, Config, SourceFullName, Token};
+    use futures::TryStreamExt;
+    use mockito::{mock, server_url, Matcher};
+    use serde_json::json;
+
+    fn client() -> AsyncClient {
+        AsyncClient::new(Config {
+            endpoint: server_url().parse().unwrap(),
+            token: Token("token".to_owned()),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn comment(id: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "uid": format!("abc.{id}"),
+            "timestamp": "2023-01-02T03:04:05Z",
+            "created_at": "2023-01-02T03:04:05Z",
+            "messages": [{"body": {"text": "Hello"}}]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_get_sources() {
+        let sources = mock("GET", "/api/v1/sources")
+            .match_header("authorization", "Bearer token")
+            .with_body(json!({"status": "ok", "sources": []}).to_string())
+            .create();
+
+        assert!(client().get_sources().await.unwrap().is_empty());
+        sources.assert();
+    }
+
+    #[tokio::test]
+    async fn test_api_error() {
+        let forbidden = mock("GET", "/api/v1/sources")
+            .with_status(403)
+            .with_header("x-request-id", "request-1")
+            .with_body(json!({"status": "error", "message": "Forbidden"}).to_string())
+            .create();
+
+        let error = client().get_sources().await.unwrap_err();
+        assert!(matches!(error, crate::Error::Forbidden { .. }));
+        assert_eq!(error.request_id(), Some("request-1"));
+        forbidden.assert();
+    }
+
+    #[tokio::test]
+    async fn test_get_comments_iter() {
+        let path = "/api/_private/sources/owner%2Fsource/comments";
+        let first_page = mock("GET", path)
+            .match_query(Matcher::Exact("limit=2&include_markup=true".to_owned()))
+            .with_body(
+                json!({"status": "ok", "comments": [comment("a"), comment("b")], "continuation": "next"})
+                    .to_string(),
+            )
+            .create();
+        let second_page = mock("GET", path)
+            .match_query(Matcher::Exact(
+                "after=next&limit=2&include_markup=true".to_owned(),
+            ))
+            .with_body(json!({"status": "ok", "comments": [comment("c")]}).to_string())
+            .create();
+
+        let client = client();
+        let source_name = SourceFullName("owner/source".to_owned());
+        let pages: Vec<_> = client
+            .get_comments_iter(&source_name, Some(2), CommentsIterTimerange::default())
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            pages
+                .iter()
+                .map(|page| page.iter().map(|comment| comment.id.0.as_str()).collect())
+                .collect::<Vec<Vec<_>>>(),
+            vec![vec!["a", "b"], vec!["c"]]
+        );
+        first_page.assert();
+        second_page.assert();
+    }
+}
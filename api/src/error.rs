@@ -9,6 +9,44 @@ pub enum Error {
     Api {
         status_code: StatusCode,
         message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Resource not found: {}", message)]
+    NotFound {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Unauthorized, the API token may be invalid or expired: {}", message)]
+    Unauthorized {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Permission denied: {}", message)]
+    Forbidden {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Conflict with the current state of the resource: {}", message)]
+    Conflict {
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Quota exceeded: {}", message)]
+    QuotaExceeded {
+        status_code: StatusCode,
+        message: String,
+        request_id: Option<String>,
+    },
+
+    #[error("Rate limited by the API: {}", message)]
+    RateLimited {
+        message: String,
+        request_id: Option<String>,
     },
 
     #[error("Invalid endpoint: '{}'", endpoint)]
@@ -86,4 +124,37 @@ pub enum Error {
         message: String,
         source: Box<dyn std::error::Error + Send + Sync + 'static>,
     },
+}
+
+impl Error {
+    /// The id the server assigned to the failed request, if it returned one. Quoting it in
+    /// support requests allows the request to be found in the server logs.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Error::Api { request_id, .. }
+            | Error::NotFound { request_id, .. }
+            | Error::Unauthorized { request_id, .. }
+            | Error::Forbidden { request_id, .. }
+            | Error::Conflict { request_id, .. }
+            | Error::QuotaExceeded { request_id, .. }
+            | Error::RateLimited { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status of the response which caused the error, if there was one.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            Error::Api { status_code, .. }
+            | Error::QuotaExceeded { status_code, .. }
+            | Error::BadProtocol { status_code, .. } => Some(*status_code),
+            Error::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Error::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+            Error::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            Error::Conflict { .. } => Some(StatusCode::CONFLICT),
+            Error::RateLimited { .. } => Some(StatusCode::TOO_MANY_REQUESTS),
+            Error::BadJsonResponse(source) | Error::ReqwestError { source, .. } => source.status(),
+            _ => None,
+        }
+    }
 }
\ No newline at end of file
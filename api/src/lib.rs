@@ -1,5 +1,8 @@
 #![deny(clippy::all)]
+#[cfg(feature = "async")]
+mod async_client;
 mod error;
+pub mod rate_limit;
 pub mod resources;
 pub mod retry;
 
@@ -29,14 +32,17 @@ use resources::{
     project::ForceDeleteProject,
     quota::{GetQuotasResponse, Quota},
     source::StatisticsRequestParams as SourceStatisticsRequestParams,
-    stream::{GetStreamResponse, NewStream, PutStreamRequest, PutStreamResponse},
+    stream::{
+        GetStreamResponse, NewStream, PutStreamRequest, PutStreamResponse,
+        UpdateRequest as UpdateStreamRequest, UpdateResponse as UpdateStreamResponse,
+    },
     validation::{
         LabelValidation, LabelValidationRequest, LabelValidationResponse, ValidationResponse,
     },
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::{cell::Cell, fmt::Display, path::Path};
+use std::{cell::Cell, fmt::Display, path::Path, thread::sleep};
 use url::Url;
 
 use crate::resources::{
@@ -57,11 +63,16 @@ use crate::resources::{
         UpdateRequest as UpdateDatasetRequest, UpdateResponse as UpdateDatasetResponse,
     },
     email::{PutEmailsRequest, PutEmailsResponse},
+    entity_def::{
+        CreateRequest as CreateEntityDefRequest, UpdateRequest as UpdateEntityDefRequest,
+    },
+    label_def::{CreateRequest as CreateLabelDefsRequest, UpdateRequest as UpdateLabelDefRequest},
     project::{
         CreateProjectRequest, CreateProjectResponse, GetProjectResponse, GetProjectsResponse,
         UpdateProjectRequest, UpdateProjectResponse,
     },
     quota::{CreateQuota, TenantQuotaKind},
+    request_id,
     source::{
         CreateRequest as CreateSourceRequest, CreateResponse as CreateSourceResponse,
         GetAvailableResponse as GetAvailableSourcesResponse, GetResponse as GetSourceResponse,
@@ -84,7 +95,13 @@ use crate::resources::{
     EmptySuccess, Response,
 };
 
-use crate::retry::{Retrier, RetryConfig};
+use crate::{
+    rate_limit::{RateLimitConfig, RateLimiter},
+    retry::{Retrier, RetryConfig},
+};
+
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncClient;
 
 pub use crate::{
     error::{Error, Result},
@@ -108,11 +125,13 @@ pub use crate::{
             Continuation as EmailContinuation, EmailsIterPage, Id as EmailId, Mailbox, MimeContent,
             NewEmail,
         },
-        entity_def::{EntityDef, Id as EntityDefId, Name as EntityName, NewEntityDef},
+        entity_def::{
+            EntityDef, Id as EntityDefId, Name as EntityName, NewEntityDef, UpdateEntityDef,
+        },
         integration::FullName as IntegrationFullName,
         label_def::{
             LabelDef, LabelDefPretrained, MoonFormFieldDef, Name as LabelName, NewLabelDef,
-            NewLabelDefPretrained, PretrainedId as LabelDefPretrainedId,
+            NewLabelDefPretrained, PretrainedId as LabelDefPretrainedId, UpdateLabelDef,
         },
         label_group::{
             LabelGroup, Name as LabelGroupName, NewLabelGroup, DEFAULT_LABEL_GROUP_NAME,
@@ -122,10 +141,10 @@ pub use crate::{
             FullName as SourceFullName, Id as SourceId, Identifier as SourceIdentifier,
             Name as SourceName, NewSource, Source, SourceKind, TransformTag, UpdateSource,
         },
-        statistics::Statistics as CommentStatistics,
+        statistics::{LabelCount, Statistics as CommentStatistics},
         stream::{
             Batch as StreamBatch, FullName as StreamFullName, SequenceId as StreamSequenceId,
-            Stream, StreamException, StreamExceptionMetadata,
+            Stream, StreamException, StreamExceptionMetadata, UpdateStream,
         },
         user::{
             Email, GlobalPermission, Id as UserId, Identifier as UserIdentifier,
@@ -142,9 +161,11 @@ pub struct Config {
     pub token: Token,
     pub accept_invalid_certificates: bool,
     pub proxy: Option<Url>,
-    /// Retry settings to use, if any. This will apply to all requests except for POST requests
-    /// which are not idempotent (as they cannot be naively retried).
+    /// Retry settings to use, if any. Requests which are not idempotent (as they cannot be naively
+    /// retried) are only retried if they were rate limited or could not reach the server.
     pub retry_config: Option<RetryConfig>,
+    /// Rate limit to apply to all requests, if any.
+    pub rate_limit_config: Option<RateLimitConfig>,
 }
 
 impl Default for Config {
@@ -155,6 +176,7 @@ impl Default for Config {
             accept_invalid_certificates: false,
             proxy: None,
             retry_config: None,
+            rate_limit_config: None,
         }
     }
 }
@@ -165,6 +187,7 @@ pub struct Client {
     http_client: HttpClient,
     headers: HeaderMap,
     retrier: Option<Retrier>,
+    rate_limiter: RateLimiter,
 }
 
 #[derive(Serialize)]
@@ -210,11 +233,13 @@ impl Client {
         let headers = build_headers(&config)?;
         let endpoints = Endpoints::new(config.endpoint)?;
         let retrier = config.retry_config.map(Retrier::new);
+        let rate_limiter = RateLimiter::new(config.rate_limit_config);
         Ok(Client {
             endpoints,
             http_client,
             headers,
             retrier,
+            rate_limiter,
         })
     }
 
@@ -223,6 +248,12 @@ impl Client {
         &self.endpoints.base
     }
 
+    /// Number of responses so far which asked the client to slow down, either with a 429 status
+    /// or a `Retry-After` header. Callers making concurrent requests can use this to back off.
+    pub fn throttled_responses(&self) -> u64 {
+        self.rate_limiter.throttled_responses()
+    }
+
     /// List all visible sources.
     pub fn get_sources(&self) -> Result<Vec<Source>> {
         Ok(self
@@ -385,6 +416,18 @@ impl Client {
         CommentsIter::new(self, source_name, page_size, timerange)
     }
 
+    /// Iterate through the comments in a source which come after a given continuation, as
+    /// previously returned by `CommentsIter::continuation`.
+    pub fn get_comments_iter_after<'a>(
+        &'a self,
+        source_name: &'a SourceFullName,
+        page_size: Option<usize>,
+        to_timestamp: Option<DateTime<Utc>>,
+        after: Continuation,
+    ) -> CommentsIter<'a> {
+        CommentsIter::new_after(self, source_name, page_size, to_timestamp, after)
+    }
+
     /// Get a page of comments from a source.
     pub fn get_emails_iter_page(
         &self,
@@ -440,7 +483,7 @@ impl Client {
                 integration: integration.clone(),
             }),
             None::<()>,
-            Retry::No,
+            Retry::IfUnprocessed,
         )
     }
 
@@ -456,7 +499,7 @@ impl Client {
                 integration: integration.clone(),
             }),
             None::<()>,
-            Retry::No,
+            Retry::IfUnprocessed,
         )
     }
 
@@ -471,7 +514,7 @@ impl Client {
             self.endpoints.put_comments(source_name)?,
             Some(PutCommentsRequest { comments }),
             Some(NoChargeQuery { no_charge }),
-            Retry::No,
+            Retry::IfUnprocessed,
         )
     }
 
@@ -604,21 +647,25 @@ impl Client {
                 message: "PUT comment audio operation failed".to_owned(),
                 source: source.into(),
             })?;
+        let url = self.endpoints.comment_audio(source_id, comment_id)?;
         let http_response = self
-            .http_client
-            .put(self.endpoints.comment_audio(source_id, comment_id)?)
-            .headers(self.headers.clone())
-            .multipart(form)
-            .send()
+            .send_rate_limited(|| {
+                self.http_client
+                    .put(url)
+                    .headers(self.headers.clone())
+                    .multipart(form)
+                    .send()
+            })
             .map_err(|source| Error::ReqwestError {
                 message: "PUT comment audio operation failed".to_owned(),
                 source,
             })?;
         let status = http_response.status();
+        let request_id = request_id(http_response.headers());
         http_response
             .json::<Response<EmptySuccess>>()
             .map_err(Error::BadJsonResponse)?
-            .into_result(status)?;
+            .into_result(status, request_id)?;
         Ok(())
     }
 
@@ -696,6 +743,87 @@ impl Client {
         self.delete(self.endpoints.dataset_by_id(&dataset_id)?)
     }
 
+    /// Create label defs in a label group of a dataset.
+    pub fn create_label_defs(
+        &self,
+        dataset_name: &DatasetFullName,
+        label_group: &LabelGroupName,
+        label_defs: &[NewLabelDef],
+    ) -> Result<()> {
+        self.put::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.label_defs(dataset_name, label_group)?,
+            CreateLabelDefsRequest { label_defs },
+        )?;
+        Ok(())
+    }
+
+    /// Rename a label def or change its title or description.
+    pub fn update_label_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        label_group: &LabelGroupName,
+        label_name: &LabelName,
+        label_def: UpdateLabelDef<'_>,
+    ) -> Result<()> {
+        self.post::<_, _, serde::de::IgnoredAny>(
+            self.endpoints
+                .label_def(dataset_name, label_group, label_name)?,
+            UpdateLabelDefRequest { label_def },
+            Retry::IfUnprocessed,
+        )?;
+        Ok(())
+    }
+
+    /// Delete a label def, along with all its annotations.
+    pub fn delete_label_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        label_group: &LabelGroupName,
+        label_name: &LabelName,
+    ) -> Result<()> {
+        self.delete(
+            self.endpoints
+                .label_def(dataset_name, label_group, label_name)?,
+        )
+    }
+
+    /// Create an entity def in a dataset.
+    pub fn create_entity_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        entity_def: &NewEntityDef,
+    ) -> Result<()> {
+        self.put::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.entity_defs(dataset_name)?,
+            CreateEntityDefRequest { entity_def },
+        )?;
+        Ok(())
+    }
+
+    /// Change the title, parents or trainability of an entity def.
+    pub fn update_entity_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        entity_name: &EntityName,
+        entity_def: UpdateEntityDef<'_>,
+    ) -> Result<()> {
+        self.post::<_, _, serde::de::IgnoredAny>(
+            self.endpoints.entity_def(dataset_name, entity_name)?,
+            UpdateEntityDefRequest { entity_def },
+            Retry::Yes,
+        )?;
+        Ok(())
+    }
+
+    /// Delete an entity def, along with all its annotations.
+    pub fn delete_entity_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        entity_name: &EntityName,
+    ) -> Result<()> {
+        self.delete(self.endpoints.entity_def(dataset_name, entity_name)?)
+    }
+
     /// Get labellings for a given a dataset and a list of comment UIDs.
     pub fn get_labellings<'a>(
         &self,
@@ -718,7 +846,34 @@ impl Client {
         return_predictions: bool,
         limit: Option<usize>,
     ) -> LabellingsIter<'a> {
-        LabellingsIter::new(self, dataset_name, source_id, return_predictions, limit)
+        LabellingsIter::new(
+            self,
+            dataset_name,
+            source_id,
+            return_predictions,
+            None,
+            limit,
+        )
+    }
+
+    /// Iterate through the reviewed comments in a source which come after a given pagination
+    /// token, as previously returned by `LabellingsIter::after`.
+    pub fn get_labellings_iter_after<'a>(
+        &'a self,
+        dataset_name: &'a DatasetFullName,
+        source_id: &'a SourceId,
+        return_predictions: bool,
+        after: GetLabellingsAfter,
+        limit: Option<usize>,
+    ) -> LabellingsIter<'a> {
+        LabellingsIter::new(
+            self,
+            dataset_name,
+            source_id,
+            return_predictions,
+            Some(after),
+            limit,
+        )
     }
 
     /// Get reviewed comments in bulk
@@ -793,7 +948,7 @@ impl Client {
                 filter,
                 continuation,
             },
-            Retry::No,
+            Retry::Yes,
         )
     }
 
@@ -846,7 +1001,7 @@ impl Client {
         self.post::<_, _, WelcomeEmailResponse>(
             self.endpoints.welcome_email(&user_id)?,
             json!({}),
-            Retry::No,
+            Retry::IfUnprocessed,
         )?;
         Ok(())
     }
@@ -867,7 +1022,7 @@ impl Client {
                 self.endpoints.dataset_statistics(dataset_name)?,
                 serde_json::to_value(params)
                     .expect("dataset statistics params serialization error"),
-                Retry::No,
+                Retry::Yes,
             )?
             .statistics)
     }
@@ -881,7 +1036,7 @@ impl Client {
             .post::<_, _, GetStatisticsResponse>(
                 self.endpoints.source_statistics(source_name)?,
                 serde_json::to_value(params).expect("source statistics params serialization error"),
-                Retry::No,
+                Retry::Yes,
             )?
             .statistics)
     }
@@ -941,7 +1096,7 @@ impl Client {
         self.post(
             self.endpoints.stream_fetch(stream_name)?,
             StreamFetchRequest { size },
-            Retry::No,
+            Retry::Yes,
         )
     }
 
@@ -951,6 +1106,24 @@ impl Client {
             .stream)
     }
 
+    pub fn update_stream(
+        &self,
+        stream_name: &StreamFullName,
+        options: UpdateStream<'_>,
+    ) -> Result<Stream> {
+        Ok(self
+            .post::<_, _, UpdateStreamResponse>(
+                self.endpoints.stream(stream_name)?,
+                UpdateStreamRequest { stream: options },
+                Retry::Yes,
+            )?
+            .stream)
+    }
+
+    pub fn delete_stream(&self, stream_name: &StreamFullName) -> Result<()> {
+        self.delete(self.endpoints.stream(stream_name)?)
+    }
+
     pub fn advance_stream(
         &self,
         stream_name: &StreamFullName,
@@ -959,7 +1132,7 @@ impl Client {
         self.post::<_, _, serde::de::IgnoredAny>(
             self.endpoints.stream_advance(stream_name)?,
             StreamAdvanceRequest { sequence_id },
-            Retry::No,
+            Retry::Yes,
         )?;
         Ok(())
     }
@@ -974,7 +1147,7 @@ impl Client {
             StreamResetRequest {
                 to_comment_created_at,
             },
-            Retry::No,
+            Retry::Yes,
         )?;
         Ok(())
     }
@@ -1090,27 +1263,31 @@ impl Client {
 
         let attempts = Cell::new(0);
         let http_response = self
-            .with_retries(|| {
-                attempts.set(attempts.get() + 1);
-
-                let mut request = self
-                    .http_client
-                    .delete(url.clone())
-                    .headers(self.headers.clone());
-                if let Some(query) = query {
-                    request = request.query(query);
-                }
-                request.send()
-            })
+            .with_retries(
+                || {
+                    attempts.set(attempts.get() + 1);
+
+                    let mut request = self
+                        .http_client
+                        .delete(url.clone())
+                        .headers(self.headers.clone());
+                    if let Some(query) = query {
+                        request = request.query(query);
+                    }
+                    request.send()
+                },
+                Retry::Yes,
+            )
             .map_err(|source| Error::ReqwestError {
                 source,
                 message: "DELETE operation failed.".to_owned(),
             })?;
         let status = http_response.status();
+        let request_id = request_id(http_response.headers());
         http_response
             .json::<Response<EmptySuccess>>()
             .map_err(Error::BadJsonResponse)?
-            .into_result(status)
+            .into_result(status, request_id)
             .map_or_else(
                 // Ignore 404 not found if the request had to be re-tried - assume the target
                 // object was deleted on a previous incomplete request.
@@ -1183,37 +1360,59 @@ impl Client {
             request.send()
         };
 
-        let result = match retry {
-            Retry::Yes => self.with_retries(do_request),
-            Retry::No => do_request(),
-        };
-        let http_response = result.map_err(|source| Error::ReqwestError {
-            source,
-            message: format!("{method} operation failed."),
-        })?;
+        let http_response =
+            self.with_retries(do_request, retry)
+                .map_err(|source| Error::ReqwestError {
+                    source,
+                    message: format!("{method} operation failed."),
+                })?;
 
         let status = http_response.status();
+        let request_id = request_id(http_response.headers());
 
         http_response
             .json::<Response<SuccessT>>()
             .map_err(Error::BadJsonResponse)?
-            .into_result(status)
+            .into_result(status, request_id)
     }
 
     fn with_retries(
         &self,
         send_request: impl Fn() -> ReqwestResult<HttpResponse>,
+        retry: Retry,
     ) -> ReqwestResult<HttpResponse> {
+        let send_request = || self.send_rate_limited(&send_request);
         match &self.retrier {
-            Some(retrier) => retrier.with_retries(send_request),
+            Some(retrier) => retrier.with_retries(send_request, retry),
             None => send_request(),
         }
     }
+
+    fn send_rate_limited(
+        &self,
+        send_request: impl FnOnce() -> ReqwestResult<HttpResponse>,
+    ) -> ReqwestResult<HttpResponse> {
+        let wait = self.rate_limiter.reserve();
+        if !wait.is_zero() {
+            debug!("Rate limited - waiting {:?} before sending request.", wait);
+            sleep(wait);
+        }
+        let result = send_request();
+        if let Ok(response) = &result {
+            self.rate_limiter
+                .observe(response.status(), response.headers());
+        }
+        result
+    }
 }
 
+/// How a request can be retried after a transient error.
 enum Retry {
+    /// The request is read-only or idempotent, so it can be retried after any transient error.
     Yes,
-    No,
+    /// The request has side effects, so it is only retried if the server cannot have acted on
+    /// it: when the connection failed or the request was rate limited.
+    IfUnprocessed,
 }
 
 pub struct DatasetQueryIter<'a> {
@@ -1236,6 +1435,11 @@ impl<'a> DatasetQueryIter<'a> {
             params,
         }
     }
+
+    /// The continuation which will be used to fetch the next page, if any.
+    pub fn continuation(&self) -> Option<&Continuation> {
+        self.params.continuation.as_ref()
+    }
 }
 
 impl<'a> Iterator for DatasetQueryIter<'a> {
@@ -1341,6 +1545,31 @@ impl<'a> CommentsIter<'a> {
             page_size: page_size.unwrap_or(Self::DEFAULT_PAGE_SIZE),
         }
     }
+
+    fn new_after(
+        client: &'a Client,
+        source_name: &'a SourceFullName,
+        page_size: Option<usize>,
+        to_timestamp: Option<DateTime<Utc>>,
+        after: Continuation,
+    ) -> Self {
+        Self {
+            client,
+            source_name,
+            to_timestamp,
+            continuation: Some(ContinuationKind::Continuation(after)),
+            done: false,
+            page_size: page_size.unwrap_or(Self::DEFAULT_PAGE_SIZE),
+        }
+    }
+
+    /// The continuation which will be used to fetch the next page, if any.
+    pub fn continuation(&self) -> Option<&Continuation> {
+        match &self.continuation {
+            Some(ContinuationKind::Continuation(continuation)) => Some(continuation),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> Iterator for CommentsIter<'a> {
@@ -1380,6 +1609,7 @@ impl<'a> LabellingsIter<'a> {
         dataset_name: &'a DatasetFullName,
         source_id: &'a SourceId,
         return_predictions: bool,
+        after: Option<GetLabellingsAfter>,
         limit: Option<usize>,
     ) -> Self {
         Self {
@@ -1387,11 +1617,16 @@ impl<'a> LabellingsIter<'a> {
             dataset_name,
             source_id,
             return_predictions,
-            after: None,
+            after,
             limit,
             done: false,
         }
     }
+
+    /// The pagination token which will be used to fetch the next page, if any.
+    pub fn after(&self) -> Option<&GetLabellingsAfter> {
+        self.after.as_ref()
+    }
 }
 
 impl<'a> Iterator for LabellingsIter<'a> {
@@ -1774,6 +2009,67 @@ impl Endpoints {
         construct_endpoint(&self.base, &["api", "v1", "datasets", &dataset_name.0])
     }
 
+    fn label_defs(
+        &self,
+        dataset_name: &DatasetFullName,
+        label_group: &LabelGroupName,
+    ) -> Result<Url> {
+        construct_endpoint(
+            &self.base,
+            &[
+                "api",
+                "_private",
+                "datasets",
+                &dataset_name.0,
+                "label-groups",
+                &label_group.0,
+                "labels",
+            ],
+        )
+    }
+
+    fn label_def(
+        &self,
+        dataset_name: &DatasetFullName,
+        label_group: &LabelGroupName,
+        label_name: &LabelName,
+    ) -> Result<Url> {
+        construct_endpoint(
+            &self.base,
+            &[
+                "api",
+                "_private",
+                "datasets",
+                &dataset_name.0,
+                "label-groups",
+                &label_group.0,
+                "labels",
+                &label_name.0,
+            ],
+        )
+    }
+
+    fn entity_defs(&self, dataset_name: &DatasetFullName) -> Result<Url> {
+        construct_endpoint(
+            &self.base,
+            &["api", "_private", "datasets", &dataset_name.0, "entities"],
+        )
+    }
+
+    fn entity_def(&self, dataset_name: &DatasetFullName, entity_name: &EntityName) -> Result<Url> {
+        construct_endpoint(
+            &self.base,
+            &[
+                "api",
+                "_private",
+                "datasets",
+                &dataset_name.0,
+                "entities",
+                &entity_name.0,
+            ],
+        )
+    }
+
     fn get_labellings(&self, dataset_name: &DatasetFullName) -> Result<Url> {
         construct_endpoint(
             &self.base,
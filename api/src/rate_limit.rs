@@ -0,0 +1,182 @@
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering::SeqCst},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Configuration for limiting the rate of requests made by the Reinfer client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimitConfig {
+    /// Maximum sustained number of requests per second. Up to this many requests (and at least
+    /// one) can be sent in a burst after the client has been idle.
+    pub requests_per_second: f64,
+}
+
+/// Token bucket shared by all the requests of a client. Requests reserve a token before being
+/// sent and wait for it if the bucket is empty. Independently of the configured rate, if the
+/// server responds with a `Retry-After` header, all requests are held back until then.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    config: Option<RateLimitConfig>,
+    state: Mutex<State>,
+    throttled_responses: AtomicU64,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    last_refill: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        let tokens = config.as_ref().map_or(0.0, RateLimitConfig::burst);
+        Self {
+            config,
+            state: Mutex::new(State {
+                tokens,
+                last_refill: Instant::now(),
+                paused_until: None,
+            }),
+            throttled_responses: AtomicU64::new(0),
+        }
+    }
+
+    /// Reserve a slot for a request, returning how long to wait before sending it.
+    pub fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+
+        let mut wait = state.paused_until.map_or(Duration::ZERO, |paused_until| {
+            paused_until.saturating_duration_since(now)
+        });
+
+        if let Some(config) = &self.config {
+            let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+            state.tokens =
+                (state.tokens + elapsed * config.requests_per_second).min(config.burst());
+            state.last_refill = now;
+            // Tokens can go negative, which queues up requests behind the ones already waiting.
+            state.tokens -= 1.0;
+            if state.tokens < 0.0 {
+                wait = wait.max(Duration::from_secs_f64(
+                    -state.tokens / config.requests_per_second,
+                ));
+            }
+        }
+        wait
+    }
+
+    /// Record the response to a request, holding back further requests if the server asked the
+    /// client to slow down.
+    pub fn observe(&self, status: StatusCode, headers: &HeaderMap) {
+        let retry_after = retry_after(headers);
+        if status != StatusCode::TOO_MANY_REQUESTS && retry_after.is_none() {
+            return;
+        }
+        self.throttled_responses.fetch_add(1, SeqCst);
+
+        if let Some(retry_after) = retry_after {
+            let paused_until = Instant::now() + retry_after;
+            let mut state = self.state.lock().expect("rate limiter lock poisoned");
+            if state
+                .paused_until
+                .is_none_or(|current| current < paused_until)
+            {
+                state.paused_until = Some(paused_until);
+            }
+        }
+    }
+
+    pub fn throttled_responses(&self) -> u64 {
+        self.throttled_responses.load(SeqCst)
+    }
+}
+
+impl RateLimitConfig {
+    fn burst(&self) -> f64 {
+        self.requests_per_second.max(1.0)
+    }
+}
+
+/// Parse a `Retry-After` header, which is either a number of seconds or an HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        date.with_timezone(&Utc)
+            .signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{retry_after, RateLimitConfig, RateLimiter};
+    use http::StatusCode;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new(Some(RateLimitConfig {
+            requests_per_second: 2.0,
+        }));
+
+        // The first two requests fit in the burst, after which they are spaced out.
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        let wait = limiter.reserve();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        let wait = limiter.reserve();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_retry_after_pauses_requests() {
+        let limiter = RateLimiter::new(None);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+
+        limiter.observe(StatusCode::OK, &HeaderMap::new());
+        assert_eq!(limiter.throttled_responses(), 0);
+
+        limiter.observe(StatusCode::TOO_MANY_REQUESTS, &HeaderMap::new());
+        assert_eq!(limiter.throttled_responses(), 1);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("3"));
+        limiter.observe(StatusCode::SERVICE_UNAVAILABLE, &headers);
+        assert_eq!(limiter.throttled_responses(), 2);
+        let wait = limiter.reserve();
+        assert!(wait > Duration::from_secs(2) && wait <= Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+}
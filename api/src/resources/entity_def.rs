@@ -23,3 +23,26 @@ pub struct NewEntityDef {
     pub title: String,
     pub trainable: bool,
 }
+
+/// Changes to an existing entity def. Fields which are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct UpdateEntityDef<'request> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inherits_from: Option<&'request [Id]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<&'request str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trainable: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub(crate) struct CreateRequest<'request> {
+    pub entity_def: &'request NewEntityDef,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub(crate) struct UpdateRequest<'request> {
+    pub entity_def: UpdateEntityDef<'request>,
+}
@@ -57,4 +57,28 @@ pub struct NewLabelDefPretrained {
 pub struct MoonFormFieldDef {
     pub name: String,
     pub kind: String,
-}
\ No newline at end of file
+}
+
+/// Changes to an existing label def. Fields which are `None` are left unchanged.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct UpdateLabelDef<'request> {
+    /// Rename the label, keeping its annotations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'request Name>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<&'request str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'request str>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub(crate) struct CreateRequest<'request> {
+    pub label_defs: &'request [NewLabelDef],
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub(crate) struct UpdateRequest<'request> {
+    pub label_def: UpdateLabelDef<'request>,
+}
@@ -20,9 +20,15 @@ pub mod user;
 pub mod validation;
 
 use crate::error::{Error, Result};
-use reqwest::StatusCode;
+use reqwest::{header::HeaderMap, StatusCode};
 use serde::Deserialize;
 
+/// Header in which the API returns the id it assigned to each request.
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error code in the body of API errors caused by a tenant quota being exceeded.
+const QUOTA_EXCEEDED_CODE: &str = "quota_exceeded";
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "status")]
 pub(crate) enum Response<SuccessT> {
@@ -36,22 +42,61 @@ pub(crate) enum Response<SuccessT> {
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct ApiError {
     message: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct EmptySuccess {}
 
 impl ApiError {
-    fn into_error_kind(self, status_code: StatusCode) -> Error {
-        Error::Api {
-            status_code,
-            message: self.message.unwrap_or_default(),
+    fn into_error_kind(self, status_code: StatusCode, request_id: Option<String>) -> Error {
+        let message = self.message.unwrap_or_default();
+        if self.code.as_deref() == Some(QUOTA_EXCEEDED_CODE)
+            || status_code == StatusCode::PAYMENT_REQUIRED
+        {
+            return Error::QuotaExceeded {
+                status_code,
+                message,
+                request_id,
+            };
+        }
+        match status_code {
+            StatusCode::NOT_FOUND => Error::NotFound {
+                message,
+                request_id,
+            },
+            StatusCode::UNAUTHORIZED => Error::Unauthorized {
+                message,
+                request_id,
+            },
+            StatusCode::FORBIDDEN => Error::Forbidden {
+                message,
+                request_id,
+            },
+            StatusCode::CONFLICT => Error::Conflict {
+                message,
+                request_id,
+            },
+            StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+                message,
+                request_id,
+            },
+            _ => Error::Api {
+                status_code,
+                message,
+                request_id,
+            },
         }
     }
 }
 
 impl<'de, SuccessT: Deserialize<'de>> Response<SuccessT> {
-    pub fn into_result(self, status_code: StatusCode) -> Result<SuccessT> {
+    pub fn into_result(
+        self,
+        status_code: StatusCode,
+        request_id: Option<String>,
+    ) -> Result<SuccessT> {
         match self {
             Response::Success(success) => {
                 if status_code.is_success() {
@@ -70,9 +115,71 @@ impl<'de, SuccessT: Deserialize<'de>> Response<SuccessT> {
                         message: error.message.unwrap_or_default(),
                     })
                 } else {
-                    Err(error.into_error_kind(status_code))
+                    Err(error.into_error_kind(status_code, request_id))
                 }
             }
         }
     }
+}
+
+pub(crate) fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmptySuccess, Response};
+    use crate::Error;
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    fn error_for(status_code: StatusCode, body: serde_json::Value) -> Error {
+        serde_json::from_value::<Response<EmptySuccess>>(body)
+            .unwrap()
+            .into_result(status_code, Some("request-1".to_owned()))
+            .unwrap_err()
+    }
+
+    #[test]
+    fn test_into_result_typed_errors() {
+        let body = json!({"status": "error", "message": "Nope"});
+
+        let error = error_for(StatusCode::NOT_FOUND, body.clone());
+        assert!(matches!(error, Error::NotFound { ref message, .. } if message == "Nope"));
+        assert_eq!(error.request_id(), Some("request-1"));
+
+        assert!(matches!(
+            error_for(StatusCode::UNAUTHORIZED, body.clone()),
+            Error::Unauthorized { .. }
+        ));
+        assert!(matches!(
+            error_for(StatusCode::FORBIDDEN, body.clone()),
+            Error::Forbidden { .. }
+        ));
+        assert!(matches!(
+            error_for(StatusCode::CONFLICT, body.clone()),
+            Error::Conflict { .. }
+        ));
+        assert!(matches!(
+            error_for(StatusCode::TOO_MANY_REQUESTS, body.clone()),
+            Error::RateLimited { .. }
+        ));
+        assert!(matches!(
+            error_for(StatusCode::BAD_REQUEST, body),
+            Error::Api {
+                status_code: StatusCode::BAD_REQUEST,
+                ..
+            }
+        ));
+        assert!(matches!(
+            error_for(
+                StatusCode::TOO_MANY_REQUESTS,
+                json!({"status": "error", "message": "Nope", "code": "quota_exceeded"})
+            ),
+            Error::QuotaExceeded { .. }
+        ));
+    }
 }
\ No newline at end of file
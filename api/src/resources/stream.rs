@@ -68,16 +68,57 @@ pub struct NewStream {
     pub comment_filter: Option<CommentFilter>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub model: Option<StreamModel>,
+    #[serde(
+        rename = "label_threshold_filter",
+        skip_serializing_if = "Option::is_none",
+        default
+    )]
+    pub label_filter: Option<LabelFilter>,
 }
 
 impl NewStream {
+    /// Pin the model and the label filter of the stream, if any, to a model version.
     pub fn set_model_version(&mut self, model_version: &ModelVersion) {
         if let Some(model) = &mut self.model {
             model.version = model_version.clone()
         }
+        if let Some(label_filter) = &mut self.label_filter {
+            label_filter.model_version = model_version.clone()
+        }
     }
 }
 
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateStream<'request> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<&'request str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'request str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment_filter: Option<&'request CommentFilter>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<&'request StreamModel>,
+
+    #[serde(
+        rename = "label_threshold_filter",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub label_filter: Option<&'request LabelFilter>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct UpdateRequest<'request> {
+    pub stream: UpdateStream<'request>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct UpdateResponse {
+    pub stream: Stream,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct StreamModel {
     pub version: ModelVersion,
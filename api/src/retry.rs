@@ -1,10 +1,13 @@
 
 use http::StatusCode;
-use reqwest::{blocking::Response, Result};
+use rand::Rng;
+use reqwest::{blocking::Response, header::HeaderMap, Error, Result};
 use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
 use std::thread::sleep;
 use std::time::Duration;
 
+use crate::{rate_limit::retry_after, Retry};
+
 /// Strategy to use if retrying .
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RetryStrategy {
@@ -25,8 +28,9 @@ pub struct RetryConfig {
     pub max_retry_count: u8,
     /// Amount of time to wait for first retry.
     pub base_wait: Duration,
-    /// Amount of time to scale retry waits. The wait before retry N is an exponential backoff
-    /// using the formula `wait = retry_wait * (backoff_factor * N)`.
+    /// Amount of time to scale retry waits. The wait before retry N is a random duration (full
+    /// jitter) up to the exponential backoff `base_wait * backoff_factor ^ N`, unless the server
+    /// asked for a specific wait with a `Retry-After` header.
     pub backoff_factor: f64,
 }
 
@@ -44,33 +48,62 @@ impl Retrier {
         }
     }
 
-    fn should_retry(status: StatusCode) -> bool {
-        status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+    fn should_retry(status: StatusCode, retry: &Retry) -> bool {
+        match retry {
+            Retry::Yes => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            Retry::IfUnprocessed => status == StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn should_retry_error(error: &Error, retry: &Retry) -> bool {
+        match retry {
+            Retry::Yes => error.is_timeout() || error.is_connect() || error.is_request(),
+            Retry::IfUnprocessed => error.is_connect(),
+        }
+    }
+
+    /// Whether the next request may be retried at all, given the strategy.
+    fn retries_enabled(&self) -> bool {
+        !(self.is_first_request.swap(false, SeqCst)
+            && self.config.strategy == RetryStrategy::Automatic)
     }
 
-    pub fn with_retries(&self, send_request: impl Fn() -> Result<Response>) -> Result<Response> {
-        if self.is_first_request.swap(false, SeqCst)
-            && self.config.strategy == RetryStrategy::Automatic
-        {
+    fn wait_duration(&self, i_retry: u8, headers: Option<&HeaderMap>) -> Duration {
+        if let Some(retry_after) = headers.and_then(retry_after) {
+            return retry_after;
+        }
+        let wait_factor = self.config.backoff_factor.powi(i_retry.into());
+        let max_wait = self.config.base_wait.mul_f64(wait_factor);
+        max_wait.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    pub fn with_retries(
+        &self,
+        send_request: impl Fn() -> Result<Response>,
+        retry: Retry,
+    ) -> Result<Response> {
+        if !self.retries_enabled() {
             return send_request();
         }
 
         for i_retry in 0..self.config.max_retry_count {
             macro_rules! warn_and_sleep {
-                ($src:expr) => {{
-                    let wait_factor = self.config.backoff_factor.powi(i_retry.into());
-                    let duration = self.config.base_wait.mul_f64(wait_factor);
+                ($src:expr, $headers:expr) => {{
+                    let duration = self.wait_duration(i_retry, $headers);
                     log::warn!("{} - retrying after {:?}.", $src, duration);
                     sleep(duration)
                 }};
             }
 
             match send_request() {
-                Ok(response) if Self::should_retry(response.status()) => {
-                    warn_and_sleep!(format!("{} for {}", response.status(), response.url()))
+                Ok(response) if Self::should_retry(response.status(), &retry) => {
+                    warn_and_sleep!(
+                        format!("{} for {}", response.status(), response.url()),
+                        Some(response.headers())
+                    )
                 }
-                Err(error) if error.is_timeout() || error.is_connect() || error.is_request() => {
-                    warn_and_sleep!(error)
+                Err(error) if Self::should_retry_error(&error, &retry) => {
+                    warn_and_sleep!(error, None)
                 }
                 // If anything else, just return it immediately
                 result => return result,
@@ -80,13 +113,51 @@ impl Retrier {
         // On last retry don't handle the error, just propagate all errors.
         send_request()
     }
+
+    /// The same as `with_retries`, for requests made by the async client.
+    #[cfg(feature = "async")]
+    pub async fn with_retries_async<FutureT>(
+        &self,
+        send_request: impl Fn() -> FutureT,
+        retry: Retry,
+    ) -> Result<reqwest::Response>
+    where
+        FutureT: std::future::Future<Output = Result<reqwest::Response>>,
+    {
+        if !self.retries_enabled() {
+            return send_request().await;
+        }
+
+        for i_retry in 0..self.config.max_retry_count {
+            let (source, duration) = match send_request().await {
+                Ok(response) if Self::should_retry(response.status(), &retry) => (
+                    format!("{} for {}", response.status(), response.url()),
+                    self.wait_duration(i_retry, Some(response.headers())),
+                ),
+                Err(error) if Self::should_retry_error(&error, &retry) => {
+                    (error.to_string(), self.wait_duration(i_retry, None))
+                }
+                // If anything else, just return it immediately
+                result => return result,
+            };
+            log::warn!("{} - retrying after {:?}.", source, duration);
+            tokio::time::sleep(duration).await;
+        }
+
+        // On last retry don't handle the error, just propagate all errors.
+        send_request().await
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{Retrier, RetryConfig, RetryStrategy};
+    use crate::Retry;
     use mockito::{mock, server_address};
-    use reqwest::blocking::{get, Client};
+    use reqwest::{
+        blocking::{get, Client},
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+    };
     use std::thread::sleep;
     use std::time::Duration;
 
@@ -103,7 +174,7 @@ mod tests {
         let ok = mock("GET", "/").expect(1).create();
         assert!(
             handler
-                .with_retries(|| get(format!("http://{}", server_address())))
+                .with_retries(|| get(format!("http://{}", server_address())), Retry::Yes)
                 .unwrap()
                 .status()
                 == 200
@@ -119,7 +190,7 @@ mod tests {
             handler.config.max_retry_count = i_retry;
             assert!(
                 handler
-                    .with_retries(|| get(format!("http://{}", server_address())))
+                    .with_retries(|| get(format!("http://{}", server_address())), Retry::Yes)
                     .unwrap()
                     .status()
                     == 500
@@ -141,7 +212,7 @@ mod tests {
         let err = mock("GET", "/").with_status(500).expect(1).create();
         assert!(
             handler
-                .with_retries(|| get(format!("http://{}", server_address())))
+                .with_retries(|| get(format!("http://{}", server_address())), Retry::Yes)
                 .unwrap()
                 .status()
                 == 500
@@ -152,7 +223,7 @@ mod tests {
         let ok = mock("GET", "/").expect(1).create();
         assert!(
             handler
-                .with_retries(|| get(format!("http://{}", server_address())))
+                .with_retries(|| get(format!("http://{}", server_address())), Retry::Yes)
                 .unwrap()
                 .status()
                 == 200
@@ -168,7 +239,7 @@ mod tests {
             handler.config.max_retry_count = i_retry;
             assert!(
                 handler
-                    .with_retries(|| get(format!("http://{}", server_address())))
+                    .with_retries(|| get(format!("http://{}", server_address())), Retry::Yes)
                     .unwrap()
                     .status()
                     == 500
@@ -196,17 +267,82 @@ mod tests {
             .create();
         let client = Client::new();
         assert!(handler
-            .with_retries(|| client
-                .get(format!("http://{}", server_address()))
-                .timeout(Duration::from_secs_f64(0.1))
-                .send()
-                .and_then(|r| {
-                    // This is a bit of a hack to force a timeout
-                    let _ = r.text()?;
-                    unreachable!()
-                }))
+            .with_retries(
+                || client
+                    .get(format!("http://{}", server_address()))
+                    .timeout(Duration::from_secs_f64(0.1))
+                    .send()
+                    .and_then(|r| {
+                        // This is a bit of a hack to force a timeout
+                        let _ = r.text()?;
+                        unreachable!()
+                    }),
+                Retry::Yes
+            )
             .unwrap_err()
             .is_timeout());
         timeout.assert();
     }
+
+    #[test]
+    fn test_retry_if_unprocessed() {
+        let handler = Retrier::new(RetryConfig {
+            strategy: RetryStrategy::Always,
+            max_retry_count: 2,
+            base_wait: Duration::from_secs(0),
+            backoff_factor: 0.0,
+        });
+        let url = format!("http://{}/unprocessed", server_address());
+
+        // Server errors may have happened after the request was acted on, so they aren't retried.
+        let err = mock("POST", "/unprocessed")
+            .with_status(500)
+            .expect(1)
+            .create();
+        assert_eq!(
+            handler
+                .with_retries(|| Client::new().post(&url).send(), Retry::IfUnprocessed)
+                .unwrap()
+                .status(),
+            500
+        );
+        err.assert();
+
+        // Rate limited requests were not processed, so they are.
+        let limited = mock("POST", "/unprocessed")
+            .with_status(429)
+            .expect(3)
+            .create();
+        assert_eq!(
+            handler
+                .with_retries(|| Client::new().post(&url).send(), Retry::IfUnprocessed)
+                .unwrap()
+                .status(),
+            429
+        );
+        limited.assert();
+    }
+
+    #[test]
+    fn test_wait_duration() {
+        let handler = Retrier::new(RetryConfig {
+            strategy: RetryStrategy::Always,
+            max_retry_count: 5,
+            base_wait: Duration::from_secs(1),
+            backoff_factor: 2.0,
+        });
+
+        // Waits are jittered up to the exponential backoff.
+        for i_retry in 0..5 {
+            assert!(handler.wait_duration(i_retry, None) <= Duration::from_secs(1 << i_retry));
+        }
+
+        // Unless the server asks for a specific wait.
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(
+            handler.wait_duration(0, Some(&headers)),
+            Duration::from_secs(7)
+        );
+    }
 }
\ No newline at end of file
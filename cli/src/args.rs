@@ -1,15 +1,44 @@
 
 use crate::{
     commands::{
-        config::ConfigArgs, create::CreateArgs, delete::DeleteArgs, get::GetArgs, parse::ParseArgs,
-        update::UpdateArgs,
+        apply::ApplyArgs, clone::CloneArgs, config::ConfigArgs, create::CreateArgs,
+        delete::DeleteArgs, export::ExportArgs, get::GetArgs, migrate::MigrateArgs,
+        parse::ParseArgs, stream::StreamArgs, update::UpdateArgs,
     },
+    config::ContextDefaults,
+    errors::EXIT_CODES_HELP,
     printer::OutputFormat,
 };
 use anyhow::{anyhow, Error, Result};
+use once_cell::sync::OnceCell;
 use reqwest::Url;
 use std::{path::PathBuf, str::FromStr};
-use structopt::StructOpt;
+use structopt::{clap::Arg, StructOpt};
+
+/// The defaults of the selected context, set before the arguments of the command are parsed.
+static CONTEXT_DEFAULTS: OnceCell<ContextDefaults> = OnceCell::new();
+
+pub fn set_context_defaults(defaults: ContextDefaults) {
+    let _ = CONTEXT_DEFAULTS.set(defaults);
+}
+
+/// Make an argument fall back to a default of the selected context, given by its key in
+/// `CONTEXT_DEFAULT_KEYS`, when it is neither passed nor set by its environment variable.
+pub trait ContextDefault {
+    fn context_default(self, key: &str) -> Self;
+}
+
+impl ContextDefault for Arg<'_, '_> {
+    fn context_default(self, key: &str) -> Self {
+        match CONTEXT_DEFAULTS
+            .get()
+            .and_then(|defaults| defaults.argument(key))
+        {
+            Some(default) => self.default_value(default),
+            None => self,
+        }
+    }
+}
 
 /// re is the command line interface to reinfer clusters.
 #[derive(Debug, StructOpt)]
@@ -17,15 +46,27 @@ use structopt::StructOpt;
     global_settings = &[
         structopt::clap::AppSettings::ColoredHelp,
         structopt::clap::AppSettings::InferSubcommands,
-    ]
+    ],
+    after_help = EXIT_CODES_HELP
 )]
 pub struct Args {
+    #[structopt(flatten)]
+    pub global: GlobalArgs,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+// The options of `re` which come before the command.
+#[derive(Debug, StructOpt)]
+pub struct GlobalArgs {
     #[structopt(long = "config-file", parse(from_os_str))]
     /// Path to the configuration file. Typically defaults to ~/.config/reinfer on Linux.
     pub config: Option<PathBuf>,
 
     #[structopt(short = "c", long = "context")]
-    /// Specify what context to use. Overrides the current context, if any.
+    /// Specify what context to use. Overrides the REINFER_CONTEXT environment
+    /// variable and the current context, if any.
     pub context: Option<String>,
 
     #[structopt(short = "v", long = "verbose")]
@@ -33,35 +74,75 @@ pub struct Args {
     pub verbose: bool,
 
     #[structopt(long = "endpoint", parse(try_from_str))]
-    /// Specify what endpoint to use. Overrides the one from the current
-    /// context, if any.
+    /// Specify what endpoint to use. Overrides the REINFER_ENDPOINT environment
+    /// variable and the endpoint of the context, if any.
     pub endpoint: Option<Url>,
 
     #[structopt(short = "k", long = "accept-invalid-certificates", parse(try_from_str))]
     pub accept_invalid_certificates: Option<bool>,
 
     #[structopt(long = "token")]
-    /// Specify what API token to use. Overrides the one from the current
-    /// context, if any.
+    /// Specify what API token to use. Overrides the REINFER_TOKEN environment
+    /// variable and the token of the context, if any. The token of a context
+    /// is read from its token file if it has one, then from the credential
+    /// store, then from the config file.
     pub token: Option<String>,
 
     #[structopt(long = "proxy")]
     /// URL for an HTTP proxy that will be used for all requests if specified
     pub proxy: Option<Url>,
 
-    #[structopt(short = "o", long = "output", default_value = "table")]
-    /// Output format. One of: json, table
+    #[structopt(
+        short = "o",
+        long = "output",
+        default_value = "table",
+        env = "REINFER_OUTPUT",
+        context_default = "output"
+    )]
+    /// Output format. One of: json, jsonl, yaml, csv, table, jsonpath=<path>,
+    /// custom-columns=<NAME:path,...>
     ///
-    /// Output is provided in table format on stdout by default.
+    /// Output is provided in table format on stdout by default. `json` pretty prints each
+    /// resource, while `jsonl` prints one resource per line. `jsonpath` prints the values matched
+    /// by a JSONPath expression such as `{.id}`, and `custom-columns` prints a table with a
+    /// column for each JSONPath expression.
     pub output: OutputFormat,
 
-    #[structopt(subcommand)]
-    pub command: Command,
+    #[structopt(long = "fields", use_delimiter = true)]
+    /// Comma separated list of fields to keep from each resource, e.g. `id,owner.name`. Only
+    /// applicable to json, jsonl, yaml and csv output.
+    pub fields: Vec<String>,
 
-    #[structopt(long = "num-threads", default_value = "32")]
+    #[structopt(long = "error-format", default_value = "text")]
+    /// Format of errors printed on stderr. One of: text, json
+    ///
+    /// `json` prints a single object with the error message and its causes, the kind of error,
+    /// the exit code and, for API errors, the HTTP status and the request id.
+    pub error_format: ErrorFormat,
+
+    #[structopt(long = "num-threads")]
     /// The number of threads to use when uploading annotations and emls. Can be overwritten by the
-    /// REINFER_CLI_NUM_THREADS environment variable
-    pub num_threads: u32,
+    /// REINFER_CLI_NUM_THREADS environment variable [default: 32]
+    pub num_threads: Option<u32>,
+}
+
+/// Only the global options of `re`, which are parsed before the command to find the context whose
+/// defaults the arguments of the command fall back to.
+#[derive(Debug, StructOpt)]
+pub struct PreArgs {
+    #[structopt(flatten)]
+    pub global: GlobalArgs,
+
+    #[structopt(subcommand)]
+    _command: Option<AnyCommand>,
+}
+
+/// Any command with its arguments, which are only parsed by `Args`.
+#[allow(dead_code)]
+#[derive(Debug, StructOpt)]
+pub enum AnyCommand {
+    #[structopt(external_subcommand)]
+    Any(Vec<String>),
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -112,6 +193,41 @@ pub enum Command {
         #[structopt(subcommand)]
         parse_args: ParseArgs,
     },
+
+    #[structopt(name = "apply")]
+    /// Create or update the resources described in a project manifest
+    Apply {
+        #[structopt(flatten)]
+        apply_args: ApplyArgs,
+    },
+
+    #[structopt(name = "export")]
+    /// Export resources as manifests
+    Export {
+        #[structopt(subcommand)]
+        export_args: ExportArgs,
+    },
+
+    #[structopt(name = "clone")]
+    /// Copy resources to another dataset
+    Clone {
+        #[structopt(subcommand)]
+        clone_args: CloneArgs,
+    },
+
+    #[structopt(name = "stream")]
+    /// Consume the comments of streams and tune their thresholds
+    Stream {
+        #[structopt(subcommand)]
+        stream_args: StreamArgs,
+    },
+
+    #[structopt(name = "migrate")]
+    /// Copy resources from one context to another
+    Migrate {
+        #[structopt(subcommand)]
+        migrate_args: MigrateArgs,
+    },
 }
 
 #[derive(Debug)]
@@ -120,6 +236,24 @@ pub enum Shell {
     Zsh,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        match string {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(anyhow!("unknown error format: '{}'", string)),
+        }
+    }
+}
+
 impl FromStr for Shell {
     type Err = Error;
 
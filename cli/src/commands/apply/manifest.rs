@@ -0,0 +1,115 @@
+//! The declarative description of a project and the resources in it, as read by `apply`.
+//!
+//! Resources are identified by their name within the project, and fields which are left out are
+//! not managed: they are set to the platform default on creation and left untouched on update.
+
+use anyhow::{Context, Result};
+use reinfer_client::{
+    resources::{integration::NewIntegration, stream::NewStream},
+    BucketType, NewEntityDef, NewLabelGroup, ProjectName, SourceKind, TransformTag, UserId,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectManifest {
+    pub project: ProjectSpec,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buckets: Vec<BucketSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub datasets: Vec<DatasetSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub integrations: Vec<IntegrationSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectSpec {
+    pub name: ProjectName,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Users given control of the project when it is created. Defaults to the current user.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub user_ids: Vec<UserId>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketSpec {
+    pub name: String,
+    pub bucket_type: BucketType,
+    /// Only used when creating the bucket.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Only used when creating the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub should_translate: Option<bool>,
+    /// Name of a bucket in the project to sync the source from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    /// Only used when creating the source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<SourceKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform_tag: Option<TransformTag>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Sources in the dataset, either by name within the project or by `<owner>/<name>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<String>>,
+    /// Only used when creating the dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_sentiment: Option<bool>,
+    /// Only used when creating the dataset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_family: Option<String>,
+    /// Only used when creating the dataset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entity_defs: Vec<NewEntityDef>,
+    /// Only used when creating the dataset.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub label_groups: Vec<NewLabelGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub streams: Vec<NewStream>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IntegrationSpec {
+    pub name: String,
+    #[serde(flatten)]
+    pub integration: NewIntegration,
+}
+
+impl ProjectManifest {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not open file `{}`", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Could not parse manifest `{}`", path.display()))
+    }
+}
@@ -0,0 +1,692 @@
+pub mod manifest;
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use log::{info, warn};
+use reinfer_client::{
+    resources::{integration::NewIntegration, stream::NewStream},
+    BucketFullName, BucketId, Client, DatasetFullName, IntegrationFullName, NewBucket, NewDataset,
+    NewProject, NewSource, ProjectName, SourceFullName, SourceId, StreamFullName, UpdateDataset,
+    UpdateProject, UpdateSource, UpdateStream,
+};
+use serde_json::Value as JsonValue;
+use std::{collections::HashMap, path::PathBuf};
+use structopt::StructOpt;
+
+use self::manifest::{
+    BucketSpec, DatasetSpec, IntegrationSpec, ProjectManifest, ProjectSpec, SourceSpec,
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ApplyArgs {
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    /// Path to the YAML manifest describing the project
+    path: PathBuf,
+
+    #[structopt(long)]
+    /// Only print the changes which would be made, without making them
+    dry_run: bool,
+}
+
+pub fn run(args: &ApplyArgs, client: Client) -> Result<()> {
+    let manifest = ProjectManifest::from_file(&args.path)?;
+
+    let mut applier = Applier {
+        client: &client,
+        owner: manifest.project.name.clone(),
+        dry_run: args.dry_run,
+        num_changes: 0,
+    };
+    applier.apply_project(&manifest.project)?;
+    let bucket_ids = applier.apply_buckets(&manifest.buckets)?;
+    let source_ids = applier.apply_sources(&manifest.sources, &bucket_ids)?;
+    applier.apply_datasets(&manifest.datasets, &source_ids)?;
+    applier.apply_integrations(&manifest.integrations)?;
+
+    if args.dry_run {
+        info!("{} changes would be made.", applier.num_changes);
+    } else {
+        info!("{} changes made successfully.", applier.num_changes);
+    }
+    Ok(())
+}
+
+enum Change {
+    Create,
+    Update(Vec<&'static str>),
+    Unchanged,
+}
+
+impl Change {
+    fn from_updated_fields(fields: Vec<&'static str>) -> Self {
+        if fields.is_empty() {
+            Change::Unchanged
+        } else {
+            Change::Update(fields)
+        }
+    }
+}
+
+/// Brings the resources of a project in line with a manifest, one resource at a time.
+///
+/// In a dry run, resources which would be created do not have an id yet, so ids are tracked as
+/// options throughout.
+struct Applier<'a> {
+    client: &'a Client,
+    owner: ProjectName,
+    dry_run: bool,
+    num_changes: usize,
+}
+
+impl<'a> Applier<'a> {
+    fn full_name(&self, name: &str) -> String {
+        format!("{}/{}", self.owner.0, name)
+    }
+
+    /// Print the planned change, returning whether it should be made.
+    fn plan(&mut self, kind: &str, name: &str, change: &Change) -> bool {
+        match change {
+            Change::Create => println!("{}", format!("+ create {kind} {name}").green()),
+            Change::Update(fields) => println!(
+                "{}",
+                format!("~ update {kind} {name} ({})", fields.join(", ")).yellow()
+            ),
+            Change::Unchanged => println!("{}", format!("  unchanged {kind} {name}").dimmed()),
+        }
+        if matches!(change, Change::Unchanged) {
+            return false;
+        }
+        self.num_changes += 1;
+        !self.dry_run
+    }
+
+    fn apply_project(&mut self, spec: &ProjectSpec) -> Result<()> {
+        let existing = self
+            .client
+            .get_projects()
+            .context("Operation to list projects has failed.")?
+            .into_iter()
+            .find(|project| project.name == spec.name);
+
+        match existing {
+            None => {
+                if self.plan("project", &spec.name.0, &Change::Create) {
+                    let user_ids = if spec.user_ids.is_empty() {
+                        vec![
+                            self.client
+                                .get_current_user()
+                                .context("Operation to get the current user has failed.")?
+                                .id,
+                        ]
+                    } else {
+                        spec.user_ids.clone()
+                    };
+                    self.client
+                        .create_project(
+                            &spec.name,
+                            NewProject {
+                                title: spec.title.as_deref(),
+                                description: spec.description.as_deref(),
+                            },
+                            &user_ids,
+                        )
+                        .context("Operation to create a project has failed.")?;
+                }
+            }
+            Some(project) => {
+                let mut fields = Vec::new();
+                if differs(&spec.title, &project.title) {
+                    fields.push("title");
+                }
+                if differs(&spec.description, &project.description) {
+                    fields.push("description");
+                }
+                if self.plan(
+                    "project",
+                    &spec.name.0,
+                    &Change::from_updated_fields(fields),
+                ) {
+                    self.client
+                        .update_project(
+                            &spec.name,
+                            UpdateProject {
+                                title: spec.title.as_deref(),
+                                description: spec.description.as_deref(),
+                            },
+                        )
+                        .context("Operation to update a project has failed.")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the ids of all the buckets in the project, by name.
+    fn apply_buckets(&mut self, specs: &[BucketSpec]) -> Result<HashMap<String, Option<BucketId>>> {
+        let mut bucket_ids: HashMap<_, _> = self
+            .client
+            .get_buckets()
+            .context("Operation to list buckets has failed.")?
+            .into_iter()
+            .filter(|bucket| bucket.owner.0 == self.owner.0)
+            .map(|bucket| (bucket.name.0, Some(bucket.id)))
+            .collect();
+
+        for spec in specs {
+            let full_name = self.full_name(&spec.name);
+            if bucket_ids.contains_key(&spec.name) {
+                self.plan("bucket", &full_name, &Change::Unchanged);
+                continue;
+            }
+
+            let bucket_id = if self.plan("bucket", &full_name, &Change::Create) {
+                let bucket = self
+                    .client
+                    .create_bucket(
+                        &BucketFullName(full_name),
+                        NewBucket {
+                            bucket_type: spec.bucket_type,
+                            title: spec.title.as_deref(),
+                        },
+                    )
+                    .context("Operation to create a bucket has failed.")?;
+                Some(bucket.id)
+            } else {
+                None
+            };
+            bucket_ids.insert(spec.name.clone(), bucket_id);
+        }
+        Ok(bucket_ids)
+    }
+
+    /// Returns the ids of all the sources visible, by full name.
+    fn apply_sources(
+        &mut self,
+        specs: &[SourceSpec],
+        bucket_ids: &HashMap<String, Option<BucketId>>,
+    ) -> Result<HashMap<String, Option<SourceId>>> {
+        let existing_sources: HashMap<_, _> = self
+            .client
+            .get_sources()
+            .context("Operation to list sources has failed.")?
+            .into_iter()
+            .map(|source| (source.full_name().0, source))
+            .collect();
+        let mut source_ids: HashMap<_, _> = existing_sources
+            .iter()
+            .map(|(full_name, source)| (full_name.clone(), Some(source.id.clone())))
+            .collect();
+
+        for spec in specs {
+            let full_name = self.full_name(&spec.name);
+            let bucket_id = match &spec.bucket {
+                Some(bucket) => match bucket_ids.get(bucket) {
+                    Some(bucket_id) => bucket_id.clone(),
+                    None => bail!(
+                        "Source `{}` refers to unknown bucket `{}`",
+                        full_name,
+                        bucket
+                    ),
+                },
+                None => None,
+            };
+
+            match existing_sources.get(&full_name) {
+                None => {
+                    if self.plan("source", &full_name, &Change::Create) {
+                        let source = self
+                            .client
+                            .create_source(
+                                &SourceFullName(full_name.clone()),
+                                NewSource {
+                                    title: spec.title.as_deref(),
+                                    description: spec.description.as_deref(),
+                                    language: spec.language.as_deref(),
+                                    should_translate: spec.should_translate,
+                                    bucket_id,
+                                    sensitive_properties: None,
+                                    kind: spec.kind.as_ref(),
+                                    transform_tag: spec.transform_tag.as_ref(),
+                                },
+                            )
+                            .context("Operation to create a source has failed.")?;
+                        source_ids.insert(full_name, Some(source.id));
+                    } else {
+                        source_ids.insert(full_name, None);
+                    }
+                }
+                Some(source) => {
+                    if differs(&spec.language, &source.language) {
+                        warn!(
+                            "The language of source `{}` cannot be changed, ignoring it.",
+                            full_name
+                        );
+                    }
+                    let mut fields = Vec::new();
+                    if differs(&spec.title, &source.title) {
+                        fields.push("title");
+                    }
+                    if differs(&spec.description, &source.description) {
+                        fields.push("description");
+                    }
+                    if spec
+                        .should_translate
+                        .is_some_and(|should_translate| should_translate != source.should_translate)
+                    {
+                        fields.push("should_translate");
+                    }
+                    if spec.bucket.is_some()
+                        && (bucket_id.is_none() || bucket_id != source.bucket_id)
+                    {
+                        fields.push("bucket");
+                    }
+                    if spec.transform_tag.is_some() && spec.transform_tag != source.transform_tag {
+                        fields.push("transform_tag");
+                    }
+                    if self.plan("source", &full_name, &Change::from_updated_fields(fields)) {
+                        self.client
+                            .update_source(
+                                &source.full_name(),
+                                UpdateSource {
+                                    title: spec.title.as_deref(),
+                                    description: spec.description.as_deref(),
+                                    should_translate: spec.should_translate,
+                                    bucket_id,
+                                    sensitive_properties: None,
+                                    transform_tag: spec.transform_tag.as_ref(),
+                                },
+                            )
+                            .context("Operation to update a source has failed.")?;
+                    }
+                }
+            }
+        }
+        Ok(source_ids)
+    }
+
+    fn apply_datasets(
+        &mut self,
+        specs: &[DatasetSpec],
+        source_ids: &HashMap<String, Option<SourceId>>,
+    ) -> Result<()> {
+        let existing_datasets: HashMap<_, _> = self
+            .client
+            .get_datasets()
+            .context("Operation to list datasets has failed.")?
+            .into_iter()
+            .map(|dataset| (dataset.full_name().0, dataset))
+            .collect();
+
+        for spec in specs {
+            let full_name = self.full_name(&spec.name);
+            let desired_source_ids = match &spec.sources {
+                Some(sources) => Some(self.resolve_source_ids(&full_name, sources, source_ids)?),
+                None => None,
+            };
+
+            let existing_streams = match existing_datasets.get(&full_name) {
+                None => {
+                    if self.plan("dataset", &full_name, &Change::Create) {
+                        let source_ids = match &desired_source_ids {
+                            Some(source_ids) => source_ids
+                                .as_deref()
+                                .context("Sources of the dataset were not created")?,
+                            None => &[],
+                        };
+                        self.client
+                            .create_dataset(
+                                &DatasetFullName(full_name.clone()),
+                                NewDataset {
+                                    source_ids,
+                                    title: spec.title.as_deref(),
+                                    description: spec.description.as_deref(),
+                                    has_sentiment: spec.has_sentiment,
+                                    entity_defs: non_empty(&spec.entity_defs),
+                                    label_defs: None,
+                                    label_groups: non_empty(&spec.label_groups),
+                                    model_family: spec.model_family.as_deref(),
+                                    copy_annotations_from: None,
+                                },
+                            )
+                            .context("Operation to create a dataset has failed.")?;
+                    }
+                    Vec::new()
+                }
+                Some(dataset) => {
+                    warn_about_immutable_dataset_fields(spec, dataset);
+
+                    let mut fields = Vec::new();
+                    if differs(&spec.title, &dataset.title) {
+                        fields.push("title");
+                    }
+                    if differs(&spec.description, &dataset.description) {
+                        fields.push("description");
+                    }
+                    let sources_changed = match &desired_source_ids {
+                        Some(Some(desired_source_ids)) => {
+                            let mut desired: Vec<_> =
+                                desired_source_ids.iter().map(|id| &id.0).collect();
+                            let mut actual: Vec<_> =
+                                dataset.source_ids.iter().map(|id| &id.0).collect();
+                            desired.sort();
+                            actual.sort();
+                            desired != actual
+                        }
+                        // Only missing in a dry run, where the sources would be created first.
+                        Some(None) => true,
+                        None => false,
+                    };
+                    if sources_changed {
+                        fields.push("sources");
+                    }
+                    if self.plan("dataset", &full_name, &Change::from_updated_fields(fields)) {
+                        self.client
+                            .update_dataset(
+                                &dataset.full_name(),
+                                UpdateDataset {
+                                    source_ids: desired_source_ids.flatten().as_deref(),
+                                    title: spec.title.as_deref(),
+                                    description: spec.description.as_deref(),
+                                },
+                            )
+                            .context("Operation to update a dataset has failed.")?;
+                    }
+                    self.client
+                        .get_streams(&dataset.full_name())
+                        .context("Operation to list streams has failed.")?
+                }
+            };
+
+            let dataset_name = DatasetFullName(full_name);
+            for stream in &spec.streams {
+                self.apply_stream(&dataset_name, stream, &existing_streams)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The ids of the sources of a dataset, which are only missing in a dry run where the sources
+    /// would be created first.
+    fn resolve_source_ids(
+        &self,
+        dataset_full_name: &str,
+        sources: &[String],
+        source_ids: &HashMap<String, Option<SourceId>>,
+    ) -> Result<Option<Vec<SourceId>>> {
+        let mut desired_source_ids = Vec::with_capacity(sources.len());
+        for source in sources {
+            let source_full_name = if source.contains('/') {
+                source.clone()
+            } else {
+                self.full_name(source)
+            };
+            match source_ids.get(&source_full_name) {
+                Some(source_id) => desired_source_ids.push(source_id.clone()),
+                None => bail!(
+                    "Dataset `{}` refers to unknown source `{}`",
+                    dataset_full_name,
+                    source_full_name
+                ),
+            }
+        }
+        Ok(desired_source_ids.into_iter().collect())
+    }
+
+    fn apply_stream(
+        &mut self,
+        dataset_name: &DatasetFullName,
+        spec: &NewStream,
+        existing_streams: &[reinfer_client::Stream],
+    ) -> Result<()> {
+        let full_name = format!("{}/{}", dataset_name.0, spec.name.0);
+        match existing_streams
+            .iter()
+            .find(|stream| stream.name == spec.name)
+        {
+            None => {
+                if self.plan("stream", &full_name, &Change::Create) {
+                    self.client
+                        .put_stream(dataset_name, spec)
+                        .context("Operation to create a stream has failed.")?;
+                }
+            }
+            Some(stream) => {
+                let desired = serde_json::to_value(spec).context("Could not serialise stream.")?;
+                let actual = serde_json::to_value(stream).context("Could not serialise stream.")?;
+                let fields = STREAM_FIELDS
+                    .into_iter()
+                    .filter(|field| match (desired.get(field), actual.get(field)) {
+                        (None | Some(JsonValue::Null), _) => false,
+                        (Some(desired), Some(actual)) => differs_from_json(desired, actual),
+                        (Some(_), None) => true,
+                    })
+                    .collect();
+                if self.plan("stream", &full_name, &Change::from_updated_fields(fields)) {
+                    self.client
+                        .update_stream(
+                            &StreamFullName {
+                                dataset: dataset_name.clone(),
+                                stream: spec.name.clone(),
+                            },
+                            UpdateStream {
+                                title: spec.title.as_deref(),
+                                description: spec.description.as_deref(),
+                                comment_filter: spec.comment_filter.as_ref(),
+                                model: spec.model.as_ref(),
+                                label_filter: spec.label_filter.as_ref(),
+                            },
+                        )
+                        .context("Operation to update a stream has failed.")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_integrations(&mut self, specs: &[IntegrationSpec]) -> Result<()> {
+        let existing_integrations = self
+            .client
+            .get_integrations()
+            .context("Operation to list integrations has failed.")?;
+
+        for spec in specs {
+            let full_name = IntegrationFullName(self.full_name(&spec.name));
+            let existing = existing_integrations.iter().find(|integration| {
+                integration.owner == self.owner && integration.name.0 == spec.name
+            });
+
+            match existing {
+                None => {
+                    if self.plan("integration", &full_name.0, &Change::Create) {
+                        self.client
+                            .put_integration(&full_name, &spec.integration)
+                            .context("Operation to create an integration has failed.")?;
+                    }
+                }
+                Some(existing) => {
+                    let actual: NewIntegration =
+                        serde_json::from_value(serde_json::to_value(existing)?)
+                            .context("Could not convert existing integration.")?;
+                    let mut desired = spec.integration.clone();
+                    desired.title = desired.title.or_else(|| actual.title.clone());
+                    desired.enabled = desired.enabled.or(actual.enabled);
+
+                    let mut fields = Vec::new();
+                    if desired.title != actual.title {
+                        fields.push("title");
+                    }
+                    if desired.enabled != actual.enabled {
+                        fields.push("enabled");
+                    }
+                    if desired.configuration != actual.configuration {
+                        fields.push("configuration");
+                    }
+                    if self.plan(
+                        "integration",
+                        &full_name.0,
+                        &Change::from_updated_fields(fields),
+                    ) {
+                        self.client
+                            .post_integration(&full_name, &desired)
+                            .context("Operation to update an integration has failed.")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn warn_about_immutable_dataset_fields(spec: &DatasetSpec, dataset: &reinfer_client::Dataset) {
+    let full_name = dataset.full_name().0;
+    if spec
+        .has_sentiment
+        .is_some_and(|has_sentiment| has_sentiment != dataset.has_sentiment)
+    {
+        warn!(
+            "Sentiment of dataset `{}` cannot be changed, ignoring it.",
+            full_name
+        );
+    }
+    if differs(&spec.model_family, &dataset.model_family.0) {
+        warn!(
+            "Model family of dataset `{}` cannot be changed, ignoring it.",
+            full_name
+        );
+    }
+    for entity_def in &spec.entity_defs {
+        if !dataset
+            .entity_defs
+            .iter()
+            .any(|existing| existing.name == entity_def.name)
+        {
+            warn!(
+                "Entity `{}` is missing from dataset `{}`, but cannot be added by `apply`.",
+                entity_def.name.0, full_name
+            );
+        }
+    }
+    for label_group in &spec.label_groups {
+        let existing_group = dataset
+            .label_groups
+            .iter()
+            .find(|existing| existing.name == label_group.name);
+        for label_def in &label_group.label_defs {
+            if !existing_group.is_some_and(|group| {
+                group
+                    .label_defs
+                    .iter()
+                    .any(|existing| existing.name == label_def.name)
+            }) {
+                warn!(
+                    "Label `{}` is missing from dataset `{}`, but cannot be added by `apply`.",
+                    label_def.name.0, full_name
+                );
+            }
+        }
+    }
+}
+
+/// Whether a field is set in the manifest to something other than its actual value.
+fn differs(desired: &Option<String>, actual: &str) -> bool {
+    desired.as_deref().is_some_and(|desired| desired != actual)
+}
+
+/// The fields of a stream which `apply` updates, as serialised.
+const STREAM_FIELDS: [&str; 5] = [
+    "title",
+    "description",
+    "comment_filter",
+    "model",
+    "label_threshold_filter",
+];
+
+/// Whether any field set in `desired` has a different value in `actual`, recursing into objects.
+fn differs_from_json(desired: &JsonValue, actual: &JsonValue) -> bool {
+    match (desired, actual) {
+        (JsonValue::Object(desired), JsonValue::Object(actual)) => {
+            desired.iter().any(|(key, desired)| match actual.get(key) {
+                Some(actual) => differs_from_json(desired, actual),
+                None => !desired.is_null(),
+            })
+        }
+        (desired, actual) => desired != actual,
+    }
+}
+
+fn non_empty<T>(items: &[T]) -> Option<&[T]> {
+    if items.is_empty() {
+        None
+    } else {
+        Some(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{differs_from_json, manifest::ProjectManifest};
+    use serde_json::json;
+
+    #[test]
+    fn test_differs_from_json() {
+        let actual = json!({"name": "a", "title": "A", "model": {"version": 3, "thresholds": []}});
+
+        assert!(!differs_from_json(&json!({"name": "a"}), &actual));
+        assert!(!differs_from_json(
+            &json!({"model": {"version": 3}}),
+            &actual
+        ));
+        assert!(differs_from_json(
+            &json!({"model": {"version": 4}}),
+            &actual
+        ));
+        assert!(differs_from_json(&json!({"description": "new"}), &actual));
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest: ProjectManifest = serde_yaml::from_str(
+            r#"
+project:
+  name: acme
+  title: Acme
+buckets:
+  - name: mailbox
+    bucket_type: emails
+sources:
+  - name: support
+    bucket: mailbox
+datasets:
+  - name: triage
+    sources: [support, other/shared]
+    label_groups:
+      - name: default
+        label_defs:
+          - name: Billing
+    streams:
+      - name: billing
+integrations:
+  - name: exchange
+    configuration:
+      mailboxes: []
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(manifest.project.name.0, "acme");
+        assert_eq!(
+            manifest.datasets[0].sources,
+            Some(vec!["support".to_owned(), "other/shared".to_owned()])
+        );
+        assert_eq!(manifest.datasets[0].streams[0].name.0, "billing");
+        assert_eq!(manifest.integrations[0].name, "exchange");
+        assert!(
+            serde_yaml::from_str::<ProjectManifest>("project: {name: acme, colour: red}").is_err()
+        );
+
+        // Datasets without sources keep theirs on update.
+        let manifest: ProjectManifest =
+            serde_yaml::from_str("project: {name: acme}\ndatasets: [{name: triage}]").unwrap();
+        assert_eq!(manifest.datasets[0].sources, None);
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use reinfer_client::{
+    resources::stream::{Name as StreamName, NewStream},
+    Client, Dataset, DatasetIdentifier, ModelVersion, SourceId, SourceIdentifier, StreamFullName,
+};
+use structopt::StructOpt;
+
+use crate::commands::export::project::new_stream;
+
+#[derive(Debug, StructOpt)]
+pub enum CloneArgs {
+    #[structopt(name = "stream")]
+    /// Copy a stream, with its filters and label thresholds, to another dataset
+    Stream {
+        #[structopt(name = "stream")]
+        /// The full stream name `<owner>/<dataset>/<stream>`.
+        stream: StreamFullName,
+
+        #[structopt(long = "to-dataset")]
+        /// Name or id of the dataset to create the stream in
+        to_dataset: DatasetIdentifier,
+
+        #[structopt(long = "name")]
+        /// Name of the new stream, if different from the cloned one
+        name: Option<String>,
+
+        #[structopt(long = "model-version")]
+        /// Pin the new stream to this model version of the target dataset. Required if the stream
+        /// has label thresholds or a label filter
+        model_version: Option<ModelVersion>,
+    },
+}
+
+pub fn run(clone_args: &CloneArgs, client: Client) -> Result<()> {
+    match clone_args {
+        CloneArgs::Stream {
+            stream,
+            to_dataset,
+            name,
+            model_version,
+        } => clone_stream(&client, stream, to_dataset, name, model_version),
+    }
+}
+
+fn clone_stream(
+    client: &Client,
+    stream_name: &StreamFullName,
+    to_dataset: &DatasetIdentifier,
+    name: &Option<String>,
+    model_version: &Option<ModelVersion>,
+) -> Result<()> {
+    let stream = client
+        .get_stream(stream_name)
+        .context("Operation to get stream has failed.")?;
+    let dataset = client
+        .get_dataset(to_dataset.clone())
+        .context("Operation to get dataset has failed.")?;
+
+    let mut new_stream = new_stream(stream);
+    if let Some(name) = name {
+        new_stream.name = StreamName(name.clone());
+    }
+
+    let missing_labels = missing_labels(&new_stream, &dataset);
+    if !missing_labels.is_empty() {
+        bail!(
+            "Dataset `{}` has no labels {}, which stream `{}` uses",
+            dataset.full_name().0,
+            missing_labels.join(", "),
+            stream_name.stream.0
+        );
+    }
+
+    // Model versions belong to a dataset, so those of the cloned stream mean nothing in the
+    // target dataset.
+    match model_version {
+        Some(model_version) => new_stream.set_model_version(model_version),
+        None if new_stream.model.is_some() || new_stream.label_filter.is_some() => bail!(
+            "Stream `{}` uses a model, pass --model-version to choose a model version of \
+             dataset `{}`",
+            stream_name.stream.0,
+            dataset.full_name().0
+        ),
+        None => {}
+    }
+    if let Some(comment_filter) = &mut new_stream.comment_filter {
+        remap_sources(client, &mut comment_filter.sources, &dataset)?;
+    }
+
+    let stream = client
+        .put_stream(&dataset.full_name(), &new_stream)
+        .context("Operation to create a stream has failed.")?
+        .stream;
+    info!(
+        "New stream `{}` [id: {}] created successfully in dataset `{}`",
+        stream.name.0,
+        stream.id.0,
+        dataset.full_name().0
+    );
+    Ok(())
+}
+
+/// Replace the sources a stream filters on with the sources of the same name in the target
+/// dataset, keeping the ones which the target dataset shares.
+fn remap_sources(client: &Client, source_ids: &mut [SourceId], dataset: &Dataset) -> Result<()> {
+    let mut target_sources = None;
+    for source_id in source_ids {
+        if dataset.source_ids.contains(source_id) {
+            continue;
+        }
+        let target_sources = match &mut target_sources {
+            Some(target_sources) => target_sources,
+            None => target_sources.insert(
+                dataset
+                    .source_ids
+                    .iter()
+                    .map(|id| client.get_source(SourceIdentifier::Id(id.clone())))
+                    .collect::<Result<Vec<_>, _>>()
+                    .context("Operation to get sources has failed.")?,
+            ),
+        };
+        let source = client
+            .get_source(SourceIdentifier::Id(source_id.clone()))
+            .context("Operation to get source has failed.")?;
+        let Some(target_source) = target_sources
+            .iter()
+            .find(|target_source| target_source.name == source.name)
+        else {
+            bail!(
+                "Dataset `{}` has no source named `{}`, which the stream filters on",
+                dataset.full_name().0,
+                source.name.0
+            );
+        };
+        info!(
+            "Filtering on source `{}` instead of `{}`",
+            target_source.full_name().0,
+            source.full_name().0
+        );
+        *source_id = target_source.id.clone();
+    }
+    Ok(())
+}
+
+/// The labels used by the thresholds or the label filter of a stream, which a dataset does not
+/// have.
+fn missing_labels(stream: &NewStream, dataset: &Dataset) -> Vec<String> {
+    let threshold_labels = stream
+        .model
+        .iter()
+        .flat_map(|model| &model.label_thresholds)
+        .map(|label_threshold| label_threshold.name.join(" > "));
+    let filter_label = stream
+        .label_filter
+        .iter()
+        .map(|label_filter| label_filter.label.0.clone());
+
+    let mut missing: Vec<String> = threshold_labels
+        .chain(filter_label)
+        .filter(|label_name| {
+            !dataset
+                .label_defs
+                .iter()
+                .any(|label_def| label_def.name.0 == *label_name)
+        })
+        .map(|label_name| format!("`{label_name}`"))
+        .collect();
+    missing.sort();
+    missing.dedup();
+    missing
+}
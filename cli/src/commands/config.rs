@@ -2,16 +2,23 @@
 use colored::Colorize;
 use log::{error, info, warn};
 use prettytable::{self, row, Table};
-use reinfer_client::DEFAULT_ENDPOINT;
+use reinfer_client::{
+    Client, Config as ClientConfig, Error as ClientError, Token, DEFAULT_ENDPOINT,
+};
 use reqwest::Url;
-use std::path::Path;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
 use structopt::StructOpt;
 
 use crate::{
-    config::{self, write_reinfer_config, ContextConfig, ReinferConfig},
+    config::{self, write_reinfer_config, ContextConfig, ReinferConfig, CONTEXT_DEFAULT_KEYS},
+    credentials::{self, CredentialStoreConfig},
     utils,
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
@@ -31,6 +38,11 @@ pub enum ConfigArgs {
         /// The reinfer API token that will be used for this context
         token: Option<String>,
 
+        #[structopt(long = "token-file")]
+        /// File from which the API token is read on every invocation, e.g. a mounted secret.
+        /// Pass the flag without a value to remove it.
+        token_file: Option<Option<PathBuf>>,
+
         #[structopt(long = "accept-invalid-certificates", short = "k")]
         /// Whether to accept invalid TLS certificates
         accept_invalid_certificates: bool,
@@ -38,6 +50,11 @@ pub enum ConfigArgs {
         #[structopt(long = "proxy")]
         /// URL for an HTTP proxy that will be used for all requests if specified
         proxy: Option<Option<Url>>,
+
+        #[structopt(long = "requests-per-second")]
+        /// Maximum number of requests per second that will be sent for this context. Pass the
+        /// flag without a value to remove the limit.
+        requests_per_second: Option<Option<f64>>,
     },
 
     /// Output the token for a given context or the current one if unspecified.
@@ -63,6 +80,40 @@ pub enum ConfigArgs {
         tokens: bool,
     },
 
+    #[structopt(name = "export")]
+    /// Export contexts as JSON, to be imported with `re config import`
+    Export {
+        /// The names of the contexts to export, all of them if unspecified
+        names: Vec<String>,
+
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        /// Path where to write the contexts. If not specified, stdout will be used.
+        path: Option<PathBuf>,
+
+        #[structopt(long = "redact-tokens")]
+        /// Leave the API tokens out of the exported contexts
+        redact_tokens: bool,
+    },
+
+    #[structopt(name = "import")]
+    /// Import contexts exported with `re config export`
+    Import {
+        #[structopt(short = "f", long = "file", parse(from_os_str))]
+        /// Path to the exported contexts. If not specified, stdin will be used.
+        path: Option<PathBuf>,
+
+        #[structopt(long = "overwrite")]
+        /// Replace existing contexts with the same name, which are skipped otherwise
+        overwrite: bool,
+    },
+
+    #[structopt(name = "test")]
+    /// Check that contexts can be used by getting the current user with each of them
+    Test {
+        /// The names of the contexts to test, all of them if unspecified
+        names: Vec<String>,
+    },
+
     #[structopt(name = "use")]
     /// Set the current context in the reinfer config file
     UseContext {
@@ -70,6 +121,26 @@ pub enum ConfigArgs {
         name: String,
     },
 
+    #[structopt(name = "set")]
+    /// Set a default for the arguments of commands run in a context, or remove it if no value is
+    /// given. Arguments given on the command line or with environment variables take precedence.
+    ///
+    /// The `project`, `source`, `dataset` and `bucket` defaults apply to the commands which
+    /// require one, except for deletions. `max-retries` and `retry-wait` (in seconds) configure
+    /// how failed requests are retried.
+    SetDefault {
+        #[structopt(long = "name", short = "n")]
+        /// The name of the context, the current one if unspecified
+        name: Option<String>,
+
+        #[structopt(possible_values = &CONTEXT_DEFAULT_KEYS)]
+        /// The default to set
+        key: String,
+
+        /// The value of the default
+        value: Option<String>,
+    },
+
     #[structopt(name = "set-context-required")]
     /// Set whether context is a required field
     SetContextRequired {
@@ -77,6 +148,39 @@ pub enum ConfigArgs {
         #[structopt(name = "is-required", parse(try_from_str))]
         is_required: bool,
     },
+
+    #[structopt(name = "set-credential-store")]
+    /// Set where the API tokens of contexts are stored. Existing tokens are moved to the new
+    /// store when their context is next edited with `config add`.
+    SetCredentialStore {
+        #[structopt(subcommand)]
+        store: CredentialStoreArgs,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CredentialStoreArgs {
+    #[structopt(name = "helper")]
+    /// Store tokens with an external credential helper, which is run as `<command> get`,
+    /// `<command> store` or `<command> erase` with `context=<name>` and `endpoint=<url>` lines
+    /// (and `token=<token>` when storing) on stdin. For `get`, it prints `token=<token>`.
+    Helper {
+        /// The credential helper command, run through the shell
+        command: String,
+    },
+
+    #[structopt(name = "encrypted-file")]
+    /// Store tokens in a file encrypted with a passphrase. The passphrase is read from the
+    /// REINFER_CREDENTIALS_PASSPHRASE environment variable if set, and prompted for otherwise.
+    EncryptedFile {
+        #[structopt(long = "path", parse(from_os_str))]
+        /// Path of the encrypted file. Defaults to `tokens.enc` next to the config file.
+        path: Option<PathBuf>,
+    },
+
+    #[structopt(name = "config-file")]
+    /// Store tokens in cleartext in the config file
+    ConfigFile,
 }
 
 pub fn run(
@@ -85,10 +189,62 @@ pub fn run(
     config_path: impl AsRef<Path>,
 ) -> Result<ReinferConfig> {
     match args {
+        ConfigArgs::SetDefault { name, key, value } => {
+            let mut context = match name {
+                Some(name) => config
+                    .get_context(name)
+                    .ok_or_else(|| anyhow!("No such context `{}`.", name))?,
+                None => config
+                    .get_current_context()
+                    .ok_or_else(|| anyhow!("There is no default context in use."))?,
+            }
+            .clone();
+            context.defaults.set(key, value.as_deref())?;
+            let context_name = context.name.clone();
+            config.set_context(context);
+            write_reinfer_config(&config_path, &config)?;
+            match value {
+                Some(value) => info!(
+                    "Default {} of context `{}` set to `{}`.",
+                    key, context_name, value
+                ),
+                None => info!("Default {} of context `{}` removed.", key, context_name),
+            }
+        }
         ConfigArgs::SetContextRequired { is_required } => {
             config.context_is_required = *is_required;
             write_reinfer_config(config_path, &config)?
         }
+        ConfigArgs::SetCredentialStore { store } => {
+            config.credential_store = match store {
+                CredentialStoreArgs::Helper { command } => Some(CredentialStoreConfig::Helper {
+                    command: command.clone(),
+                }),
+                CredentialStoreArgs::EncryptedFile { path } => {
+                    let path = path
+                        .clone()
+                        .unwrap_or_else(|| config_path.as_ref().with_file_name("tokens.enc"));
+                    Some(CredentialStoreConfig::EncryptedFile {
+                        path: utils::absolute_path(&path)?,
+                    })
+                }
+                CredentialStoreArgs::ConfigFile => None,
+            };
+            write_reinfer_config(&config_path, &config)?;
+            info!("Credential store updated.");
+            let num_cleartext_tokens = config
+                .get_all_contexts()
+                .iter()
+                .filter(|context| context.token.is_some())
+                .count();
+            if config.credential_store.is_some() && num_cleartext_tokens > 0 {
+                info!(
+                    "{} contexts have a token in cleartext in `{}`, use `re config add --name <context>` to move it to the credential store.",
+                    num_cleartext_tokens,
+                    config_path.as_ref().display()
+                );
+            }
+        }
         ConfigArgs::ListContexts { tokens } if config.num_contexts() > 0 => {
             let mut contexts = config.get_all_contexts().clone();
             contexts.sort_unstable_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
@@ -97,6 +253,11 @@ pub fn run(
                 row![bFg => "Active", "Context", "Endpoint", "Insecure", "Token", "Proxy"],
             );
             for context in contexts.iter() {
+                let token = if *tokens {
+                    credentials::context_token(&config, context)?.unwrap_or_default()
+                } else {
+                    "<Hidden>".into()
+                };
                 let active = config
                     .get_current_context()
                     .map_or(false, |current_context| {
@@ -115,11 +276,7 @@ pub fn run(
                     } else {
                         "No"
                     },
-                    if *tokens {
-                        context.token.clone().unwrap_or_default()
-                    } else {
-                        "<Hidden>".into()
-                    },
+                    token,
                     context
                         .proxy
                         .clone()
@@ -136,19 +293,32 @@ pub fn run(
             name,
             endpoint,
             token,
+            token_file,
             accept_invalid_certificates,
             proxy,
+            requests_per_second,
         } => {
             add_or_edit_context(
                 name,
                 token,
+                token_file,
                 endpoint,
                 *accept_invalid_certificates,
                 proxy,
+                requests_per_second,
                 config.clone(),
                 config_path,
             )?;
         }
+        ConfigArgs::Export {
+            names,
+            path,
+            redact_tokens,
+        } => export_contexts(&config, names, path, *redact_tokens)?,
+        ConfigArgs::Import { path, overwrite } => {
+            import_contexts(&mut config, &config_path, path, *overwrite)?
+        }
+        ConfigArgs::Test { names } => test_contexts(&config, names)?,
         ConfigArgs::UseContext { name } => {
             if !config.set_current_context(name) {
                 return Err(anyhow!(
@@ -167,30 +337,38 @@ pub fn run(
         ),
         ConfigArgs::GetToken { name } => match name.as_ref() {
             None => {
+                let context = config
+                    .get_current_context()
+                    .ok_or_else(|| anyhow!("There is no default context in use."))?;
                 println!(
                     "{}",
-                    config
-                        .get_current_context()
-                        .ok_or_else(|| anyhow!("There is no default context in use."))?
-                        .token
-                        .as_ref()
+                    credentials::context_token(&config, context)?
                         .ok_or_else(|| anyhow!("The default context has no stored token."))?
                 );
             }
             Some(name) => {
+                let context = config
+                    .get_context(name)
+                    .ok_or_else(|| anyhow!("No such context `{}`.", name))?;
                 println!(
                     "{}",
-                    config
-                        .get_context(name)
-                        .ok_or_else(|| anyhow!("No such context `{}`.", name))?
-                        .token
-                        .as_ref()
+                    credentials::context_token(&config, context)?
                         .ok_or_else(|| anyhow!("The context `{}` has no stored token.", name))?
                 );
             }
         },
         ConfigArgs::DeleteContext { names } => {
             for name in names {
+                if let (Some(store_config), Some(context)) =
+                    (&config.credential_store, config.get_context(name))
+                {
+                    if let Err(error) = credentials::open_store(store_config).erase(context) {
+                        warn!(
+                            "Could not erase the token of context `{}` from the credential store: {:#}",
+                            name, error
+                        );
+                    }
+                }
                 if config.delete_context(name) {
                     config::write_reinfer_config(&config_path, &config)?;
                     info!(
@@ -211,15 +389,24 @@ pub fn run(
     Ok(config)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn add_or_edit_context(
     name: &Option<String>,
     token: &Option<String>,
+    token_file: &Option<Option<PathBuf>>,
     endpoint: &Option<Url>,
     accept_invalid_certificates: bool,
     proxy: &Option<Option<Url>>,
+    requests_per_second: &Option<Option<f64>>,
     mut config: ReinferConfig,
     config_path: impl AsRef<Path>,
 ) -> Result<()> {
+    if let Some(Some(requests_per_second)) = requests_per_second {
+        if *requests_per_second <= 0.0 {
+            return Err(anyhow!("--requests-per-second must be greater than 0"));
+        }
+    }
+
     // Get context name (either argument or from stdin)
     let name = loop {
         let name = match name {
@@ -240,19 +427,45 @@ fn add_or_edit_context(
         info!("A new context `{}` will be created.", name);
     }
 
-    // Get API token (either argument or from stdin)
-    let token = match token {
+    let token_file = match token_file {
+        Some(token_file) => token_file
+            .as_deref()
+            .map(utils::absolute_path)
+            .transpose()?,
+        None => existing_context
+            .as_ref()
+            .and_then(|context| context.token_file.clone()),
+    };
+
+    if token.is_some() && token_file.is_some() {
+        return Err(anyhow!(
+            "A context cannot have both a token and a token file, pass `--token-file` without a value to remove the token file"
+        ));
+    }
+
+    // Get API token (either argument or from stdin, unless it is read from a file)
+    let mut token = match token {
+        None if token_file.is_some() => None,
         None => utils::read_token_from_stdin()?,
         token => token.clone(),
     };
-    if token.is_none() {
+    // Move a token stored in cleartext by an older version to the credential store.
+    if token.is_none() && token_file.is_none() && config.credential_store.is_some() {
+        token = existing_context.and_then(|context| context.token.clone());
+    }
+    if let Some(token_file) = &token_file {
+        info!(
+            "The API token will be read from `{}` on every invocation.",
+            token_file.display()
+        );
+    } else if token.is_none() {
         info!(concat!(
             "No API token was associated with the context. ",
             "You will have to enter it for every request."
         ));
-    } else {
+    } else if config.credential_store.is_none() {
         warn!(
-            "Be careful, API tokens are stored in cleartext in {}.",
+            "Be careful, API tokens are stored in cleartext in {}. Use `re config set-credential-store` to store them elsewhere.",
             config_path.as_ref().display()
         );
     }
@@ -279,18 +492,34 @@ fn add_or_edit_context(
     };
 
     // Update the contexts' JSON configuration file
-    let context = ContextConfig {
+    let mut context = ContextConfig {
         name: name.clone(),
         endpoint,
         token,
+        token_file,
         accept_invalid_certificates,
         proxy: proxy.clone().unwrap_or_else(|| {
             existing_context
                 .as_ref()
                 .and_then(|context| context.proxy.clone())
         }),
+        requests_per_second: requests_per_second.unwrap_or_else(|| {
+            existing_context
+                .as_ref()
+                .and_then(|context| context.requests_per_second)
+        }),
+        defaults: existing_context
+            .map(|context| context.defaults.clone())
+            .unwrap_or_default(),
     };
 
+    if let Some(store_config) = &config.credential_store {
+        if let Some(token) = context.token.take() {
+            credentials::open_store(store_config).store(&context, &token)?;
+            info!("The API token was saved in the credential store.");
+        }
+    }
+
     let update_existing = existing_context.is_some();
     let is_new_context = !config.set_context(context);
     if is_new_context && config.num_contexts() == 1 {
@@ -309,6 +538,263 @@ fn add_or_edit_context(
     Ok(())
 }
 
+/// The contexts with the given names, or all of them if there are none.
+fn select_contexts<'a>(
+    config: &'a ReinferConfig,
+    names: &[String],
+) -> Result<Vec<&'a ContextConfig>> {
+    if names.is_empty() {
+        return Ok(config.get_all_contexts().iter().collect());
+    }
+    names
+        .iter()
+        .map(|name| {
+            config
+                .get_context(name)
+                .ok_or_else(|| anyhow!("No such context `{}`.", name))
+        })
+        .collect()
+}
+
+fn export_contexts(
+    config: &ReinferConfig,
+    names: &[String],
+    path: &Option<PathBuf>,
+    redact_tokens: bool,
+) -> Result<()> {
+    let contexts = select_contexts(config, names)?
+        .into_iter()
+        .map(|context| {
+            let mut context = context.clone();
+            // Tokens read from a file are left there, as the file is usually specific to a machine.
+            context.token = if redact_tokens || context.token_file.is_some() {
+                None
+            } else {
+                credentials::context_token(config, &context)?
+            };
+            Ok(context)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Could not open file for writing `{}`", path.display()))?;
+            // The file may hold tokens, so only the user should be able to read it, including
+            // when it already existed before.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                file.set_permissions(fs::Permissions::from_mode(0o600))
+                    .with_context(|| {
+                        format!("Could not set the permissions of `{}`", path.display())
+                    })?;
+            }
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, &contexts)
+                .with_context(|| format!("Could not write contexts to `{}`", path.display()))?;
+            writer
+                .flush()
+                .with_context(|| format!("Could not write contexts to `{}`", path.display()))?;
+            if !redact_tokens && contexts.iter().any(|context| context.token.is_some()) {
+                warn!(
+                    "Be careful, the API tokens are written in cleartext in {}.",
+                    path.display()
+                );
+            }
+        }
+        None => {
+            let mut stdout = io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &contexts)
+                .context("Could not write contexts to stdout")?;
+            writeln!(stdout)?;
+        }
+    }
+    info!("Exported {} contexts.", contexts.len());
+    Ok(())
+}
+
+fn import_contexts(
+    config: &mut ReinferConfig,
+    config_path: impl AsRef<Path>,
+    path: &Option<PathBuf>,
+    overwrite: bool,
+) -> Result<()> {
+    let contexts: Vec<ContextConfig> = match path {
+        Some(path) => {
+            let file = File::open(path)
+                .with_context(|| format!("Could not open file `{}`", path.display()))?;
+            serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("Could not parse contexts from `{}`", path.display()))?
+        }
+        None => serde_json::from_reader(io::stdin().lock())
+            .context("Could not parse contexts from stdin")?,
+    };
+
+    let mut num_imported = 0;
+    for mut context in contexts {
+        if config.get_context(&context.name).is_some() && !overwrite {
+            warn!(
+                "Context `{}` already exists, skipping it (use --overwrite to replace it).",
+                context.name
+            );
+            continue;
+        }
+        if let Some(store_config) = &config.credential_store {
+            if let Some(token) = context.token.take() {
+                credentials::open_store(store_config).store(&context, &token)?;
+            }
+        }
+        let name = context.name.clone();
+        config.set_context(context);
+        if config.get_current_context().is_none() {
+            info!("Default context set to `{}`.", name);
+            config.set_current_context(&name);
+        }
+        num_imported += 1;
+    }
+
+    config::write_reinfer_config(&config_path, config)?;
+    info!(
+        "Imported {} contexts into `{}`.",
+        num_imported,
+        config_path.as_ref().display()
+    );
+    Ok(())
+}
+
+/// How a context fared when getting the current user with it.
+struct ContextStatus {
+    reachable: &'static str,
+    token: String,
+    certificates: &'static str,
+    error: Option<String>,
+}
+
+fn test_contexts(config: &ReinferConfig, names: &[String]) -> Result<()> {
+    let contexts = select_contexts(config, names)?;
+    if contexts.is_empty() {
+        info!("No available contexts.");
+        return Ok(());
+    }
+
+    let mut table = new_table();
+    table.set_titles(
+        row![bFg => "Context", "Endpoint", "Reachable", "Token", "Certificates", "Proxy", "Error"],
+    );
+    let mut num_failed = 0;
+    for context in &contexts {
+        let status = test_context(config, context);
+        if status.error.is_some() {
+            num_failed += 1;
+        }
+        table.add_row(row![
+            context.name,
+            context.endpoint,
+            status.reachable,
+            status.token,
+            status.certificates,
+            context
+                .proxy
+                .as_ref()
+                .map_or_else(|| "None".to_owned(), Url::to_string),
+            status.error.unwrap_or_default()
+        ]);
+    }
+    table.printstd();
+
+    if num_failed > 0 {
+        bail!(
+            "{} of {} contexts could not be used",
+            num_failed,
+            contexts.len()
+        );
+    }
+    Ok(())
+}
+
+fn test_context(config: &ReinferConfig, context: &ContextConfig) -> ContextStatus {
+    let verified_certificates = if context.endpoint.scheme() != "https" {
+        "Not used"
+    } else if context.accept_invalid_certificates {
+        "Not verified"
+    } else {
+        "Valid"
+    };
+    let unknown = |error: String| ContextStatus {
+        reachable: "Unknown",
+        token: "Unknown".to_owned(),
+        certificates: "Unknown",
+        error: Some(error),
+    };
+
+    let token = match credentials::context_token(config, context) {
+        Ok(token) => token,
+        Err(error) => return unknown(format!("{error:#}")),
+    };
+    let has_token = token.is_some();
+    let client = match Client::new(ClientConfig {
+        endpoint: context.endpoint.clone(),
+        token: Token(token.unwrap_or_default()),
+        accept_invalid_certificates: context.accept_invalid_certificates,
+        proxy: context.proxy.clone(),
+        ..Default::default()
+    }) {
+        Ok(client) => client,
+        Err(error) => return unknown(root_cause(error)),
+    };
+
+    match client.get_current_user() {
+        Ok(user) => ContextStatus {
+            reachable: "Yes",
+            token: format!("Valid ({})", user.username.0),
+            certificates: verified_certificates,
+            error: None,
+        },
+        Err(error @ ClientError::Unauthorized { .. }) => ContextStatus {
+            reachable: "Yes",
+            token: if has_token { "Invalid" } else { "Missing" }.to_owned(),
+            certificates: verified_certificates,
+            error: Some(root_cause(error)),
+        },
+        Err(error) if is_certificate_error(&error) => ContextStatus {
+            reachable: "Yes",
+            token: "Unknown".to_owned(),
+            certificates: "Invalid",
+            error: Some(root_cause(error)),
+        },
+        Err(error @ ClientError::ReqwestError { .. }) if error.status_code().is_none() => {
+            ContextStatus {
+                reachable: "No",
+                ..unknown(root_cause(error))
+            }
+        }
+        Err(error) => ContextStatus {
+            reachable: "Yes",
+            token: "Unknown".to_owned(),
+            certificates: verified_certificates,
+            error: Some(root_cause(error)),
+        },
+    }
+}
+
+fn root_cause(error: ClientError) -> String {
+    anyhow::Error::new(error).root_cause().to_string()
+}
+
+/// Whether the TLS handshake failed because of the server's certificate. The TLS errors are not
+/// exposed by reqwest, so this has to look at their messages.
+fn is_certificate_error(error: &ClientError) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(error) = source {
+        if error.to_string().to_lowercase().contains("certificate") {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
 fn new_table() -> Table {
     let mut table = Table::new();
     let format = prettytable::format::FormatBuilder::new()
@@ -1,6 +1,11 @@
 
-use crate::progress::{Options as ProgressOptions, Progress};
-use anyhow::{Context, Result};
+use crate::{
+    args::ContextDefault,
+    commands::create::journal::{Journal, Position, RejectFile, UploadTracker},
+    concurrency::AdaptiveConcurrency,
+    progress::{Options as ProgressOptions, Progress},
+};
+use anyhow::{bail, Context, Result};
 use colored::Colorize;
 use log::info;
 use reinfer_client::{
@@ -13,7 +18,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::mpsc::channel;
 use std::{
     fs::File,
-    io::{self, BufRead, BufReader},
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
     path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -28,11 +33,21 @@ pub struct CreateAnnotationsArgs {
     /// Path to JSON file with annotations. If not specified, stdin will be used.
     annotations_path: Option<PathBuf>,
 
-    #[structopt(short = "s", long = "source")]
+    #[structopt(
+        short = "s",
+        long = "source",
+        env = "REINFER_SOURCE",
+        context_default = "source"
+    )]
     /// Name or id of the source containing the annotated comments
     source: SourceIdentifier,
 
-    #[structopt(short = "d", long = "dataset")]
+    #[structopt(
+        short = "d",
+        long = "dataset",
+        env = "REINFER_DATASET",
+        context_default = "dataset"
+    )]
     /// Dataset (name or id) where to push the annotations. The dataset must contain the source.
     dataset: DatasetIdentifier,
 
@@ -48,6 +63,16 @@ pub struct CreateAnnotationsArgs {
     #[structopt(long = "batch-size", default_value = "128")]
     /// Number of comments to batch in a single request.
     batch_size: usize,
+
+    #[structopt(long)]
+    /// Resume an interrupted upload from `--file`, skipping the batches recorded as uploaded in
+    /// its journal file.
+    resume: bool,
+
+    #[structopt(long = "reject-file", parse(from_os_str))]
+    /// Path where to report the line numbers of annotations which could not be uploaded,
+    /// instead of aborting the upload.
+    reject_file: Option<PathBuf>,
 }
 
 pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) -> Result<()> {
@@ -60,8 +85,18 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
         .get_dataset(args.dataset.clone())
         .with_context(|| format!("Unable to get dataset {}", args.dataset))?;
     let dataset_name = dataset.full_name();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
+
+    if args.resume && args.annotations_path.is_none() {
+        bail!("Cannot resume an upload when `file` is not provided.")
+    }
+
+    let reject_file = match &args.reject_file {
+        Some(path) => Some(RejectFile::create(path, args.resume)?),
+        None => None,
+    };
 
-    let statistics = match &args.annotations_path {
+    let (statistics, tracker) = match &args.annotations_path {
         Some(annotations_path) => {
             info!(
                 "Uploading comments from file `{}` to source `{}` [id: {}] and dataset `{}` [id: {}]",
@@ -71,7 +106,7 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
                 dataset_name.0,
                 dataset.id.0,
             );
-            let file = BufReader::new(File::open(annotations_path).with_context(|| {
+            let mut file = BufReader::new(File::open(annotations_path).with_context(|| {
                 format!("Could not open file `{}`", annotations_path.display())
             })?);
             let file_metadata = file.get_ref().metadata().with_context(|| {
@@ -81,7 +116,16 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
                 )
             })?;
 
+            let (journal, position) = Journal::open(annotations_path, args.resume)?;
+            if position.line_number > 0 {
+                info!("Resuming upload after line {}", position.line_number);
+            }
+            file.seek(SeekFrom::Start(position.offset))
+                .with_context(|| "Unable to seek to the position recorded in the journal")?;
+            let mut tracker = UploadTracker::new(Some(journal), reject_file, position);
+
             let statistics = Arc::new(Statistics::new());
+            statistics.add_bytes_read(position.offset as usize);
             let progress = if args.no_progress {
                 None
             } else {
@@ -96,18 +140,24 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
                 args.use_moon_forms,
                 args.batch_size,
                 pool,
+                &concurrency,
+                &mut tracker,
             )?;
             if let Some(mut progress) = progress {
                 progress.done();
             }
-            Arc::try_unwrap(statistics)
-                .expect("Not all references to `statistics` have been disposed of")
+            (
+                Arc::try_unwrap(statistics)
+                    .expect("Not all references to `statistics` have been disposed of"),
+                tracker,
+            )
         }
         None => {
             info!(
                 "Uploading annotations from stdin to source `{}` [id: {}] and dataset `{} [id: {}]",
                 source_name.0, source.id.0, dataset_name.0, dataset.id.0
             );
+            let mut tracker = UploadTracker::new(None, reject_file, Position::default());
             let statistics = Statistics::new();
             upload_annotations_from_reader(
                 client,
@@ -118,8 +168,10 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
                 args.use_moon_forms,
                 args.batch_size,
                 pool,
+                &concurrency,
+                &mut tracker,
             )?;
-            statistics
+            (statistics, tracker)
         }
     };
 
@@ -127,13 +179,14 @@ pub fn create(client: &Client, args: &CreateAnnotationsArgs, pool: &mut Pool) ->
         "Successfully uploaded {} annotations.",
         statistics.num_annotations(),
     );
-    Ok(())
+    tracker.finish()
 }
 
 pub trait AnnotationStatistic {
     fn add_annotation(&self);
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn upload_batch_of_annotations(
     annotations_to_upload: &mut Vec<NewAnnotation>,
     client: &Client,
@@ -142,6 +195,7 @@ pub fn upload_batch_of_annotations(
     dataset_name: &DatasetFullName,
     use_moon_forms: bool,
     pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
 ) -> Result<()> {
     let (error_sender, error_receiver) = channel();
 
@@ -153,33 +207,36 @@ pub fn upload_batch_of_annotations(
                 let comment_uid =
                     CommentUid(format!("{}.{}", source.id.0, new_comment.comment.id.0));
 
-                let result = (if !use_moon_forms {
-                    client.update_labelling(
-                        dataset_name,
-                        &comment_uid,
-                        new_comment
-                            .labelling
-                            .clone()
-                            .map(Into::<Vec<NewLabelling>>::into)
-                            .as_deref(),
-                        new_comment.entities.as_ref(),
-                        None,
-                    )
-                } else {
-                    client.update_labelling(
-                        dataset_name,
-                        &comment_uid,
-                        None,
-                        None,
-                        new_comment.moon_forms.as_deref(),
-                    )
-                })
-                .with_context(|| {
-                    format!(
-                        "Could not update labelling for comment `{}`",
-                        &comment_uid.0
-                    )
-                });
+                let result = concurrency
+                    .run(|| {
+                        if !use_moon_forms {
+                            client.update_labelling(
+                                dataset_name,
+                                &comment_uid,
+                                new_comment
+                                    .labelling
+                                    .clone()
+                                    .map(Into::<Vec<NewLabelling>>::into)
+                                    .as_deref(),
+                                new_comment.entities.as_ref(),
+                                None,
+                            )
+                        } else {
+                            client.update_labelling(
+                                dataset_name,
+                                &comment_uid,
+                                None,
+                                None,
+                                new_comment.moon_forms.as_deref(),
+                            )
+                        }
+                    })
+                    .with_context(|| {
+                        format!(
+                            "Could not update labelling for comment `{}`",
+                            &comment_uid.0
+                        )
+                    });
 
                 if let Err(error) = result {
                     error_sender.send(error).expect("Could not send error");
@@ -208,16 +265,31 @@ fn upload_annotations_from_reader(
     use_moon_forms: bool,
     batch_size: usize,
     pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+    tracker: &mut UploadTracker,
 ) -> Result<()> {
     let mut annotations_to_upload = Vec::new();
 
-    for read_comment_result in read_annotations_iter(annotations, Some(statistics)) {
-        let new_comment = read_comment_result?;
-        if new_comment.has_annotations() {
+    for read_comment_result in
+        read_annotations_iter(annotations, Some(statistics), tracker.position())
+    {
+        let (position, new_comment) = read_comment_result?;
+        let new_comment = match new_comment {
+            Ok(new_comment) => new_comment,
+            Err(error) => {
+                tracker.read(position, false);
+                tracker.reject_line(position.line_number, error)?;
+                continue;
+            }
+        };
+        let has_annotations = new_comment.has_annotations();
+        tracker.read(position, has_annotations);
+
+        if has_annotations {
             annotations_to_upload.push(new_comment);
 
             if annotations_to_upload.len() >= batch_size {
-                upload_batch_of_annotations(
+                upload_annotations_and_commit(
                     &mut annotations_to_upload,
                     client,
                     source,
@@ -225,24 +297,54 @@ fn upload_annotations_from_reader(
                     dataset_name,
                     use_moon_forms,
                     pool,
+                    concurrency,
+                    tracker,
                 )?;
             }
         }
     }
 
+    upload_annotations_and_commit(
+        &mut annotations_to_upload,
+        client,
+        source,
+        statistics,
+        dataset_name,
+        use_moon_forms,
+        pool,
+        concurrency,
+        tracker,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn upload_annotations_and_commit(
+    annotations_to_upload: &mut Vec<NewAnnotation>,
+    client: &Client,
+    source: &Source,
+    statistics: &Statistics,
+    dataset_name: &DatasetFullName,
+    use_moon_forms: bool,
+    pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+    tracker: &mut UploadTracker,
+) -> Result<()> {
     if !annotations_to_upload.is_empty() {
-        upload_batch_of_annotations(
-            &mut annotations_to_upload,
+        if let Err(error) = upload_batch_of_annotations(
+            annotations_to_upload,
             client,
             source,
             statistics,
             dataset_name,
             use_moon_forms,
             pool,
-        )?;
+            concurrency,
+        ) {
+            tracker.reject_pending(error)?;
+            annotations_to_upload.clear();
+        }
     }
-
-    Ok(())
+    tracker.commit()
 }
 
 /// This struct only contains the minimal amount of data required to be able to upload annotations
@@ -272,14 +374,17 @@ impl HasAnnotations for NewAnnotation {
     }
 }
 
+/// Read annotations line by line, starting from the given position in the input. Each annotation
+/// is returned along with the position right after it; only failing to read the input is fatal.
 fn read_annotations_iter<'a>(
     mut annotations: impl BufRead + 'a,
     statistics: Option<&'a Statistics>,
-) -> impl Iterator<Item = Result<NewAnnotation>> + 'a {
+    start: Position,
+) -> impl Iterator<Item = Result<(Position, Result<NewAnnotation>)>> + 'a {
     let mut line = String::new();
-    let mut line_number: u32 = 0;
+    let mut position = start;
     std::iter::from_fn(move || {
-        line_number += 1;
+        let line_number = position.line_number + 1;
         line.clear();
 
         let read_result = annotations
@@ -292,15 +397,20 @@ fn read_annotations_iter<'a>(
                 if let Some(s) = statistics {
                     s.add_bytes_read(bytes_read)
                 }
+                position = Position {
+                    line_number,
+                    offset: position.offset + bytes_read as u64,
+                };
             }
             Err(e) => return Some(Err(e)),
         }
 
-        Some(
+        Some(Ok((
+            position,
             serde_json::from_str::<NewAnnotation>(line.trim_end()).with_context(|| {
                 format!("Could not parse annotations at line {line_number} from input stream")
             }),
-        )
+        )))
     })
 }
 
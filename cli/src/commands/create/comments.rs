@@ -1,16 +1,22 @@
 
 use crate::{
+    args::ContextDefault,
     commands::{
-        create::annotations::{
-            upload_batch_of_annotations, AnnotationStatistic, CommentIdComment, NewAnnotation,
+        create::{
+            annotations::{
+                upload_batch_of_annotations, AnnotationStatistic, CommentIdComment, NewAnnotation,
+            },
+            comments_csv::{read_csv_comments_iter, CommentMapping},
+            journal::{Journal, Position, RejectFile, UploadTracker},
         },
         ensure_uip_user_consents_to_ai_unit_charge,
     },
+    concurrency::AdaptiveConcurrency,
     progress::{Options as ProgressOptions, Progress},
 };
-use anyhow::{anyhow, ensure, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use colored::Colorize;
-use log::{debug, info};
+use log::{debug, info, warn};
 use reinfer_client::{
     Client, CommentId, DatasetFullName, DatasetIdentifier, NewAnnotatedComment, NewComment, Source,
     SourceIdentifier,
@@ -19,8 +25,9 @@ use scoped_threadpool::Pool;
 use std::{
     collections::HashSet,
     fs::File,
-    io::{self, BufRead, BufReader, Seek},
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
     path::PathBuf,
+    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -32,10 +39,15 @@ use structopt::StructOpt;
 #[derive(Debug, StructOpt)]
 pub struct CreateCommentsArgs {
     #[structopt(short = "f", long = "file", parse(from_os_str))]
-    /// Path to JSON file with comments. If not specified, stdin will be used.
+    /// Path to JSON or CSV file with comments. If not specified, stdin will be used.
     comments_path: Option<PathBuf>,
 
-    #[structopt(short = "s", long = "source")]
+    #[structopt(
+        short = "s",
+        long = "source",
+        env = "REINFER_SOURCE",
+        context_default = "source"
+    )]
     /// Name or id of the source where the comments will be uploaded.
     source: SourceIdentifier,
 
@@ -75,6 +87,45 @@ pub struct CreateCommentsArgs {
     #[structopt(short = "y", long = "yes")]
     /// Consent to ai unit charge. Suppresses confirmation prompt.
     yes: bool,
+
+    #[structopt(long)]
+    /// Resume an interrupted upload from `--file`, skipping the batches recorded as uploaded in
+    /// its journal file.
+    resume: bool,
+
+    #[structopt(long = "reject-file", parse(from_os_str))]
+    /// Path where to report the line numbers of comments which could not be uploaded, instead
+    /// of aborting the upload.
+    reject_file: Option<PathBuf>,
+
+    #[structopt(long = "format", default_value = "json")]
+    /// Format of the input. One of: json, csv
+    format: InputFormat,
+
+    #[structopt(long = "mapping", parse(from_os_str))]
+    /// Path to a JSON file mapping CSV columns to comment fields. Required with `--format csv`.
+    mapping: Option<PathBuf>,
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        if string == "json" {
+            Ok(InputFormat::Json)
+        } else if string == "csv" {
+            Ok(InputFormat::Csv)
+        } else {
+            Err(anyhow!("{}", string))
+        }
+    }
 }
 
 pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Result<()> {
@@ -87,6 +138,7 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
         .with_context(|| format!("Unable to get source {}", args.source))?;
 
     let source_name = source.full_name();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
 
     let dataset_name = match args.dataset.as_ref() {
         Some(dataset_ident) => Some(
@@ -100,7 +152,29 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
 
     ensure!(args.batch_size > 0, "--batch-size must be greater than 0");
 
-    let statistics = match &args.comments_path {
+    if args.resume && args.comments_path.is_none() {
+        bail!("Cannot resume an upload when `file` is not provided.")
+    }
+
+    let mapping = match (args.format, &args.mapping) {
+        (InputFormat::Csv, Some(mapping_path)) => Some(CommentMapping::from_file(mapping_path)?),
+        (InputFormat::Csv, None) => bail!("The `mapping` option is required with `--format csv`."),
+        (InputFormat::Json, Some(_)) => {
+            bail!("The `mapping` option can only be used with `--format csv`.")
+        }
+        (InputFormat::Json, None) => None,
+    };
+
+    if mapping.is_some() && args.comments_path.is_none() {
+        bail!("Cannot upload comments with `--format csv` when `file` is not provided.")
+    }
+
+    let reject_file = match &args.reject_file {
+        Some(path) => Some(RejectFile::create(path, args.resume)?),
+        None => None,
+    };
+
+    let (statistics, tracker) = match &args.comments_path {
         Some(comments_path) => {
             info!(
                 "Uploading comments from file `{}` to source `{}` [id: {}]",
@@ -119,19 +193,43 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
                 )
             })?;
 
-            if !args.allow_duplicates {
+            if let Some(mapping) = &mapping {
+                debug!("Validating `{}`", comments_path.display());
+                validate_comments(
+                    read_csv_comments_iter(&mut file, mapping, Position::default())?,
+                    !args.allow_duplicates,
+                    args.reject_file.is_some(),
+                )?;
+                file.rewind()
+                    .with_context(|| "Unable to seek to file start after validating comments")?;
+            } else if !args.allow_duplicates {
                 debug!(
                     "Checking `{}` for duplicate comment ids",
                     comments_path.display(),
                 );
-                check_no_duplicate_ids(&mut file)?;
+                check_no_duplicate_ids(
+                    read_comments_iter(&mut file, None, Position::default()),
+                    args.reject_file.is_some(),
+                )?;
 
                 file.rewind().with_context(|| {
                     "Unable to seek to file start after checking for duplicate ids"
                 })?;
             }
 
+            let (journal, position) = Journal::open(comments_path, args.resume)?;
+            if position.line_number > 0 {
+                info!("Resuming upload after line {}", position.line_number);
+            }
+            // CSV rows are skipped while reading instead, as the header must be read first.
+            if mapping.is_none() {
+                file.seek(SeekFrom::Start(position.offset))
+                    .with_context(|| "Unable to seek to the position recorded in the journal")?;
+            }
+            let mut tracker = UploadTracker::new(Some(journal), reject_file, position);
+
             let statistics = Arc::new(Statistics::new());
+            statistics.add_bytes_read(position.offset as usize);
             let progress = if args.no_progress {
                 None
             } else {
@@ -141,10 +239,18 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
                     args.overwrite,
                 ))
             };
-            upload_comments_from_reader(
+            let comments: Box<dyn Iterator<Item = _>> = match &mapping {
+                Some(mapping) => Box::new(with_bytes_read(
+                    read_csv_comments_iter(file, mapping, position)?,
+                    &statistics,
+                    position,
+                )),
+                None => Box::new(read_comments_iter(file, Some(&*statistics), position)),
+            };
+            upload_comments(
                 client,
                 &source,
-                file,
+                comments,
                 args.batch_size,
                 &statistics,
                 dataset_name.as_ref(),
@@ -153,11 +259,13 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
                 args.use_moon_forms,
                 args.no_charge,
                 pool,
+                &concurrency,
+                &mut tracker,
             )?;
             if let Some(mut progress) = progress {
                 progress.done();
             }
-            Arc::try_unwrap(statistics).unwrap()
+            (Arc::try_unwrap(statistics).unwrap(), tracker)
         }
         None => {
             info!(
@@ -168,11 +276,16 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
                 args.allow_duplicates,
                 "--allow-duplicates is required when uploading from stdin"
             );
+            let mut tracker = UploadTracker::new(None, reject_file, Position::default());
             let statistics = Statistics::new();
-            upload_comments_from_reader(
+            upload_comments(
                 client,
                 &source,
-                BufReader::new(io::stdin()),
+                read_comments_iter(
+                    BufReader::new(io::stdin()),
+                    Some(&statistics),
+                    Position::default(),
+                ),
                 args.batch_size,
                 &statistics,
                 dataset_name.as_ref(),
@@ -181,8 +294,10 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
                 args.use_moon_forms,
                 args.no_charge,
                 pool,
+                &concurrency,
+                &mut tracker,
             )?;
-            statistics
+            (statistics, tracker)
         }
     };
 
@@ -208,17 +323,20 @@ pub fn create(client: &Client, args: &CreateCommentsArgs, pool: &mut Pool) -> Re
         );
     }
 
-    Ok(())
+    tracker.finish()
 }
 
+/// Read comments line by line, starting from the given position in the input. Each comment is
+/// returned along with the position right after it; only failing to read the input is fatal.
 fn read_comments_iter<'a>(
     mut comments: impl BufRead + 'a,
     statistics: Option<&'a Statistics>,
-) -> impl Iterator<Item = Result<NewAnnotatedComment>> + 'a {
+    start: Position,
+) -> impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>> + 'a {
     let mut line = String::new();
-    let mut line_number: u32 = 0;
+    let mut position = start;
     std::iter::from_fn(move || {
-        line_number += 1;
+        let line_number = position.line_number + 1;
         line.clear();
 
         let read_result = comments
@@ -231,22 +349,51 @@ fn read_comments_iter<'a>(
                 if let Some(s) = statistics {
                     s.add_bytes_read(bytes_read)
                 }
+                position = Position {
+                    line_number,
+                    offset: position.offset + bytes_read as u64,
+                };
             }
             Err(e) => return Some(Err(e)),
         }
 
-        Some(
+        Some(Ok((
+            position,
             serde_json::from_str::<NewAnnotatedComment>(line.trim_end()).with_context(|| {
                 format!("Could not parse comment at line {line_number} from input stream")
             }),
-        )
+        )))
     })
 }
 
-fn check_no_duplicate_ids(comments: impl BufRead) -> Result<()> {
+/// Count the bytes of input read by an iterator over comments which does not do so itself.
+fn with_bytes_read<'a>(
+    comments: impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>> + 'a,
+    statistics: &'a Statistics,
+    start: Position,
+) -> impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>> + 'a {
+    let mut offset = start.offset;
+    comments.inspect(move |read_comment_result| {
+        if let Ok((position, _)) = read_comment_result {
+            statistics.add_bytes_read((position.offset - offset) as usize);
+            offset = position.offset;
+        }
+    })
+}
+
+/// Check the input for duplicate comment ids. Comments which cannot be parsed are skipped if
+/// `skip_invalid` is set, as they will be rejected during the upload.
+fn check_no_duplicate_ids(
+    comments: impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>>,
+    skip_invalid: bool,
+) -> Result<()> {
     let mut seen = HashSet::new();
-    for read_comment_result in read_comments_iter(comments, None) {
-        let new_comment = read_comment_result?;
+    for read_comment_result in comments {
+        let new_comment = match read_comment_result?.1 {
+            Ok(new_comment) => new_comment,
+            Err(_) if skip_invalid => continue,
+            Err(error) => return Err(error),
+        };
         let id = new_comment.comment.id;
 
         if !seen.insert(id.clone()) {
@@ -257,6 +404,38 @@ fn check_no_duplicate_ids(comments: impl BufRead) -> Result<()> {
     Ok(())
 }
 
+/// Check every comment in the input before uploading any of them, reporting all the invalid ones
+/// along with their line number. Invalid comments are tolerated if `skip_invalid` is set, as they
+/// will be rejected during the upload.
+fn validate_comments(
+    comments: impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>>,
+    check_duplicates: bool,
+    skip_invalid: bool,
+) -> Result<()> {
+    let mut seen = HashSet::new();
+    let mut num_invalid = 0;
+    for read_comment_result in comments {
+        let new_comment = match read_comment_result?.1 {
+            Ok(new_comment) => new_comment,
+            Err(error) => {
+                warn!("{:#}", error);
+                num_invalid += 1;
+                continue;
+            }
+        };
+        let id = new_comment.comment.id;
+
+        if check_duplicates && !seen.insert(id.clone()) {
+            return Err(anyhow!("Duplicate comments with id {}", id.0));
+        }
+    }
+
+    if num_invalid > 0 && !skip_invalid {
+        bail!("{} comments in the input are invalid", num_invalid);
+    }
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 fn upload_batch_of_comments(
     client: &Client,
@@ -319,10 +498,10 @@ fn upload_batch_of_comments(
 }
 
 #[allow(clippy::too_many_arguments)]
-fn upload_comments_from_reader(
+fn upload_comments(
     client: &Client,
     source: &Source,
-    comments: impl BufRead,
+    comments: impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>>,
     batch_size: usize,
     statistics: &Statistics,
     dataset_name: Option<&DatasetFullName>,
@@ -331,6 +510,8 @@ fn upload_comments_from_reader(
     use_moon_forms: bool,
     no_charge: bool,
     pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+    tracker: &mut UploadTracker,
 ) -> Result<()> {
     assert!(batch_size > 0);
 
@@ -349,8 +530,17 @@ fn upload_comments_from_reader(
         move |id: &CommentId| overwrite || (allow_duplicates && !seen.insert(id.clone()))
     };
 
-    for read_comment_result in read_comments_iter(comments, Some(statistics)) {
-        let new_comment = read_comment_result?;
+    for read_comment_result in comments {
+        let (position, new_comment) = read_comment_result?;
+        let new_comment = match new_comment {
+            Ok(new_comment) => new_comment,
+            Err(error) => {
+                tracker.read(position, false);
+                tracker.reject_line(position.line_number, error)?;
+                continue;
+            }
+        };
+        tracker.read(position, true);
 
         if dataset_name.is_some() && new_comment.has_annotations() {
             if !use_moon_forms {
@@ -384,45 +574,13 @@ fn upload_comments_from_reader(
             comments_to_put.push(new_comment.comment);
         }
 
-        if (comments_to_put.len() + comments_to_sync.len()) >= batch_size {
-            upload_batch_of_comments(
-                client,
-                source,
-                statistics,
-                &mut comments_to_put,
-                &mut comments_to_sync,
-                &mut audio_paths,
-                no_charge,
-            )?;
-        }
-
-        if let Some(dataset_name) = dataset_name {
-            if annotations.len() >= batch_size {
-                upload_batch_of_comments(
-                    client,
-                    source,
-                    statistics,
-                    &mut comments_to_put,
-                    &mut comments_to_sync,
-                    &mut audio_paths,
-                    no_charge,
-                )?;
-
-                upload_batch_of_annotations(
-                    &mut annotations,
-                    client,
-                    source,
-                    statistics,
-                    dataset_name,
-                    use_moon_forms,
-                    pool,
-                )?;
-            }
+        let comments_batch_full = (comments_to_put.len() + comments_to_sync.len()) >= batch_size;
+        let annotations_batch_full = dataset_name.is_some() && annotations.len() >= batch_size;
+        if !comments_batch_full && !annotations_batch_full {
+            continue;
         }
-    }
 
-    if !comments_to_put.is_empty() || !comments_to_sync.is_empty() {
-        upload_batch_of_comments(
+        let result = upload_batch_of_comments(
             client,
             source,
             statistics,
@@ -430,12 +588,9 @@ fn upload_comments_from_reader(
             &mut comments_to_sync,
             &mut audio_paths,
             no_charge,
-        )?;
-    }
-
-    if let Some(dataset_name) = dataset_name {
-        if !annotations.is_empty() {
-            upload_batch_of_annotations(
+        )
+        .and_then(|()| match dataset_name {
+            Some(dataset_name) if annotations_batch_full => upload_batch_of_annotations(
                 &mut annotations,
                 client,
                 source,
@@ -443,10 +598,52 @@ fn upload_comments_from_reader(
                 dataset_name,
                 use_moon_forms,
                 pool,
-            )?;
+                concurrency,
+            ),
+            _ => Ok(()),
+        });
+
+        if let Err(error) = result {
+            tracker.reject_pending(error)?;
+            comments_to_put.clear();
+            comments_to_sync.clear();
+            audio_paths.clear();
+            annotations.clear();
+        }
+
+        // Comments are only committed to the journal once their annotations are uploaded too.
+        if annotations.is_empty() {
+            tracker.commit()?;
         }
     }
-    Ok(())
+
+    let result = upload_batch_of_comments(
+        client,
+        source,
+        statistics,
+        &mut comments_to_put,
+        &mut comments_to_sync,
+        &mut audio_paths,
+        no_charge,
+    )
+    .and_then(|()| match dataset_name {
+        Some(dataset_name) if !annotations.is_empty() => upload_batch_of_annotations(
+            &mut annotations,
+            client,
+            source,
+            statistics,
+            dataset_name,
+            use_moon_forms,
+            pool,
+            concurrency,
+        ),
+        _ => Ok(()),
+    });
+
+    if let Err(error) = result {
+        tracker.reject_pending(error)?;
+    }
+    tracker.commit()
 }
 
 #[derive(Debug)]
@@ -592,7 +789,7 @@ fn progress_bar(
 
 #[cfg(test)]
 mod tests {
-    use super::{check_no_duplicate_ids, read_comments_iter, Statistics};
+    use super::{check_no_duplicate_ids, read_comments_iter, Position, Statistics};
     use std::io::{BufReader, Cursor};
 
     const SAMPLE_DUPLICATES: &str = include_str!("../../../tests/samples/duplicates.jsonl");
@@ -602,7 +799,7 @@ mod tests {
         let reader = BufReader::new(Cursor::new(SAMPLE_DUPLICATES));
         let statistics = Statistics::new();
 
-        let comments_iter = read_comments_iter(reader, Some(&statistics));
+        let comments_iter = read_comments_iter(reader, Some(&statistics), Position::default());
 
         assert_eq!(comments_iter.count(), 5);
         assert_eq!(statistics.bytes_read(), SAMPLE_DUPLICATES.len());
@@ -611,7 +808,8 @@ mod tests {
     #[test]
     fn check_detects_duplicates() {
         let reader = BufReader::new(Cursor::new(SAMPLE_DUPLICATES));
-        let result = check_no_duplicate_ids(reader);
+        let result =
+            check_no_duplicate_ids(read_comments_iter(reader, None, Position::default()), false);
 
         assert!(result.is_err());
         assert!(result
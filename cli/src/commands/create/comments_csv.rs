@@ -0,0 +1,356 @@
+//! Conversion of CSV rows into comments, using a mapping file which says which column holds
+//! each field of a comment.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use ordered_float::NotNan;
+use reinfer_client::{
+    resources::comment::ThreadId, CommentId, Message, MessageBody, MessageSubject,
+    NewAnnotatedComment, NewComment, PropertyMap,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs::File, io::Read, path::Path};
+
+use super::journal::Position;
+
+/// Which CSV column holds each field of a comment, read from the `--mapping` file, e.g.
+///
+/// ```json
+/// {
+///   "id": "Ticket ID",
+///   "timestamp": "Created",
+///   "body": "Description",
+///   "subject": "Title",
+///   "user_properties": {
+///     "Priority": {"column": "Priority"},
+///     "Score": {"column": "CSAT", "type": "number"}
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommentMapping {
+    id: String,
+    timestamp: String,
+    /// `strftime` style format of the timestamps, which are assumed to be in UTC. Defaults to
+    /// RFC 3339.
+    #[serde(default)]
+    timestamp_format: Option<String>,
+    #[serde(default)]
+    thread_id: Option<String>,
+    body: String,
+    #[serde(default)]
+    subject: Option<String>,
+    #[serde(default)]
+    from: Option<String>,
+    /// Column with the recipients, separated by `,` or `;`.
+    #[serde(default)]
+    to: Option<String>,
+    #[serde(default)]
+    user_properties: BTreeMap<String, UserPropertyMapping>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserPropertyMapping {
+    column: String,
+    #[serde(rename = "type", default)]
+    kind: UserPropertyKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UserPropertyKind {
+    #[default]
+    String,
+    Number,
+}
+
+impl CommentMapping {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Could not open mapping file `{}`", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("Could not parse mapping file `{}`", path.display()))
+    }
+}
+
+/// A mapping resolved against the header of a CSV file, holding column indices.
+struct ColumnIndices {
+    id: usize,
+    timestamp: usize,
+    timestamp_format: Option<String>,
+    thread_id: Option<usize>,
+    body: usize,
+    subject: Option<usize>,
+    from: Option<usize>,
+    to: Option<usize>,
+    user_properties: Vec<(String, usize, UserPropertyKind)>,
+}
+
+impl ColumnIndices {
+    fn new(mapping: &CommentMapping, header: &csv::StringRecord) -> Result<Self> {
+        let index = |column: &str| {
+            header
+                .iter()
+                .position(|name| name == column)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Column `{}` from the mapping is not in the CSV header",
+                        column
+                    )
+                })
+        };
+        let optional_index = |column: &Option<String>| column.as_deref().map(index).transpose();
+
+        Ok(Self {
+            id: index(&mapping.id)?,
+            timestamp: index(&mapping.timestamp)?,
+            timestamp_format: mapping.timestamp_format.clone(),
+            thread_id: optional_index(&mapping.thread_id)?,
+            body: index(&mapping.body)?,
+            subject: optional_index(&mapping.subject)?,
+            from: optional_index(&mapping.from)?,
+            to: optional_index(&mapping.to)?,
+            user_properties: mapping
+                .user_properties
+                .iter()
+                .map(|(name, property)| Ok((name.clone(), index(&property.column)?, property.kind)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn to_comment(&self, record: &csv::StringRecord) -> Result<NewAnnotatedComment> {
+        let field = |index: usize| {
+            record.get(index).map(str::trim).ok_or_else(|| {
+                anyhow!(
+                    "Row has {} fields, expected at least {}",
+                    record.len(),
+                    index + 1
+                )
+            })
+        };
+        let non_empty_field = |index: Option<usize>| -> Result<Option<String>> {
+            Ok(match index {
+                Some(index) => Some(field(index)?)
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_owned),
+                None => None,
+            })
+        };
+
+        let id = field(self.id)?;
+        if id.is_empty() {
+            bail!("Comment id is empty");
+        }
+
+        let mut user_properties = PropertyMap::new();
+        for (name, index, kind) in &self.user_properties {
+            let value = field(*index)?;
+            if value.is_empty() {
+                continue;
+            }
+            match kind {
+                UserPropertyKind::String => {
+                    user_properties.insert_string(name.clone(), value.to_owned())
+                }
+                UserPropertyKind::Number => {
+                    let number = value
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(|number| NotNan::new(number).ok())
+                        .ok_or_else(|| {
+                            anyhow!("User property `{}` has non-numeric value `{}`", name, value)
+                        })?;
+                    user_properties.insert_number(name.clone(), number)
+                }
+            }
+        }
+
+        Ok(NewAnnotatedComment {
+            comment: NewComment {
+                id: CommentId(id.to_owned()),
+                thread_id: non_empty_field(self.thread_id)?.map(ThreadId),
+                timestamp: parse_timestamp(
+                    field(self.timestamp)?,
+                    self.timestamp_format.as_deref(),
+                )?,
+                messages: vec![Message {
+                    body: MessageBody {
+                        text: field(self.body)?.to_owned(),
+                        translated_from: None,
+                        text_markup: None,
+                        translated_from_markup: None,
+                    },
+                    language: None,
+                    subject: non_empty_field(self.subject)?.map(|text| MessageSubject {
+                        text,
+                        translated_from: None,
+                    }),
+                    signature: None,
+                    from: non_empty_field(self.from)?,
+                    to: non_empty_field(self.to)?.map(|to| {
+                        to.split([',', ';'])
+                            .map(str::trim)
+                            .filter(|recipient| !recipient.is_empty())
+                            .map(str::to_owned)
+                            .collect()
+                    }),
+                    cc: None,
+                    bcc: None,
+                    sent_at: None,
+                }],
+                user_properties,
+                attachments: Vec::new(),
+            },
+            labelling: None,
+            entities: None,
+            audio_path: None,
+            moon_forms: None,
+        })
+    }
+}
+
+fn parse_timestamp(value: &str, format: Option<&str>) -> Result<DateTime<Utc>> {
+    match format {
+        Some(format) => NaiveDateTime::parse_from_str(value, format)
+            .map(|timestamp| Utc.from_utc_datetime(&timestamp))
+            .with_context(|| format!("Timestamp `{value}` does not match format `{format}`")),
+        None => DateTime::parse_from_rfc3339(value)
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .with_context(|| format!("Timestamp `{value}` is not in RFC 3339 format")),
+    }
+}
+
+/// Read comments from CSV rows, skipping the rows up to the given position. Each comment is
+/// returned along with the position right after it, where the line number is the line the row
+/// starts on; only failing to read the input is fatal.
+pub fn read_csv_comments_iter<'a>(
+    reader: impl Read + 'a,
+    mapping: &CommentMapping,
+    start: Position,
+) -> Result<impl Iterator<Item = Result<(Position, Result<NewAnnotatedComment>)>> + 'a> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let header = reader
+        .headers()
+        .context("Could not read CSV header")?
+        .clone();
+    let columns = ColumnIndices::new(mapping, &header)?;
+
+    let mut record = csv::StringRecord::new();
+    Ok(std::iter::from_fn(move || loop {
+        let line_number = reader.position().line() as usize;
+        let read_result = reader.read_record(&mut record);
+        let position = Position {
+            line_number,
+            offset: reader.position().byte(),
+        };
+        let comment = match read_result {
+            Ok(false) => return None,
+            Ok(true) => columns.to_comment(&record),
+            Err(error) if error.is_io_error() => {
+                return Some(Err(error).context("Could not read CSV input"))
+            }
+            Err(error) => Err(error.into()),
+        };
+        if line_number > start.line_number {
+            return Some(Ok((
+                position,
+                comment.with_context(|| format!("Could not parse comment at line {line_number}")),
+            )));
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_csv_comments_iter, CommentMapping, Position};
+    use pretty_assertions::assert_eq;
+    use reinfer_client::PropertyValue;
+    use std::io::Cursor;
+
+    const SAMPLE_CSV: &str = concat!(
+        "Ticket,Created,Title,Description,Requester,Assignees,Tier,Score\n",
+        "t-1,2023-01-02T03:04:05Z,Refund,\"Please refund\nmy order\",a@example.com,\"b@example.com; c@example.com\",Gold,7.5\n",
+        "t-2,yesterday,Hi,Hello,,,,\n",
+        "t-3,2023-01-03T00:00:00Z,Hi,Hello,,,Silver,high\n",
+        ",2023-01-03T00:00:00Z,Hi,Hello,,,,\n",
+    );
+
+    fn mapping() -> CommentMapping {
+        serde_json::from_str(
+            r#"{
+                "id": "Ticket",
+                "timestamp": "Created",
+                "subject": "Title",
+                "body": "Description",
+                "from": "Requester",
+                "to": "Assignees",
+                "user_properties": {
+                    "Tier": {"column": "Tier"},
+                    "Score": {"column": "Score", "type": "number"}
+                }
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_read_csv_comments_iter() {
+        let rows: Vec<_> =
+            read_csv_comments_iter(Cursor::new(SAMPLE_CSV), &mapping(), Position::default())
+                .unwrap()
+                .map(Result::unwrap)
+                .collect();
+
+        assert_eq!(
+            rows.iter()
+                .map(|(position, comment)| (position.line_number, comment.is_ok()))
+                .collect::<Vec<_>>(),
+            vec![(2, true), (4, false), (5, false), (6, false)]
+        );
+        assert_eq!(rows.last().unwrap().0.offset, SAMPLE_CSV.len() as u64);
+
+        let comment = &rows[0].1.as_ref().unwrap().comment;
+        assert_eq!(comment.id.0, "t-1");
+        assert_eq!(comment.messages[0].body.text, "Please refund\nmy order");
+        assert_eq!(
+            comment.messages[0].to,
+            Some(vec!["b@example.com".to_owned(), "c@example.com".to_owned()])
+        );
+        assert_eq!(
+            comment.user_properties.get("Tier"),
+            Some(&PropertyValue::String("Gold".to_owned()))
+        );
+        assert_eq!(
+            comment.user_properties.get("Score"),
+            Some(&PropertyValue::Number(7.5.try_into().unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_read_csv_comments_iter_skips_to_start() {
+        let start = Position {
+            line_number: 4,
+            offset: 0,
+        };
+        let line_numbers: Vec<_> =
+            read_csv_comments_iter(Cursor::new(SAMPLE_CSV), &mapping(), start)
+                .unwrap()
+                .map(|row| row.unwrap().0.line_number)
+                .collect();
+
+        assert_eq!(line_numbers, vec![5, 6]);
+    }
+
+    #[test]
+    fn test_missing_column_is_rejected() {
+        let mapping: CommentMapping =
+            serde_json::from_str(r#"{"id": "Ticket", "timestamp": "Created", "body": "Text"}"#)
+                .unwrap();
+
+        assert!(
+            read_csv_comments_iter(Cursor::new(SAMPLE_CSV), &mapping, Position::default()).is_err()
+        );
+    }
+}
@@ -15,6 +15,7 @@ use std::{
 use structopt::StructOpt;
 
 use crate::{
+    args::ContextDefault,
     commands::ensure_uip_user_consents_to_ai_unit_charge,
     progress::{Options as ProgressOptions, Progress},
 };
@@ -25,7 +26,12 @@ pub struct CreateEmailsArgs {
     /// Path to JSON file with emails. If not specified, stdin will be used.
     emails_path: Option<PathBuf>,
 
-    #[structopt(short = "b", long = "bucket")]
+    #[structopt(
+        short = "b",
+        long = "bucket",
+        env = "REINFER_BUCKET",
+        context_default = "bucket"
+    )]
     /// Name of the bucket where the emails will be uploaded.
     bucket: BucketIdentifier,
 
@@ -0,0 +1,348 @@
+//! Bookkeeping for uploads read line by line from an input file: a journal of how far the input
+//! has been uploaded, so an interrupted upload can be resumed, and a report of the input lines
+//! which could not be uploaded.
+
+use anyhow::{Context, Error, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::errors::PartialFailure;
+
+/// How far into the input an upload has read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    /// Number of lines read so far.
+    pub line_number: usize,
+    /// Number of bytes read so far.
+    pub offset: u64,
+}
+
+/// Append-only record of the positions up to which every line of the input has been uploaded
+/// (or rejected). It lives next to the input file as `<input>.journal`.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    /// Open the journal for the given input file, returning the position to start reading from.
+    ///
+    /// Unless `resume` is set, any existing journal is discarded and the upload starts from the
+    /// beginning of the input. When resuming, an entry cut short by an interrupted upload is
+    /// removed, so that new entries start on a line of their own.
+    pub fn open(input_path: &Path, resume: bool) -> Result<(Self, Position)> {
+        let path = journal_path(input_path);
+        let (position, valid_len) = if resume {
+            read_last_position(&path)?
+        } else {
+            (Position::default(), 0)
+        };
+
+        let mut options = OpenOptions::new();
+        if resume {
+            if path.exists() {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|file| file.set_len(valid_len))
+                    .with_context(|| {
+                        format!("Could not repair journal file `{}`", path.display())
+                    })?;
+            }
+            options.create(true).append(true);
+        } else {
+            options.create(true).write(true).truncate(true);
+        }
+        let file = options
+            .open(&path)
+            .with_context(|| format!("Could not open journal file `{}`", path.display()))?;
+
+        Ok((Self { path, file }, position))
+    }
+
+    fn commit(&mut self, position: Position) -> Result<()> {
+        let mut entry = serde_json::to_string(&position).context("Could not serialise position")?;
+        entry.push('\n');
+        self.file
+            .write_all(entry.as_bytes())
+            .and_then(|_| self.file.sync_data())
+            .with_context(|| format!("Could not write journal file `{}`", self.path.display()))
+    }
+
+    fn finish(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Could not remove journal file `{}`", self.path.display()))
+    }
+}
+
+fn journal_path(input_path: &Path) -> PathBuf {
+    let mut file_name = input_path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".journal");
+    input_path.with_file_name(file_name)
+}
+
+/// The last position in a journal file, and the length of the journal up to the end of its
+/// last complete entry.
+fn read_last_position(path: &Path) -> Result<(Position, u64)> {
+    if !path.exists() {
+        info!(
+            "No journal file found at `{}`, starting from the beginning of the input.",
+            path.display()
+        );
+        return Ok((Position::default(), 0));
+    }
+    let file = File::open(path)
+        .with_context(|| format!("Could not open journal file `{}`", path.display()))?;
+    last_position(BufReader::new(file))
+        .with_context(|| format!("Could not read journal file `{}`", path.display()))
+}
+
+fn last_position(mut journal: impl BufRead) -> Result<(Position, u64)> {
+    let mut position = Position::default();
+    let mut valid_len = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let num_bytes = journal.read_line(&mut line)?;
+        // The last entry may have been cut short if the previous upload was killed while writing
+        // it, in which case the entry before it still holds.
+        if !line.ends_with('\n') {
+            break;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => position = entry,
+            Err(_) => break,
+        }
+        valid_len += num_bytes as u64;
+    }
+    Ok((position, valid_len))
+}
+
+#[derive(Serialize)]
+struct Rejection<'a> {
+    line_number: usize,
+    error: &'a str,
+}
+
+/// Report of the input lines which could not be uploaded, one JSON object per line.
+pub struct RejectFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    num_rejected: usize,
+}
+
+impl RejectFile {
+    /// Create the reject file, appending to an existing one when resuming an upload.
+    pub fn create(path: &Path, append: bool) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        if append {
+            options.create(true).append(true);
+        } else {
+            options.create(true).write(true).truncate(true);
+        }
+        let file = options
+            .open(path)
+            .with_context(|| format!("Could not open reject file `{}`", path.display()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            writer: BufWriter::new(file),
+            num_rejected: 0,
+        })
+    }
+
+    fn reject(&mut self, line_numbers: &[usize], error: &Error) -> Result<()> {
+        let error = format!("{error:#}");
+        for &line_number in line_numbers {
+            serde_json::to_writer(
+                &mut self.writer,
+                &Rejection {
+                    line_number,
+                    error: &error,
+                },
+            )
+            .context("Could not serialise rejected line")?;
+            writeln!(self.writer).with_context(|| {
+                format!("Could not write reject file `{}`", self.path.display())
+            })?;
+        }
+        self.num_rejected += line_numbers.len();
+        Ok(())
+    }
+}
+
+/// Keeps track of the input lines read but not yet uploaded, so that they can be committed to
+/// the journal once uploaded, or written to the reject file if their batch fails.
+pub struct UploadTracker {
+    journal: Option<Journal>,
+    reject_file: Option<RejectFile>,
+    position: Position,
+    pending_line_numbers: Vec<usize>,
+}
+
+impl UploadTracker {
+    pub fn new(
+        journal: Option<Journal>,
+        reject_file: Option<RejectFile>,
+        position: Position,
+    ) -> Self {
+        Self {
+            journal,
+            reject_file,
+            position,
+            pending_line_numbers: Vec::new(),
+        }
+    }
+
+    /// The position after the last line read.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Record that a line has been read. Lines which are `pending` must be uploaded before the
+    /// journal moves past them.
+    pub fn read(&mut self, position: Position, pending: bool) {
+        self.position = position;
+        if pending {
+            self.pending_line_numbers.push(position.line_number);
+        }
+    }
+
+    /// Reject a single line, e.g. because it could not be parsed.
+    ///
+    /// Without a reject file this aborts the upload, returning the error.
+    pub fn reject_line(&mut self, line_number: usize, error: Error) -> Result<()> {
+        match self.reject_file.as_mut() {
+            Some(reject_file) => reject_file.reject(&[line_number], &error),
+            None => Err(error),
+        }
+    }
+
+    /// Reject all the pending lines, because the batch they belong to could not be uploaded.
+    ///
+    /// Without a reject file this aborts the upload, returning the error.
+    pub fn reject_pending(&mut self, error: Error) -> Result<()> {
+        let reject_file = match self.reject_file.as_mut() {
+            Some(reject_file) => reject_file,
+            None => return Err(error),
+        };
+        warn!(
+            "Rejecting {} lines which could not be uploaded: {:#}",
+            self.pending_line_numbers.len(),
+            error
+        );
+        reject_file.reject(&self.pending_line_numbers, &error)?;
+        self.pending_line_numbers.clear();
+        Ok(())
+    }
+
+    /// Record that every line read so far has been uploaded.
+    pub fn commit(&mut self) -> Result<()> {
+        self.pending_line_numbers.clear();
+        match self.journal.as_mut() {
+            Some(journal) => journal.commit(self.position),
+            None => Ok(()),
+        }
+    }
+
+    /// Clean up the journal once the whole input has been handled, and fail if any lines were
+    /// rejected along the way.
+    pub fn finish(self) -> Result<()> {
+        if let Some(journal) = self.journal {
+            journal.finish()?;
+        }
+        if let Some(mut reject_file) = self.reject_file {
+            reject_file.writer.flush().with_context(|| {
+                format!(
+                    "Could not write reject file `{}`",
+                    reject_file.path.display()
+                )
+            })?;
+            if reject_file.num_rejected > 0 {
+                return Err(PartialFailure(format!(
+                    "{} input lines could not be uploaded, see `{}` for their line numbers",
+                    reject_file.num_rejected,
+                    reject_file.path.display()
+                ))
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{journal_path, last_position, Journal, Position};
+    use std::{fs, io::Cursor, path::PathBuf};
+
+    #[test]
+    fn test_journal_path() {
+        assert_eq!(
+            journal_path(&PathBuf::from("data/comments.jsonl")),
+            PathBuf::from("data/comments.jsonl.journal")
+        );
+    }
+
+    #[test]
+    fn test_last_position_ignores_truncated_entry() {
+        let journal = concat!(
+            "{\"line_number\":128,\"offset\":4096}\n",
+            "{\"line_number\":256,\"offset\":8192}\n",
+            "{\"line_number\":384,\"off",
+        );
+
+        assert_eq!(
+            last_position(Cursor::new(journal)).unwrap(),
+            (
+                Position {
+                    line_number: 256,
+                    offset: 8192
+                },
+                2 * "{\"line_number\":128,\"offset\":4096}\n".len() as u64
+            )
+        );
+        assert_eq!(
+            last_position(Cursor::new("")).unwrap(),
+            (Position::default(), 0)
+        );
+    }
+
+    #[test]
+    fn test_resume_twice_after_truncated_entry() {
+        let input_path =
+            std::env::temp_dir().join(format!("reinfer-journal-{}.jsonl", std::process::id()));
+        let path = journal_path(&input_path);
+        fs::write(
+            &path,
+            concat!(
+                "{\"line_number\":128,\"offset\":4096}\n",
+                "{\"line_number\":256,\"off",
+            ),
+        )
+        .unwrap();
+        let position = |line_number| Position {
+            line_number,
+            offset: line_number as u64 * 32,
+        };
+
+        let (mut journal, resumed) = Journal::open(&input_path, true).unwrap();
+        assert_eq!(resumed.line_number, 128);
+        journal.commit(position(384)).unwrap();
+        drop(journal);
+
+        let (mut journal, resumed) = Journal::open(&input_path, true).unwrap();
+        assert_eq!(resumed, position(384));
+        journal.commit(position(512)).unwrap();
+        drop(journal);
+
+        let (journal, resumed) = Journal::open(&input_path, true).unwrap();
+        assert_eq!(resumed, position(512));
+        journal.finish().unwrap();
+    }
+}
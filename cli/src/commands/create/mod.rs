@@ -1,10 +1,12 @@
 
-mod annotations;
+pub mod annotations;
 mod bucket;
 mod comments;
+mod comments_csv;
 mod dataset;
 mod emails;
 mod integrations;
+mod journal;
 mod project;
 mod quota;
 mod source;
@@ -12,9 +12,16 @@ use reinfer_client::{resources::stream::NewStream, Client, DatasetIdentifier};
 
 use structopt::StructOpt;
 
+use crate::args::ContextDefault;
+
 #[derive(Debug, StructOpt)]
 pub struct CreateStreamsArgs {
-    #[structopt(short = "d", long = "dataset")]
+    #[structopt(
+        short = "d",
+        long = "dataset",
+        env = "REINFER_DATASET",
+        context_default = "dataset"
+    )]
     /// Dataset where the streams should be created
     dataset_id: DatasetIdentifier,
 
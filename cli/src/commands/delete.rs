@@ -10,7 +10,7 @@ use structopt::StructOpt;
 use reinfer_client::{
     resources::project::ForceDeleteProject, BucketIdentifier, Client, CommentId, CommentsIter,
     CommentsIterTimerange, DatasetIdentifier, ProjectName, Source, SourceIdentifier,
-    UserIdentifier,
+    StreamFullName, UserIdentifier,
 };
 
 use crate::progress::{Options as ProgressOptions, Progress};
@@ -101,6 +101,14 @@ pub enum DeleteArgs {
         /// Force deletion of the project, even if it's not empty.
         force: bool,
     },
+
+    #[structopt(name = "stream")]
+    /// Delete a stream
+    Stream {
+        #[structopt(name = "stream")]
+        /// The full stream name `<owner>/<dataset>/<stream>`.
+        stream: StreamFullName,
+    },
 }
 
 pub fn run(delete_args: &DeleteArgs, client: Client) -> Result<()> {
@@ -167,6 +175,12 @@ pub fn run(delete_args: &DeleteArgs, client: Client) -> Result<()> {
                 .context("Operation to delete project has failed.")?;
             log::info!("Deleted project.");
         }
+        DeleteArgs::Stream { stream } => {
+            client
+                .delete_stream(stream)
+                .context("Operation to delete stream has failed.")?;
+            log::info!("Deleted stream.");
+        }
     };
     Ok(())
 }
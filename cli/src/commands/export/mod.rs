@@ -0,0 +1,26 @@
+pub mod project;
+mod taxonomy;
+
+use self::{project::ExportProjectArgs, taxonomy::ExportTaxonomyArgs};
+use anyhow::Result;
+use reinfer_client::Client;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum ExportArgs {
+    #[structopt(name = "project")]
+    /// Export a project and its resources as a manifest which can be passed to `apply`
+    Project(ExportProjectArgs),
+
+    #[structopt(name = "taxonomy")]
+    /// Export the labels and entities of a dataset as a taxonomy which can be passed to
+    /// `update labels`
+    Taxonomy(ExportTaxonomyArgs),
+}
+
+pub fn run(export_args: &ExportArgs, client: Client) -> Result<()> {
+    match export_args {
+        ExportArgs::Project(project_args) => project::export(&client, project_args),
+        ExportArgs::Taxonomy(taxonomy_args) => taxonomy::export(&client, taxonomy_args),
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::{Context, Result};
+use log::{info, warn};
+use reinfer_client::{
+    resources::{integration::Integration, integration::NewIntegration, stream::NewStream},
+    Bucket, BucketType, Client, Dataset, EntityDef, LabelDef, LabelGroup, NewEntityDef,
+    NewLabelDef, NewLabelDefPretrained, NewLabelGroup, Project, ProjectName, Source, Stream,
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+use crate::{
+    args::ContextDefault,
+    commands::apply::manifest::{
+        BucketSpec, DatasetSpec, IntegrationSpec, ProjectManifest, ProjectSpec, SourceSpec,
+    },
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ExportProjectArgs {
+    #[structopt(name = "project", env = "REINFER_PROJECT", context_default = "project")]
+    /// Name of the project to export
+    project: ProjectName,
+
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    /// Path where to write the manifest. If not specified, stdout will be used.
+    path: Option<PathBuf>,
+}
+
+pub fn export(client: &Client, args: &ExportProjectArgs) -> Result<()> {
+    let ExportProjectArgs { project, path } = args;
+
+    let project = client
+        .get_project(project)
+        .context("Operation to get project has failed.")?;
+    let buckets = client
+        .get_buckets()
+        .context("Operation to list buckets has failed.")?;
+    let sources = client
+        .get_sources()
+        .context("Operation to list sources has failed.")?;
+    let mut datasets = Vec::new();
+    for dataset in client
+        .get_datasets()
+        .context("Operation to list datasets has failed.")?
+        .into_iter()
+        .filter(|dataset| dataset.owner.0 == project.name.0)
+    {
+        let streams = client
+            .get_streams(&dataset.full_name())
+            .context("Operation to list streams has failed.")?;
+        datasets.push((dataset, streams));
+    }
+    let integrations = client
+        .get_integrations()
+        .context("Operation to list integrations has failed.")?;
+
+    let manifest = project_manifest(project, &buckets, &sources, datasets, integrations);
+
+    match path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Could not open file for writing `{}`", path.display()))
+                .map(BufWriter::new)?;
+            write_manifest(file, &manifest)?;
+            info!(
+                "Exported project {} to {}",
+                manifest.project.name.0,
+                path.display()
+            );
+            Ok(())
+        }
+        None => write_manifest(io::stdout().lock(), &manifest),
+    }
+}
+
+fn write_manifest(mut writer: impl Write, manifest: &ProjectManifest) -> Result<()> {
+    serde_yaml::to_writer(&mut writer, manifest).context("Could not serialise manifest.")?;
+    writer.flush().context("Failed to flush output.")
+}
+
+/// Build a manifest from everything visible to the current user, keeping only the resources in
+/// the project. Ids are replaced by names so that the manifest can be applied to another cluster.
+fn project_manifest(
+    project: Project,
+    buckets: &[Bucket],
+    sources: &[Source],
+    datasets: Vec<(Dataset, Vec<Stream>)>,
+    integrations: Vec<Integration>,
+) -> ProjectManifest {
+    let owner = project.name.0.as_str();
+
+    let mut buckets: Vec<_> = buckets
+        .iter()
+        .filter(|bucket| bucket.owner.0 == owner)
+        .collect();
+    buckets.sort_unstable_by(|lhs, rhs| lhs.name.0.cmp(&rhs.name.0));
+    let bucket_names: HashMap<_, _> = buckets
+        .iter()
+        .map(|bucket| (&bucket.id, bucket.name.0.as_str()))
+        .collect();
+
+    // Sources in other projects are referred to by their full name.
+    let source_names: HashMap<_, _> = sources
+        .iter()
+        .map(|source| {
+            let name = if source.owner.0 == owner {
+                source.name.0.clone()
+            } else {
+                source.full_name().0
+            };
+            (&source.id, name)
+        })
+        .collect();
+
+    let mut project_sources: Vec<_> = sources
+        .iter()
+        .filter(|source| source.owner.0 == owner)
+        .collect();
+    project_sources.sort_unstable_by(|lhs, rhs| lhs.name.0.cmp(&rhs.name.0));
+    let sources = project_sources
+        .into_iter()
+        .map(|source| {
+            let bucket = source.bucket_id.as_ref().and_then(|bucket_id| {
+                let bucket = bucket_names.get(bucket_id).map(|name| name.to_string());
+                if bucket.is_none() {
+                    warn!(
+                        "Bucket of source `{}` is not in the project, leaving it out.",
+                        source.full_name().0
+                    );
+                }
+                bucket
+            });
+            SourceSpec {
+                name: source.name.0.clone(),
+                title: non_empty(&source.title),
+                description: non_empty(&source.description),
+                language: Some(source.language.clone()),
+                should_translate: Some(source.should_translate),
+                bucket,
+                kind: Some(source.kind.clone()),
+                transform_tag: source.transform_tag.clone(),
+            }
+        })
+        .collect();
+
+    let mut datasets: Vec<_> = datasets
+        .into_iter()
+        .map(|(dataset, streams)| {
+            let sources = dataset
+                .source_ids
+                .iter()
+                .filter_map(|source_id| {
+                    let name = source_names.get(source_id).cloned();
+                    if name.is_none() {
+                        warn!(
+                            "Source `{}` of dataset `{}` is not visible, leaving it out.",
+                            source_id.0,
+                            dataset.full_name().0
+                        );
+                    }
+                    name
+                })
+                .collect();
+            let mut streams: Vec<_> = streams.into_iter().map(new_stream).collect();
+            streams.sort_unstable_by(|lhs, rhs| lhs.name.0.cmp(&rhs.name.0));
+            DatasetSpec {
+                name: dataset.name.0,
+                title: non_empty(&dataset.title),
+                description: non_empty(&dataset.description),
+                sources: Some(sources),
+                has_sentiment: Some(dataset.has_sentiment),
+                model_family: Some(dataset.model_family.0),
+                entity_defs: dataset
+                    .entity_defs
+                    .into_iter()
+                    .map(new_entity_def)
+                    .collect(),
+                label_groups: dataset
+                    .label_groups
+                    .into_iter()
+                    .map(new_label_group)
+                    .collect(),
+                streams,
+            }
+        })
+        .collect();
+    datasets.sort_unstable_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+    let mut integrations: Vec<_> = integrations
+        .into_iter()
+        .filter(|integration| integration.owner.0 == owner)
+        .map(|integration| IntegrationSpec {
+            name: integration.name.0,
+            integration: NewIntegration {
+                title: Some(integration.title),
+                enabled: Some(integration.enabled),
+                configuration: integration.configuration,
+            },
+        })
+        .collect();
+    integrations.sort_unstable_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+
+    ProjectManifest {
+        project: ProjectSpec {
+            name: project.name.clone(),
+            title: non_empty(&project.title),
+            description: non_empty(&project.description),
+            user_ids: Vec::new(),
+        },
+        buckets: buckets
+            .into_iter()
+            .map(|bucket| BucketSpec {
+                name: bucket.name.0.clone(),
+                bucket_type: BucketType::Emails,
+                title: None,
+            })
+            .collect(),
+        sources,
+        datasets,
+        integrations,
+    }
+}
+
+pub fn new_label_group(label_group: LabelGroup) -> NewLabelGroup {
+    NewLabelGroup {
+        name: label_group.name,
+        label_defs: label_group
+            .label_defs
+            .into_iter()
+            .map(new_label_def)
+            .collect(),
+    }
+}
+
+pub fn new_entity_def(entity_def: EntityDef) -> NewEntityDef {
+    NewEntityDef {
+        inherits_from: entity_def.inherits_from,
+        name: entity_def.name,
+        title: entity_def.title,
+        trainable: entity_def.trainable,
+    }
+}
+
+pub fn new_label_def(label_def: LabelDef) -> NewLabelDef {
+    NewLabelDef {
+        description: non_empty(&label_def.description),
+        external_id: label_def.external_id,
+        pretrained: label_def
+            .pretrained
+            .map(|pretrained| NewLabelDefPretrained {
+                id: pretrained.id,
+                name: Some(pretrained.name),
+            }),
+        title: non_empty(&label_def.title),
+        moon_form: label_def.moon_form,
+        name: label_def.name,
+    }
+}
+
+pub fn new_stream(stream: Stream) -> NewStream {
+    NewStream {
+        name: stream.name,
+        title: non_empty(&stream.title),
+        description: non_empty(&stream.description),
+        comment_filter: Some(stream.comment_filter),
+        model: stream.model,
+        label_filter: stream.label_filter,
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::project_manifest;
+    use serde_json::json;
+
+    #[test]
+    fn test_project_manifest() {
+        let project = serde_json::from_value(json!({
+            "name": "acme",
+            "title": "Acme",
+            "description": "",
+            "created_at": "2023-01-02T03:04:05Z",
+            "updated_at": "2023-01-02T03:04:05Z"
+        }))
+        .unwrap();
+        let buckets = serde_json::from_value::<Vec<_>>(json!([
+            {"id": "b1", "name": "mailbox", "owner": "acme", "created_at": "2023-01-02T03:04:05Z"},
+            {"id": "b2", "name": "mailbox", "owner": "other", "created_at": "2023-01-02T03:04:05Z"}
+        ]))
+        .unwrap();
+        let source = |id: &str, owner: &str, name: &str| {
+            json!({
+                "id": id, "owner": owner, "name": name, "title": "", "description": "",
+                "language": "en", "should_translate": false, "bucket_id": "b1",
+                "created_at": "2023-01-02T03:04:05Z", "updated_at": "2023-01-02T03:04:05Z",
+                "_kind": "unknown"
+            })
+        };
+        let sources = serde_json::from_value::<Vec<_>>(json!([
+            source("s2", "acme", "support"),
+            source("s1", "other", "shared"),
+        ]))
+        .unwrap();
+        let dataset = serde_json::from_value(json!({
+            "id": "d1", "name": "triage", "owner": "acme", "title": "Triage", "description": "",
+            "created": "2023-01-02T03:04:05Z", "last_modified": "2023-01-02T03:04:05Z",
+            "model_family": "english", "source_ids": ["s1", "s2", "s3"], "has_sentiment": false,
+            "entity_defs": [], "label_defs": [],
+            "label_groups": [{"name": "default", "label_defs": [{"name": "Billing"}]}]
+        }))
+        .unwrap();
+
+        let manifest =
+            project_manifest(project, &buckets, &sources, vec![(dataset, vec![])], vec![]);
+
+        assert_eq!(
+            serde_json::to_value(&manifest).unwrap(),
+            json!({
+                "project": {"name": "acme", "title": "Acme"},
+                "buckets": [{"name": "mailbox", "bucket_type": "emails"}],
+                "sources": [{
+                    "name": "support", "language": "en", "should_translate": false,
+                    "bucket": "mailbox", "kind": "unknown"
+                }],
+                "datasets": [{
+                    "name": "triage", "title": "Triage", "sources": ["other/shared", "support"],
+                    "has_sentiment": false, "model_family": "english",
+                    "label_groups": [{"name": "default", "label_defs": [{"name": "Billing"}]}]
+                }]
+            })
+        );
+    }
+}
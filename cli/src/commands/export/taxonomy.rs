@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use log::info;
+use reinfer_client::{Client, DatasetIdentifier};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+use super::project::{new_entity_def, new_label_def};
+use crate::{
+    args::ContextDefault,
+    commands::update::labels::{sorted_label_groups, Taxonomy},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ExportTaxonomyArgs {
+    #[structopt(name = "dataset", env = "REINFER_DATASET", context_default = "dataset")]
+    /// Name or id of the dataset to export
+    dataset: DatasetIdentifier,
+
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    /// Path where to write the taxonomy. If not specified, stdout will be used.
+    path: Option<PathBuf>,
+}
+
+pub fn export(client: &Client, args: &ExportTaxonomyArgs) -> Result<()> {
+    let ExportTaxonomyArgs { dataset, path } = args;
+
+    let dataset = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?;
+    let dataset_name = dataset.full_name();
+    let mut entity_defs: Vec<_> = dataset
+        .entity_defs
+        .into_iter()
+        .map(new_entity_def)
+        .collect();
+    entity_defs.sort_by(|lhs, rhs| lhs.name.0.cmp(&rhs.name.0));
+    let taxonomy = Taxonomy {
+        label_groups: sorted_label_groups(dataset.label_groups.into_iter().map(|group| {
+            (
+                group.name,
+                group.label_defs.into_iter().map(new_label_def).collect(),
+            )
+        })),
+        entity_defs: Some(entity_defs),
+    };
+
+    match path {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Could not open file for writing `{}`", path.display()))
+                .map(BufWriter::new)?;
+            write_taxonomy(file, &taxonomy)?;
+            info!(
+                "Exported the taxonomy of {} to {}",
+                dataset_name.0,
+                path.display()
+            );
+            Ok(())
+        }
+        None => write_taxonomy(io::stdout().lock(), &taxonomy),
+    }
+}
+
+fn write_taxonomy(mut writer: impl Write, taxonomy: &Taxonomy) -> Result<()> {
+    serde_yaml::to_writer(&mut writer, taxonomy).context("Could not serialise taxonomy.")?;
+    writer.flush().context("Failed to flush output.")
+}
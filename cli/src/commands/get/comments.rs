@@ -9,8 +9,8 @@ use regex::Regex;
 use reinfer_client::{
     resources::{
         comment::{
-            CommentTimestampFilter, MessagesFilter, PredictedLabelName, PropertyFilter,
-            ReviewedFilterEnum, UserPropertiesFilter,
+            CommentTimestampFilter, GetLabellingsAfter, MessagesFilter, PredictedLabelName,
+            PropertyFilter, ReviewedFilterEnum, UserPropertiesFilter,
         },
         dataset::{
             Attribute, AttributeFilter, AttributeFilterEnum, OrderEnum, QueryRequestParams,
@@ -18,16 +18,17 @@ use reinfer_client::{
         },
         source::StatisticsRequestParams as SourceStatisticsRequestParams,
     },
-    AnnotatedComment, Client, CommentFilter, CommentId, CommentsIterTimerange, DatasetFullName,
-    DatasetIdentifier, Entities, HasAnnotations, LabelName, Labelling, ModelVersion,
-    PredictedLabel, PropertyValue, Source, SourceIdentifier, DEFAULT_LABEL_GROUP_NAME,
+    AnnotatedComment, Client, CommentFilter, CommentId, CommentsIterTimerange, Continuation,
+    DatasetFullName, DatasetId, DatasetIdentifier, Entities, HasAnnotations, LabelName, Labelling,
+    ModelVersion, PredictedLabel, PropertyValue, Source, SourceId, SourceIdentifier,
+    DEFAULT_LABEL_GROUP_NAME,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs::File,
-    io::{self, BufWriter, Write},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -36,14 +37,16 @@ use std::{
 };
 use structopt::StructOpt;
 
+use super::comments_csv::{Column, CsvCommentWriter, DEFAULT_COLUMNS};
 use crate::{
+    args::ContextDefault,
     printer::print_resources_as_json,
     progress::{Options as ProgressOptions, Progress},
 };
 
 #[derive(Debug, StructOpt)]
 pub struct GetSingleCommentArgs {
-    #[structopt(long = "source")]
+    #[structopt(long = "source", env = "REINFER_SOURCE", context_default = "source")]
     /// Source name or id
     source: SourceIdentifier,
 
@@ -58,7 +61,7 @@ pub struct GetSingleCommentArgs {
 
 #[derive(Debug, StructOpt)]
 pub struct GetManyCommentsArgs {
-    #[structopt(name = "source")]
+    #[structopt(name = "source", env = "REINFER_SOURCE", context_default = "source")]
     /// Source name or id
     source: SourceIdentifier,
 
@@ -117,11 +120,81 @@ pub struct GetManyCommentsArgs {
     #[structopt(long = "attachment-types")]
     /// The list of attachment types to filter to
     attachment_type_filters: Vec<String>,
+
+    #[structopt(long)]
+    /// Resume an interrupted download into `--file` from its checkpoint file. The same
+    /// filters as the original download must be used.
+    resume: bool,
+
+    #[structopt(long = "format", default_value = "json")]
+    /// Format to write comments in. One of: json, csv
+    format: CommentsFormat,
+
+    #[structopt(long = "columns", use_delimiter = true)]
+    /// Comma separated list of columns to write when using `--format csv`. Besides the fixed
+    /// columns, `user_properties.<name>` selects a user property and `probability.<label>` the
+    /// predicted probability of a label.
+    columns: Vec<Column>,
 }
 
 #[derive(Debug, Deserialize)]
 struct StructExt<T>(pub T);
 
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum CommentsFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl FromStr for CommentsFormat {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        if string == "json" {
+            Ok(CommentsFormat::Json)
+        } else if string == "csv" {
+            Ok(CommentsFormat::Csv)
+        } else {
+            Err(anyhow!("{}", string))
+        }
+    }
+}
+
+/// Where downloaded comments are written to, in the requested format.
+enum CommentWriter<W: Write> {
+    Json(W),
+    Csv(Box<CsvCommentWriter<W>>),
+}
+
+impl<W: Write> CommentWriter<W> {
+    fn new(writer: W, format: CommentsFormat, columns: Vec<Column>) -> Result<Self> {
+        Ok(match format {
+            CommentsFormat::Json => CommentWriter::Json(writer),
+            CommentsFormat::Csv => {
+                CommentWriter::Csv(Box::new(CsvCommentWriter::new(writer, columns)?))
+            }
+        })
+    }
+
+    fn write_comments(
+        &mut self,
+        comments: impl IntoIterator<Item = AnnotatedComment>,
+    ) -> Result<()> {
+        match self {
+            CommentWriter::Json(writer) => print_resources_as_json(comments, writer),
+            CommentWriter::Csv(writer) => writer.write_comments(comments),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            CommentWriter::Json(writer) => writer.flush().context("Failed to flush output."),
+            CommentWriter::Csv(writer) => writer.flush(),
+        }
+    }
+}
+
 impl<T: serde::de::DeserializeOwned> FromStr for StructExt<T> {
     type Err = Error;
 
@@ -256,6 +329,9 @@ pub fn get_many(client: &Client, args: &GetManyCommentsArgs) -> Result<()> {
         interactive_property_filter: interative_property_filter,
         recipients,
         senders,
+        resume,
+        format,
+        columns,
     } = args;
 
     let by_timerange = from_timestamp.is_some() || to_timestamp.is_some();
@@ -310,9 +386,32 @@ pub fn get_many(client: &Client, args: &GetManyCommentsArgs) -> Result<()> {
         bail!("Cannot filter on `senders` or `recipients` when `dataset` is not provided")
     }
 
+    if *resume && path.is_none() {
+        bail!("Cannot resume a download when `file` is not provided.")
+    }
+
+    if !columns.is_empty() && *format != CommentsFormat::Csv {
+        bail!("The `columns` option can only be used with `--format csv`.")
+    }
+
+    // Resuming relies on every comment taking up exactly one line of output, which does not hold
+    // for CSV, where fields may span several lines.
+    if *resume && *format == CommentsFormat::Csv {
+        bail!("Cannot resume a download with `--format csv`.")
+    }
+
+    let checkpoint_path = match format {
+        CommentsFormat::Json => path.as_deref().map(checkpoint_path),
+        CommentsFormat::Csv => None,
+    };
+    let resume_from = match &checkpoint_path {
+        Some(checkpoint_path) if *resume => read_checkpoint(checkpoint_path)?,
+        _ => None,
+    };
+
     let file = match path {
         Some(path) => Some(
-            File::create(path)
+            open_output_file(path, resume_from.as_ref())
                 .with_context(|| format!("Could not open file for writing `{}`", path.display()))
                 .map(BufWriter::new)?,
         ),
@@ -387,6 +486,14 @@ pub fn get_many(client: &Client, args: &GetManyCommentsArgs) -> Result<()> {
         user_properties_filter,
         attachment_property_types_filter,
         messages_filter: Some(messages_filter),
+        checkpoint_path,
+        resume_from,
+        format: *format,
+        columns: if columns.is_empty() {
+            DEFAULT_COLUMNS.to_vec()
+        } else {
+            columns.clone()
+        },
     };
 
     if let Some(file) = file {
@@ -440,6 +547,10 @@ struct CommentDownloadOptions {
     attachment_property_types_filter: Option<AttributeFilter>,
     user_properties_filter: Option<UserPropertiesFilter>,
     messages_filter: Option<MessagesFilter>,
+    checkpoint_path: Option<PathBuf>,
+    resume_from: Option<Checkpoint>,
+    format: CommentsFormat,
+    columns: Vec<Column>,
 }
 
 impl CommentDownloadOptions {
@@ -461,7 +572,7 @@ impl CommentDownloadOptions {
 fn download_comments(
     client: &Client,
     source_identifier: SourceIdentifier,
-    mut writer: impl Write,
+    writer: impl Write,
     options: CommentDownloadOptions,
 ) -> Result<()> {
     let source = client
@@ -512,10 +623,50 @@ fn download_comments(
         ))
     };
 
-    if let Some(dataset_identifier) = &options.dataset_identifier {
-        let dataset = client
-            .get_dataset(dataset_identifier.clone())
-            .context("Operation to get dataset has failed.")?;
+    let dataset = match &options.dataset_identifier {
+        Some(dataset_identifier) => Some(
+            client
+                .get_dataset(dataset_identifier.clone())
+                .context("Operation to get dataset has failed.")?,
+        ),
+        None => None,
+    };
+
+    let mut checkpointer = options.checkpoint_path.clone().map(|path| Checkpointer {
+        path,
+        source_id: source.id.clone(),
+        dataset_id: dataset.as_ref().map(|dataset| dataset.id.clone()),
+    });
+
+    let mut resume_position = None;
+    if let Some(checkpoint) = &options.resume_from {
+        ensure_checkpoint_matches(
+            checkpoint,
+            &source.id,
+            dataset.as_ref().map(|dataset| &dataset.id),
+        )?;
+        if checkpoint.position.is_none() {
+            info!(
+                "Download already completed with {} comments, nothing to resume.",
+                checkpoint.num_downloaded
+            );
+            if let Some(checkpointer) = checkpointer {
+                checkpointer.finish()?;
+            }
+            return Ok(());
+        }
+        info!(
+            "Resuming download after {} comments.",
+            checkpoint.num_downloaded
+        );
+        statistics.add_comments(checkpoint.num_downloaded);
+        statistics.add_annotated(checkpoint.num_annotated);
+        resume_position = checkpoint.position.clone();
+    }
+
+    let mut writer = CommentWriter::new(writer, options.format, options.columns.clone())?;
+
+    if let Some(dataset) = dataset {
         let dataset_name = dataset.full_name();
         let _progress = if options.show_progress {
             Some(make_progress(Some(&dataset_name))?)
@@ -530,7 +681,9 @@ fn download_comments(
                 source,
                 &statistics,
                 options.include_predictions,
-                writer,
+                &mut writer,
+                checkpointer.as_mut(),
+                resume_position,
             )?;
         } else {
             get_comments_from_uids(
@@ -540,8 +693,10 @@ fn download_comments(
                 &statistics,
                 options.include_predictions,
                 options.model_version,
-                writer,
+                &mut writer,
                 &options,
+                checkpointer.as_mut(),
+                resume_position,
             )?;
         }
     } else {
@@ -550,24 +705,43 @@ fn download_comments(
         } else {
             None
         };
-        client
-            .get_comments_iter(&source.full_name(), None, options.timerange)
-            .try_for_each(|page| {
-                let page = page.context("Operation to get comments has failed.")?;
-                statistics.add_comments(page.len());
-                print_resources_as_json(
-                    page.into_iter().map(|comment| AnnotatedComment {
-                        comment,
-                        labelling: None,
-                        entities: None,
-                        thread_properties: None,
-                        moon_forms: None,
-                        label_properties: None,
-                    }),
+        let source_name = source.full_name();
+        let mut comments_iter = match resume_position {
+            Some(CheckpointPosition::Comments(after)) => {
+                client.get_comments_iter_after(&source_name, None, options.timerange.to, after)
+            }
+            Some(_) => bail!("Checkpoint file is not for a download without a dataset."),
+            None => client.get_comments_iter(&source_name, None, options.timerange),
+        };
+        while let Some(page) = comments_iter.next() {
+            let page = page.context("Operation to get comments has failed.")?;
+            statistics.add_comments(page.len());
+            writer.write_comments(page.into_iter().map(|comment| AnnotatedComment {
+                comment,
+                labelling: None,
+                entities: None,
+                thread_properties: None,
+                moon_forms: None,
+                label_properties: None,
+            }))?;
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                checkpointer.save(
                     &mut writer,
-                )
-            })?;
+                    &statistics,
+                    comments_iter
+                        .continuation()
+                        .cloned()
+                        .map(CheckpointPosition::Comments),
+                )?;
+            }
+        }
     }
+
+    writer.flush()?;
+    if let Some(checkpointer) = checkpointer {
+        checkpointer.finish()?;
+    }
+
     log::info!(
         "Successfully downloaded {} comments [{} annotated].",
         statistics.num_downloaded(),
@@ -586,12 +760,20 @@ fn get_comments_from_uids(
     statistics: &Arc<Statistics>,
     include_predictions: bool,
     model_version: Option<u32>,
-    mut writer: impl Write,
+    writer: &mut CommentWriter<impl Write>,
     options: &CommentDownloadOptions,
+    mut checkpointer: Option<&mut Checkpointer>,
+    resume_position: Option<CheckpointPosition>,
 ) -> Result<()> {
+    let continuation = match resume_position {
+        Some(CheckpointPosition::Query(continuation)) => Some(continuation),
+        Some(_) => bail!("Checkpoint file is not for a dataset query download."),
+        None => None,
+    };
+
     let mut params = QueryRequestParams {
         attribute_filters: options.get_attribute_filters(),
-        continuation: None,
+        continuation,
         filter: CommentFilter {
             reviewed: None,
             timestamp: Some(CommentTimestampFilter {
@@ -606,103 +788,276 @@ fn get_comments_from_uids(
         order: OrderEnum::Recent,
     };
 
-    client
-        .get_dataset_query_iter(&dataset_name, &mut params)
-        .try_for_each(|page| {
-            let page = page.context("Operation to get comments has failed.")?;
-            if page.is_empty() {
-                return Ok(());
-            }
+    let mut query_iter = client.get_dataset_query_iter(&dataset_name, &mut params);
+    while let Some(page) = query_iter.next() {
+        let page = page.context("Operation to get comments has failed.")?;
+        if page.is_empty() {
+            continue;
+        }
 
-            statistics.add_comments(page.len());
+        statistics.add_comments(page.len());
 
-            if let Some(model_version) = &model_version {
-                let predictions = client
-                    .get_comment_predictions(
-                        &dataset_name,
-                        &ModelVersion(*model_version),
-                        page.iter().map(|comment| &comment.comment.uid),
-                    )
-                    .context("Operation to get predictions has failed.")?;
-                // since predict-comments endpoint doesn't return some fields,
-                // they are set to None or [] here
-                let comments =
-                    page.into_iter()
-                        .zip(predictions.into_iter())
-                        .map(|(comment, prediction)| AnnotatedComment {
-                            comment: comment.comment,
-                            labelling: Some(vec![Labelling {
-                                group: DEFAULT_LABEL_GROUP_NAME.clone(),
-                                assigned: Vec::new(),
-                                dismissed: Vec::new(),
-                                predicted: prediction.labels.map(|auto_threshold_labels| {
-                                    auto_threshold_labels
-                                        .iter()
-                                        .map(|auto_threshold_label| PredictedLabel {
-                                            name: PredictedLabelName::String(LabelName(
-                                                auto_threshold_label.name.join(" > "),
-                                            )),
-                                            sentiment: None,
-                                            probability: auto_threshold_label.probability,
-                                            auto_thresholds: Some(
-                                                auto_threshold_label.auto_thresholds.to_vec(),
-                                            ),
-                                        })
-                                        .collect()
-                                }),
-                            }]),
-                            entities: Some(Entities {
-                                assigned: Vec::new(),
-                                dismissed: Vec::new(),
-                                predicted: prediction.entities,
+        if let Some(model_version) = &model_version {
+            let predictions = client
+                .get_comment_predictions(
+                    &dataset_name,
+                    &ModelVersion(*model_version),
+                    page.iter().map(|comment| &comment.comment.uid),
+                )
+                .context("Operation to get predictions has failed.")?;
+            // since predict-comments endpoint doesn't return some fields,
+            // they are set to None or [] here
+            let comments =
+                page.into_iter()
+                    .zip(predictions.into_iter())
+                    .map(|(comment, prediction)| AnnotatedComment {
+                        comment: comment.comment,
+                        labelling: Some(vec![Labelling {
+                            group: DEFAULT_LABEL_GROUP_NAME.clone(),
+                            assigned: Vec::new(),
+                            dismissed: Vec::new(),
+                            predicted: prediction.labels.map(|auto_threshold_labels| {
+                                auto_threshold_labels
+                                    .iter()
+                                    .map(|auto_threshold_label| PredictedLabel {
+                                        name: PredictedLabelName::String(LabelName(
+                                            auto_threshold_label.name.join(" > "),
+                                        )),
+                                        sentiment: None,
+                                        probability: auto_threshold_label.probability,
+                                        auto_thresholds: Some(
+                                            auto_threshold_label.auto_thresholds.to_vec(),
+                                        ),
+                                    })
+                                    .collect()
                             }),
-                            thread_properties: None,
-                            moon_forms: None,
-                            label_properties: None,
-                        });
-                print_resources_as_json(comments, &mut writer)
-            } else {
-                let comments = page.into_iter().map(|mut annotated_comment| {
-                    if !include_predictions {
-                        annotated_comment = annotated_comment.without_predictions();
-                    }
-                    if annotated_comment.has_annotations() {
-                        statistics.add_annotated(1);
-                    }
-                    annotated_comment
-                });
-                print_resources_as_json(comments, &mut writer)
-            }
-        })?;
+                        }]),
+                        entities: Some(Entities {
+                            assigned: Vec::new(),
+                            dismissed: Vec::new(),
+                            predicted: prediction.entities,
+                        }),
+                        thread_properties: None,
+                        moon_forms: None,
+                        label_properties: None,
+                    });
+            writer.write_comments(comments)?;
+        } else {
+            let comments = page.into_iter().map(|mut annotated_comment| {
+                if !include_predictions {
+                    annotated_comment = annotated_comment.without_predictions();
+                }
+                if annotated_comment.has_annotations() {
+                    statistics.add_annotated(1);
+                }
+                annotated_comment
+            });
+            writer.write_comments(comments)?;
+        }
+
+        if let Some(checkpointer) = checkpointer.as_deref_mut() {
+            checkpointer.save(
+                writer,
+                statistics,
+                query_iter
+                    .continuation()
+                    .cloned()
+                    .map(CheckpointPosition::Query),
+            )?;
+        }
+    }
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn get_reviewed_comments_in_bulk(
     client: &Client,
     dataset_name: DatasetFullName,
     source: Source,
     statistics: &Arc<Statistics>,
     include_predictions: bool,
-    mut writer: impl Write,
+    writer: &mut CommentWriter<impl Write>,
+    mut checkpointer: Option<&mut Checkpointer>,
+    resume_position: Option<CheckpointPosition>,
 ) -> Result<()> {
-    client
-        .get_labellings_iter(&dataset_name, &source.id, include_predictions, None)
-        .try_for_each(|page| {
-            let page = page.context("Operation to get labellings has failed.")?;
-            statistics.add_comments(page.len());
-            statistics.add_annotated(page.len());
-            let comments = page.into_iter().map(|comment| {
-                if !include_predictions {
-                    comment.without_predictions()
-                } else {
-                    comment
-                }
-            });
-            print_resources_as_json(comments, &mut writer)
+    let mut labellings_iter = match resume_position {
+        Some(CheckpointPosition::Labellings(after)) => client.get_labellings_iter_after(
+            &dataset_name,
+            &source.id,
+            include_predictions,
+            after,
+            None,
+        ),
+        Some(_) => bail!("Checkpoint file is not for a reviewed comments download."),
+        None => client.get_labellings_iter(&dataset_name, &source.id, include_predictions, None),
+    };
+
+    while let Some(page) = labellings_iter.next() {
+        let page = page.context("Operation to get labellings has failed.")?;
+        if page.is_empty() {
+            break;
+        }
+        statistics.add_comments(page.len());
+        statistics.add_annotated(page.len());
+        let comments = page.into_iter().map(|comment| {
+            if !include_predictions {
+                comment.without_predictions()
+            } else {
+                comment
+            }
+        });
+        writer.write_comments(comments)?;
+
+        if let Some(checkpointer) = checkpointer.as_deref_mut() {
+            checkpointer.save(
+                writer,
+                statistics,
+                labellings_iter
+                    .after()
+                    .cloned()
+                    .map(CheckpointPosition::Labellings),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Where to pick up an interrupted download, depending on which API was used to fetch comments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CheckpointPosition {
+    Comments(Continuation),
+    Query(Continuation),
+    Labellings(GetLabellingsAfter),
+}
+
+/// Progress of a download to a file, saved next to the output file after every page.
+///
+/// A checkpoint without a position means the download ran to completion.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    source_id: SourceId,
+    dataset_id: Option<DatasetId>,
+    num_downloaded: usize,
+    num_annotated: usize,
+    position: Option<CheckpointPosition>,
+}
+
+struct Checkpointer {
+    path: PathBuf,
+    source_id: SourceId,
+    dataset_id: Option<DatasetId>,
+}
+
+impl Checkpointer {
+    /// Flush the output and record the position of the next page.
+    ///
+    /// The checkpoint is written to a temporary file and renamed over the previous one, so an
+    /// interruption at any point leaves a checkpoint consistent with the output.
+    fn save(
+        &mut self,
+        writer: &mut CommentWriter<impl Write>,
+        statistics: &Statistics,
+        position: Option<CheckpointPosition>,
+    ) -> Result<()> {
+        writer.flush()?;
+
+        let checkpoint = Checkpoint {
+            source_id: self.source_id.clone(),
+            dataset_id: self.dataset_id.clone(),
+            num_downloaded: statistics.num_downloaded(),
+            num_annotated: statistics.num_annotated(),
+            position,
+        };
+        let temporary_path = self.path.with_extension("checkpoint.tmp");
+        let contents =
+            serde_json::to_vec(&checkpoint).context("Could not serialise checkpoint.")?;
+        fs::write(&temporary_path, contents).with_context(|| {
+            format!(
+                "Could not write checkpoint file `{}`",
+                temporary_path.display()
+            )
         })?;
+        fs::rename(&temporary_path, &self.path)
+            .with_context(|| format!("Could not write checkpoint file `{}`", self.path.display()))
+    }
+
+    fn finish(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => {
+                Err(error).with_context(|| {
+                    format!("Could not remove checkpoint file `{}`", self.path.display())
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_owned();
+    file_name.push(".checkpoint");
+    path.with_file_name(file_name)
+}
+
+fn read_checkpoint(path: &Path) -> Result<Option<Checkpoint>> {
+    if !path.exists() {
+        info!(
+            "No checkpoint file found at `{}`, starting a new download.",
+            path.display()
+        );
+        return Ok(None);
+    }
+    let file = File::open(path)
+        .with_context(|| format!("Could not open checkpoint file `{}`", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map(Some)
+        .with_context(|| format!("Could not parse checkpoint file `{}`", path.display()))
+}
+
+fn ensure_checkpoint_matches(
+    checkpoint: &Checkpoint,
+    source_id: &SourceId,
+    dataset_id: Option<&DatasetId>,
+) -> Result<()> {
+    if &checkpoint.source_id != source_id || checkpoint.dataset_id.as_ref() != dataset_id {
+        bail!("The checkpoint file was created for a different source or dataset.")
+    }
     Ok(())
 }
 
+/// Open the output file, either from scratch or, when resuming, positioned right after the last
+/// comment recorded in the checkpoint. Anything written after the checkpoint is discarded.
+fn open_output_file(path: &Path, resume_from: Option<&Checkpoint>) -> Result<File> {
+    let Some(checkpoint) = resume_from else {
+        return Ok(File::create(path)?);
+    };
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let offset = byte_offset_after_lines(BufReader::new(&mut file), checkpoint.num_downloaded)?;
+    file.set_len(offset)?;
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+fn byte_offset_after_lines(mut reader: impl BufRead, num_lines: usize) -> Result<u64> {
+    let mut offset = 0;
+    let mut line = Vec::new();
+    for line_number in 1..=num_lines {
+        line.clear();
+        let bytes_read = reader.read_until(b'\n', &mut line)?;
+        if line.last() != Some(&b'\n') {
+            bail!(
+                "Output file only contains {} complete comments, but the checkpoint expects {}",
+                line_number - 1,
+                num_lines
+            );
+        }
+        offset += bytes_read as u64;
+    }
+    Ok(offset)
+}
+
 #[derive(Debug)]
 pub struct Statistics {
     downloaded: AtomicUsize,
@@ -765,4 +1120,26 @@ fn get_comments_progress_bar(
         Some(total_bytes),
         ProgressOptions { bytes_units: false },
     )
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::{byte_offset_after_lines, checkpoint_path};
+    use std::{io::Cursor, path::PathBuf};
+
+    #[test]
+    fn test_checkpoint_path() {
+        assert_eq!(
+            checkpoint_path(&PathBuf::from("exports/comments.jsonl")),
+            PathBuf::from("exports/comments.jsonl.checkpoint")
+        );
+    }
+
+    #[test]
+    fn test_byte_offset_after_lines() {
+        let output = "{\"a\":1}\n{\"b\":2}\n{\"c\":";
+
+        assert_eq!(byte_offset_after_lines(Cursor::new(output), 0).unwrap(), 0);
+        assert_eq!(byte_offset_after_lines(Cursor::new(output), 2).unwrap(), 16);
+        assert!(byte_offset_after_lines(Cursor::new(output), 3).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Flattening of annotated comments into CSV rows, one row per comment.
+
+use anyhow::{anyhow, Context, Error, Result};
+use reinfer_client::{
+    resources::comment::{get_default_labelling_group, PredictedLabelName},
+    AnnotatedComment, Entity, Message, PropertyValue,
+};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    io::Write,
+    str::FromStr,
+};
+
+/// Separator between the values of columns which hold several values, e.g. labels.
+const VALUE_SEPARATOR: &str = "; ";
+
+/// Separator between the values taken from each message of a comment.
+const MESSAGE_SEPARATOR: &str = "\n";
+
+const USER_PROPERTY_PREFIX: &str = "user_properties.";
+const PROBABILITY_PREFIX: &str = "probability.";
+
+/// Columns written when `--columns` is not specified.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::Uid,
+    Column::ThreadId,
+    Column::Timestamp,
+    Column::Subject,
+    Column::Body,
+    Column::From,
+    Column::To,
+    Column::Cc,
+    Column::ReviewedLabels,
+    Column::DismissedLabels,
+    Column::PredictedLabels,
+    Column::Entities,
+    Column::PredictedEntities,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Uid,
+    ThreadId,
+    Timestamp,
+    CreatedAt,
+    Subject,
+    Body,
+    From,
+    To,
+    Cc,
+    Bcc,
+    SentAt,
+    Attachments,
+    UserProperty(String),
+    ReviewedLabels,
+    DismissedLabels,
+    PredictedLabels,
+    Probability(String),
+    Entities,
+    PredictedEntities,
+}
+
+impl FromStr for Column {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        Ok(match string {
+            "id" => Column::Id,
+            "uid" => Column::Uid,
+            "thread_id" => Column::ThreadId,
+            "timestamp" => Column::Timestamp,
+            "created_at" => Column::CreatedAt,
+            "subject" => Column::Subject,
+            "body" => Column::Body,
+            "from" => Column::From,
+            "to" => Column::To,
+            "cc" => Column::Cc,
+            "bcc" => Column::Bcc,
+            "sent_at" => Column::SentAt,
+            "attachments" => Column::Attachments,
+            "reviewed_labels" => Column::ReviewedLabels,
+            "dismissed_labels" => Column::DismissedLabels,
+            "predicted_labels" => Column::PredictedLabels,
+            "entities" => Column::Entities,
+            "predicted_entities" => Column::PredictedEntities,
+            _ => {
+                if let Some(name) = string.strip_prefix(USER_PROPERTY_PREFIX) {
+                    Column::UserProperty(name.to_owned())
+                } else if let Some(label) = string.strip_prefix(PROBABILITY_PREFIX) {
+                    Column::Probability(label.to_owned())
+                } else {
+                    return Err(anyhow!(
+                        "unknown column `{}`, expected one of: id, uid, thread_id, timestamp, \
+                         created_at, subject, body, from, to, cc, bcc, sent_at, attachments, \
+                         reviewed_labels, dismissed_labels, predicted_labels, entities, \
+                         predicted_entities, {}<name>, {}<label>",
+                        string,
+                        USER_PROPERTY_PREFIX,
+                        PROBABILITY_PREFIX
+                    ));
+                }
+            }
+        })
+    }
+}
+
+impl Display for Column {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Column::Id => write!(formatter, "id"),
+            Column::Uid => write!(formatter, "uid"),
+            Column::ThreadId => write!(formatter, "thread_id"),
+            Column::Timestamp => write!(formatter, "timestamp"),
+            Column::CreatedAt => write!(formatter, "created_at"),
+            Column::Subject => write!(formatter, "subject"),
+            Column::Body => write!(formatter, "body"),
+            Column::From => write!(formatter, "from"),
+            Column::To => write!(formatter, "to"),
+            Column::Cc => write!(formatter, "cc"),
+            Column::Bcc => write!(formatter, "bcc"),
+            Column::SentAt => write!(formatter, "sent_at"),
+            Column::Attachments => write!(formatter, "attachments"),
+            Column::UserProperty(name) => write!(formatter, "{USER_PROPERTY_PREFIX}{name}"),
+            Column::ReviewedLabels => write!(formatter, "reviewed_labels"),
+            Column::DismissedLabels => write!(formatter, "dismissed_labels"),
+            Column::PredictedLabels => write!(formatter, "predicted_labels"),
+            Column::Probability(label) => write!(formatter, "{PROBABILITY_PREFIX}{label}"),
+            Column::Entities => write!(formatter, "entities"),
+            Column::PredictedEntities => write!(formatter, "predicted_entities"),
+        }
+    }
+}
+
+impl Column {
+    fn value(&self, annotated_comment: &AnnotatedComment) -> String {
+        let comment = &annotated_comment.comment;
+        let labelling = get_default_labelling_group(&annotated_comment.labelling);
+        match self {
+            Column::Id => comment.id.0.clone(),
+            Column::Uid => comment.uid.0.clone(),
+            Column::ThreadId => comment
+                .thread_id
+                .as_ref()
+                .map(|thread_id| thread_id.0.clone())
+                .unwrap_or_default(),
+            Column::Timestamp => comment.timestamp.to_rfc3339(),
+            Column::CreatedAt => comment.created_at.to_rfc3339(),
+            Column::Subject => join_messages(&comment.messages, |message| {
+                message.subject.as_ref().map(|subject| subject.text.clone())
+            }),
+            Column::Body => {
+                join_messages(&comment.messages, |message| Some(message.body.text.clone()))
+            }
+            Column::From => join_messages(&comment.messages, |message| message.from.clone()),
+            Column::To => join_messages(&comment.messages, |message| {
+                message.to.as_ref().map(|to| to.join(VALUE_SEPARATOR))
+            }),
+            Column::Cc => join_messages(&comment.messages, |message| {
+                message.cc.as_ref().map(|cc| cc.join(VALUE_SEPARATOR))
+            }),
+            Column::Bcc => join_messages(&comment.messages, |message| {
+                message.bcc.as_ref().map(|bcc| bcc.join(VALUE_SEPARATOR))
+            }),
+            Column::SentAt => join_messages(&comment.messages, |message| {
+                message.sent_at.map(|sent_at| sent_at.to_rfc3339())
+            }),
+            Column::Attachments => join_values(
+                comment
+                    .attachments
+                    .iter()
+                    .map(|attachment| attachment.name.clone()),
+            ),
+            Column::UserProperty(name) => match comment.user_properties.get(name) {
+                Some(PropertyValue::String(value)) => value.clone(),
+                Some(PropertyValue::Number(value)) => value.to_string(),
+                None => String::new(),
+            },
+            Column::ReviewedLabels => join_values(
+                labelling
+                    .iter()
+                    .flat_map(|labelling| &labelling.assigned)
+                    .map(|label| label.name.0.clone()),
+            ),
+            Column::DismissedLabels => join_values(
+                labelling
+                    .iter()
+                    .flat_map(|labelling| &labelling.dismissed)
+                    .map(|label| label.name.0.clone()),
+            ),
+            Column::PredictedLabels => join_values(
+                labelling
+                    .iter()
+                    .flat_map(|labelling| labelling.predicted.iter().flatten())
+                    .map(|label| {
+                        format!(
+                            "{}={}",
+                            predicted_label_name(&label.name),
+                            label.probability
+                        )
+                    }),
+            ),
+            Column::Probability(name) => labelling
+                .iter()
+                .flat_map(|labelling| labelling.predicted.iter().flatten())
+                .find(|label| predicted_label_name(&label.name) == *name)
+                .map(|label| label.probability.to_string())
+                .unwrap_or_default(),
+            Column::Entities => join_entities(
+                annotated_comment
+                    .entities
+                    .iter()
+                    .flat_map(|entities| &entities.assigned),
+            ),
+            Column::PredictedEntities => join_entities(
+                annotated_comment
+                    .entities
+                    .iter()
+                    .flat_map(|entities| entities.predicted.iter().flatten()),
+            ),
+        }
+    }
+}
+
+fn join_messages(messages: &[Message], value: impl Fn(&Message) -> Option<String>) -> String {
+    messages
+        .iter()
+        .filter_map(value)
+        .collect::<Vec<_>>()
+        .join(MESSAGE_SEPARATOR)
+}
+
+fn join_values(values: impl Iterator<Item = String>) -> String {
+    values.collect::<Vec<_>>().join(VALUE_SEPARATOR)
+}
+
+fn join_entities<'a>(entities: impl Iterator<Item = &'a Entity>) -> String {
+    join_values(entities.map(|entity| format!("{}={}", entity.name.0, entity.formatted_value)))
+}
+
+fn predicted_label_name(name: &PredictedLabelName) -> String {
+    match name {
+        PredictedLabelName::Parts(parts) => parts.join(" > "),
+        PredictedLabelName::String(name) => name.0.clone(),
+    }
+}
+
+/// Writes annotated comments as CSV, with a header row followed by one row per comment.
+pub struct CsvCommentWriter<W: Write> {
+    writer: csv::Writer<W>,
+    columns: Vec<Column>,
+}
+
+impl<W: Write> CsvCommentWriter<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> Result<Self> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer
+            .write_record(columns.iter().map(ToString::to_string))
+            .context("Failed to write CSV header.")?;
+        Ok(Self { writer, columns })
+    }
+
+    pub fn write_comments(
+        &mut self,
+        comments: impl IntoIterator<Item = AnnotatedComment>,
+    ) -> Result<()> {
+        for comment in comments {
+            self.writer
+                .write_record(self.columns.iter().map(|column| column.value(&comment)))
+                .context("Failed to write CSV row.")?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().context("Failed to flush output.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Column, CsvCommentWriter, DEFAULT_COLUMNS};
+    use reinfer_client::AnnotatedComment;
+    use serde_json::json;
+
+    #[test]
+    fn test_column_round_trip() {
+        for column in DEFAULT_COLUMNS.iter().cloned().chain([
+            Column::UserProperty("Customer Tier".to_owned()),
+            Column::Probability("Billing > Refund".to_owned()),
+        ]) {
+            assert_eq!(column.to_string().parse::<Column>().unwrap(), column);
+        }
+        assert!("not_a_column".parse::<Column>().is_err());
+    }
+
+    #[test]
+    fn test_write_comments() {
+        let comment: AnnotatedComment = serde_json::from_value(json!({
+            "comment": {
+                "id": "comment-1",
+                "uid": "abc.comment-1",
+                "timestamp": "2023-01-02T03:04:05Z",
+                "created_at": "2023-01-02T03:04:05Z",
+                "messages": [
+                    {"body": {"text": "Hello, world"}, "from": "a@example.com", "to": ["b@example.com", "c@example.com"]},
+                    {"body": {"text": "Reply"}, "from": "b@example.com"}
+                ],
+                "user_properties": {"string:Tier": "Gold", "number:Score": 7}
+            },
+            "labelling": [{
+                "group": "default",
+                "assigned": [{"name": "Billing", "sentiment": "positive"}],
+                "predicted": [
+                    {"name": ["Billing", "Refund"], "probability": 0.75},
+                    {"name": "Billing", "probability": 0.5}
+                ]
+            }],
+            "entities": {
+                "assigned": [{"name": "order", "formatted_value": "123", "spans": []}],
+                "dismissed": []
+            }
+        }))
+        .unwrap();
+
+        let columns = [
+            "id",
+            "body",
+            "from",
+            "to",
+            "user_properties.Tier",
+            "user_properties.Score",
+            "reviewed_labels",
+            "predicted_labels",
+            "probability.Billing > Refund",
+            "entities",
+        ]
+        .iter()
+        .map(|column| column.parse().unwrap())
+        .collect();
+
+        let mut output = Vec::new();
+        let mut writer = CsvCommentWriter::new(&mut output, columns).unwrap();
+        writer.write_comments(vec![comment]).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "id,body,from,to,user_properties.Tier,user_properties.Score,reviewed_labels,",
+                "predicted_labels,probability.Billing > Refund,entities\n",
+                "comment-1,\"Hello, world\nReply\",\"a@example.com\nb@example.com\",",
+                "b@example.com; c@example.com,Gold,7,Billing,",
+                "Billing > Refund=0.75; Billing=0.5,0.75,order=123\n",
+            )
+        );
+    }
+}
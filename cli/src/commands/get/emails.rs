@@ -14,13 +14,14 @@ use std::{
 use structopt::StructOpt;
 
 use crate::{
+    args::ContextDefault,
     printer::print_resources_as_json,
     progress::{Options as ProgressOptions, Progress},
 };
 
 #[derive(Debug, StructOpt)]
 pub struct GetManyEmailsArgs {
-    #[structopt(name = "bucket")]
+    #[structopt(name = "bucket", env = "REINFER_BUCKET", context_default = "bucket")]
     /// Bucket name or id
     bucket: BucketIdentifier,
 
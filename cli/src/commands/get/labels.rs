@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use reinfer_client::{Client, DatasetIdentifier, EntityDef};
+use structopt::StructOpt;
+
+use crate::{
+    args::ContextDefault,
+    printer::{PrintableLabelDef, Printer},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct GetLabelsArgs {
+    #[structopt(name = "dataset", env = "REINFER_DATASET", context_default = "dataset")]
+    /// The dataset name or id
+    dataset: DatasetIdentifier,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct GetEntitiesArgs {
+    #[structopt(name = "dataset", env = "REINFER_DATASET", context_default = "dataset")]
+    /// The dataset name or id
+    dataset: DatasetIdentifier,
+}
+
+pub fn get_labels(client: &Client, args: &GetLabelsArgs, printer: &Printer) -> Result<()> {
+    let GetLabelsArgs { dataset } = args;
+
+    let dataset = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?;
+    let mut label_defs: Vec<_> = dataset
+        .label_groups
+        .into_iter()
+        .flat_map(|group| {
+            let group_name = group.name;
+            group
+                .label_defs
+                .into_iter()
+                .map(move |label_def| PrintableLabelDef {
+                    group: group_name.clone(),
+                    name: label_def.name,
+                    title: label_def.title,
+                    description: label_def.description,
+                })
+        })
+        .collect();
+    label_defs
+        .sort_unstable_by(|lhs, rhs| (&lhs.group.0, &lhs.name.0).cmp(&(&rhs.group.0, &rhs.name.0)));
+    printer.print_resources(&label_defs)
+}
+
+pub fn get_entities(client: &Client, args: &GetEntitiesArgs, printer: &Printer) -> Result<()> {
+    let GetEntitiesArgs { dataset } = args;
+
+    let mut entity_defs: Vec<EntityDef> = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?
+        .entity_defs;
+    entity_defs.sort_unstable_by(|lhs, rhs| lhs.name.0.cmp(&rhs.name.0));
+    printer.print_resources(&entity_defs)
+}
@@ -2,14 +2,17 @@
 mod audit_events;
 mod buckets;
 mod comments;
+mod comments_csv;
 mod datasets;
 mod emails;
 mod integrations;
+mod labels;
 mod projects;
 mod quota;
 mod sources;
-mod streams;
+pub mod streams;
 mod users;
+mod validation;
 
 use anyhow::Result;
 use reinfer_client::Client;
@@ -23,10 +26,12 @@ use self::{
     datasets::GetDatasetsArgs,
     emails::GetManyEmailsArgs,
     integrations::GetIntegrationsArgs,
+    labels::{GetEntitiesArgs, GetLabelsArgs},
     projects::GetProjectsArgs,
     sources::GetSourcesArgs,
     streams::{GetStreamCommentsArgs, GetStreamStatsArgs, GetStreamsArgs},
     users::GetUsersArgs,
+    validation::GetValidationArgs,
 };
 use crate::printer::Printer;
 
@@ -46,12 +51,20 @@ pub enum GetArgs {
 
     #[structopt(name = "comments")]
     /// Download all comments from a source
-    Comments(GetManyCommentsArgs),
+    Comments(Box<GetManyCommentsArgs>),
 
     #[structopt(name = "datasets")]
     /// List the available datasets
     Datasets(GetDatasetsArgs),
 
+    #[structopt(name = "labels")]
+    /// List the label defs of a dataset
+    Labels(GetLabelsArgs),
+
+    #[structopt(name = "entities")]
+    /// List the entity defs of a dataset
+    Entities(GetEntitiesArgs),
+
     #[structopt(name = "projects")]
     /// List the available projects
     Projects(GetProjectsArgs),
@@ -72,6 +85,10 @@ pub enum GetArgs {
     /// Get the validation stats for a given stream
     StreamStats(GetStreamStatsArgs),
 
+    #[structopt(name = "validation")]
+    /// Get the precision-recall curves of the labels of a dataset for a model version
+    Validation(GetValidationArgs),
+
     #[structopt(name = "users")]
     /// List the available users
     Users(GetUsersArgs),
@@ -100,11 +117,14 @@ pub fn run(args: &GetArgs, client: Client, printer: &Printer, pool: &mut Pool) -
         GetArgs::Comment(args) => comments::get_single(&client, args),
         GetArgs::Comments(args) => comments::get_many(&client, args),
         GetArgs::Datasets(args) => datasets::get(&client, args, printer),
+        GetArgs::Labels(args) => labels::get_labels(&client, args, printer),
+        GetArgs::Entities(args) => labels::get_entities(&client, args, printer),
         GetArgs::Projects(args) => projects::get(&client, args, printer),
         GetArgs::Sources(args) => sources::get(&client, args, printer),
         GetArgs::Streams(args) => streams::get(&client, args, printer),
         GetArgs::StreamComments(args) => streams::get_stream_comments(&client, args),
         GetArgs::StreamStats(args) => streams::get_stream_stats(&client, args, printer, pool),
+        GetArgs::Validation(args) => validation::get(&client, args, printer, pool),
         GetArgs::Users(args) => users::get(&client, args, printer),
         GetArgs::CurrentUser => users::get_current_user(&client, printer),
         GetArgs::Quotas => quota::get(&client, printer),
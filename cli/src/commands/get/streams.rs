@@ -20,11 +20,20 @@ use std::{
 };
 use structopt::StructOpt;
 
-use crate::printer::{print_resources_as_json, DisplayTable, Printer};
+use crate::{
+    args::ContextDefault,
+    concurrency::AdaptiveConcurrency,
+    printer::{print_resources_as_json, DisplayTable, Printer},
+};
 
 #[derive(Debug, StructOpt)]
 pub struct GetStreamsArgs {
-    #[structopt(short = "d", long = "dataset")]
+    #[structopt(
+        short = "d",
+        long = "dataset",
+        env = "REINFER_DATASET",
+        context_default = "dataset"
+    )]
     /// The dataset name or id
     dataset: DatasetIdentifier,
 
@@ -97,16 +106,20 @@ pub fn get(client: &Client, args: &GetStreamsArgs, printer: &Printer) -> Result<
 
 #[derive(Serialize)]
 pub struct StreamStat {
-    label_name: LabelName,
-    threshold: NotNan<f64>,
-    precision: NotNan<f64>,
-    recall: NotNan<f64>,
-    compare_to_precision: Option<NotNan<f64>>,
-    compare_to_recall: Option<NotNan<f64>>,
-    maintain_recall_precision: Option<NotNan<f64>>,
-    maintain_recall_threshold: Option<NotNan<f64>>,
-    maintain_precision_recall: Option<NotNan<f64>>,
-    maintain_precision_threshold: Option<NotNan<f64>>,
+    pub label_name: LabelName,
+    pub model_version: ModelVersion,
+    pub threshold: NotNan<f64>,
+    pub precision: NotNan<f64>,
+    pub recall: NotNan<f64>,
+    pub compare_to_model_version: Option<ModelVersion>,
+    pub compare_to_precision: Option<NotNan<f64>>,
+    pub compare_to_recall: Option<NotNan<f64>>,
+    pub compare_to_precision_delta: Option<NotNan<f64>>,
+    pub compare_to_recall_delta: Option<NotNan<f64>>,
+    pub maintain_recall_precision: Option<NotNan<f64>>,
+    pub maintain_recall_threshold: Option<NotNan<f64>>,
+    pub maintain_precision_recall: Option<NotNan<f64>>,
+    pub maintain_precision_threshold: Option<NotNan<f64>>,
 }
 impl DisplayTable for StreamStat {
     fn to_table_headers() -> prettytable::Row {
@@ -163,7 +176,7 @@ impl DisplayTable for StreamStat {
     }
 }
 
-fn red_if_lower_green_otherwise(test: NotNan<f64>, threshold: NotNan<f64>) -> ColoredString {
+pub fn red_if_lower_green_otherwise(test: NotNan<f64>, threshold: NotNan<f64>) -> ColoredString {
     let test_str = format!("{:.3}", test);
 
     let diff = test - threshold;
@@ -176,12 +189,12 @@ fn red_if_lower_green_otherwise(test: NotNan<f64>, threshold: NotNan<f64>) -> Co
 }
 
 #[derive(Default)]
-struct ThresholdAndPrecision {
-    threshold: Option<NotNan<f64>>,
-    precision: Option<NotNan<f64>>,
+pub struct ThresholdAndPrecision {
+    pub threshold: Option<NotNan<f64>>,
+    pub precision: Option<NotNan<f64>>,
 }
 
-fn get_threshold_and_precision_for_recall(
+pub fn get_threshold_and_precision_for_recall(
     recall: NotNan<f64>,
     label_name: &LabelName,
     label_validation: &LabelValidation,
@@ -203,12 +216,12 @@ fn get_threshold_and_precision_for_recall(
 }
 
 #[derive(Default)]
-struct ThresholdAndRecall {
-    threshold: Option<NotNan<f64>>,
-    recall: Option<NotNan<f64>>,
+pub struct ThresholdAndRecall {
+    pub threshold: Option<NotNan<f64>>,
+    pub recall: Option<NotNan<f64>>,
 }
 
-fn get_threshold_and_recall_for_precision(
+pub fn get_threshold_and_recall_for_precision(
     precision: NotNan<f64>,
     label_name: &LabelName,
     label_validation: &LabelValidation,
@@ -240,12 +253,12 @@ fn get_threshold_and_recall_for_precision(
 }
 
 #[derive(Default)]
-struct PrecisionAndRecall {
-    precision: NotNan<f64>,
-    recall: NotNan<f64>,
+pub struct PrecisionAndRecall {
+    pub precision: NotNan<f64>,
+    pub recall: NotNan<f64>,
 }
 
-fn get_precision_and_recall_for_threshold(
+pub fn get_precision_and_recall_for_threshold(
     threshold: NotNan<f64>,
     label_name: &LabelName,
     label_validation: &LabelValidation,
@@ -275,10 +288,10 @@ fn get_precision_and_recall_for_threshold(
 }
 
 #[derive(Clone)]
-struct CompareConfig {
-    validation: ValidationResponse,
-    dataset_name: DatasetFullName,
-    model_version: ModelVersion,
+pub struct CompareConfig {
+    pub validation: ValidationResponse,
+    pub dataset_name: DatasetFullName,
+    pub model_version: ModelVersion,
 }
 
 impl CompareConfig {
@@ -293,11 +306,11 @@ impl CompareConfig {
     }
 }
 
-fn get_compare_config(
+pub fn get_compare_config(
     client: &Client,
     model_version: &Option<ModelVersion>,
     dataset_name: &Option<DatasetFullName>,
-    stream_name: &StreamFullName,
+    default_dataset_name: &DatasetFullName,
 ) -> Result<Option<CompareConfig>> {
     if model_version.is_none() && dataset_name.is_none() {
         return Ok(None);
@@ -306,7 +319,7 @@ fn get_compare_config(
     let dataset_name = if let Some(dataset_name) = dataset_name {
         dataset_name
     } else {
-        &stream_name.dataset
+        default_dataset_name
     };
 
     let model_version = model_version
@@ -347,11 +360,15 @@ fn get_stream_stat(
 
     let mut stream_stat = StreamStat {
         label_name: label_name.clone(),
+        model_version: model.version.clone(),
         threshold: label_threshold.threshold,
         precision,
         recall,
+        compare_to_model_version: None,
         compare_to_precision: None,
         compare_to_recall: None,
+        compare_to_precision_delta: None,
+        compare_to_recall_delta: None,
         maintain_recall_precision: None,
         maintain_recall_threshold: None,
         maintain_precision_recall: None,
@@ -390,8 +407,13 @@ fn get_stream_stat(
             )
             .unwrap_or_default();
 
+            stream_stat.compare_to_model_version = Some(compare_config.model_version.clone());
             stream_stat.compare_to_precision = Some(same_threshold_precision_and_recall.precision);
             stream_stat.compare_to_recall = Some(same_threshold_precision_and_recall.recall);
+            stream_stat.compare_to_precision_delta =
+                Some(same_threshold_precision_and_recall.precision - precision);
+            stream_stat.compare_to_recall_delta =
+                Some(same_threshold_precision_and_recall.recall - recall);
             stream_stat.maintain_recall_precision =
                 maintain_recall_threshold_and_precision.precision;
             stream_stat.maintain_recall_threshold =
@@ -404,38 +426,19 @@ fn get_stream_stat(
     Ok(stream_stat)
 }
 
-pub fn get_stream_stats(
+/// The precision and recall of a stream model at each of its label thresholds, optionally
+/// compared to another model version or dataset. Sorted by label name.
+pub fn stream_stats(
     client: &Client,
-    args: &GetStreamStatsArgs,
-    printer: &Printer,
+    stream_full_name: &StreamFullName,
+    model: &StreamModel,
+    compare_config: &Option<CompareConfig>,
     pool: &mut Pool,
-) -> Result<()> {
-    let GetStreamStatsArgs {
-        stream_full_name,
-        compare_to_model_version,
-        compare_to_dataset,
-    } = args;
-
-    if compare_to_dataset.is_some() && compare_to_model_version.is_none() {
-        return Err(anyhow!(
-            "You cannot provide `compare_to_dataset` without `compare_to_model_version`"
-        ));
-    }
-
-    info!("Getting Stream");
-    let stream = client.get_stream(stream_full_name)?;
-    let model = stream.model.context("No model associated with stream.")?;
-
-    let compare_config = get_compare_config(
-        client,
-        compare_to_model_version,
-        compare_to_dataset,
-        stream_full_name,
-    )?;
-
+) -> Result<Vec<StreamStat>> {
     let mut stream_stats = Vec::new();
 
     let (sender, receiver) = channel();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
 
     pool.scoped(|scope| {
         for label_threshold in &model.label_thresholds {
@@ -444,17 +447,19 @@ pub fn get_stream_stats(
                 continue;
             }
             let sender = sender.clone();
-            let model = model.clone();
             let compare_config = compare_config.clone();
+            let concurrency = &concurrency;
 
             scope.execute(move || {
-                let result = get_stream_stat(
-                    label_threshold,
-                    stream_full_name,
-                    &model,
-                    &compare_config,
-                    client,
-                );
+                let result = concurrency.run(|| {
+                    get_stream_stat(
+                        label_threshold,
+                        stream_full_name,
+                        model,
+                        &compare_config,
+                        client,
+                    )
+                });
                 sender.send(result).expect("Could not send result");
             });
         }
@@ -469,7 +474,39 @@ pub fn get_stream_stats(
     }
 
     stream_stats.sort_by(|a, b| a.label_name.0.cmp(&b.label_name.0));
+    Ok(stream_stats)
+}
+
+pub fn get_stream_stats(
+    client: &Client,
+    args: &GetStreamStatsArgs,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
+    let GetStreamStatsArgs {
+        stream_full_name,
+        compare_to_model_version,
+        compare_to_dataset,
+    } = args;
+
+    if compare_to_dataset.is_some() && compare_to_model_version.is_none() {
+        return Err(anyhow!(
+            "You cannot provide `compare_to_dataset` without `compare_to_model_version`"
+        ));
+    }
+
+    info!("Getting Stream");
+    let stream = client.get_stream(stream_full_name)?;
+    let model = stream.model.context("No model associated with stream.")?;
+
+    let compare_config = get_compare_config(
+        client,
+        compare_to_model_version,
+        compare_to_dataset,
+        &stream_full_name.dataset,
+    )?;
 
+    let stream_stats = stream_stats(client, stream_full_name, &model, &compare_config, pool)?;
     printer.print_resources(&stream_stats)?;
     Ok(())
 }
@@ -0,0 +1,342 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use log::info;
+use ordered_float::NotNan;
+use prettytable::row;
+use reinfer_client::{
+    resources::validation::LabelValidation, Client, DatasetFullName, DatasetIdentifier, LabelName,
+    ModelVersion,
+};
+use scoped_threadpool::Pool;
+use serde::Serialize;
+use std::sync::mpsc::channel;
+use structopt::StructOpt;
+
+use crate::{
+    args::ContextDefault,
+    commands::get::streams::{
+        get_compare_config, get_precision_and_recall_for_threshold, red_if_lower_green_otherwise,
+        CompareConfig,
+    },
+    concurrency::AdaptiveConcurrency,
+    printer::{DisplayTable, Printer},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct GetValidationArgs {
+    #[structopt(name = "dataset", env = "REINFER_DATASET", context_default = "dataset")]
+    /// The dataset name or id
+    dataset: DatasetIdentifier,
+
+    #[structopt(short = "v", long = "model-version")]
+    /// The model version to get the validation of
+    model_version: ModelVersion,
+
+    #[structopt(short = "l", long = "label", number_of_values = 1)]
+    /// Only get the validation of these labels, instead of every label of the default group
+    labels: Vec<String>,
+
+    #[structopt(long = "compare-version")]
+    /// The model version to compare the precision and recall at each threshold with
+    compare_to_model_version: Option<ModelVersion>,
+
+    #[structopt(long = "compare-dataset", requires = "compare-version")]
+    /// The dataset to compare with, if not the same one
+    compare_to_dataset: Option<DatasetFullName>,
+}
+
+/// A point of the precision-recall curve of a label, optionally compared with the same threshold
+/// under another model version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValidationPoint {
+    label_name: LabelName,
+    model_version: ModelVersion,
+    threshold: NotNan<f64>,
+    precision: NotNan<f64>,
+    recall: NotNan<f64>,
+    compare_to_model_version: Option<ModelVersion>,
+    compare_to_precision: Option<NotNan<f64>>,
+    compare_to_recall: Option<NotNan<f64>>,
+    compare_to_precision_delta: Option<NotNan<f64>>,
+    compare_to_recall_delta: Option<NotNan<f64>>,
+}
+
+impl DisplayTable for ValidationPoint {
+    fn to_table_headers() -> prettytable::Row {
+        row![
+            "Name",
+            "Threshold (T)",
+            "Precision (P)",
+            "Recall (R)",
+            "P at same T",
+            "R at same T"
+        ]
+    }
+
+    fn to_table_row(&self) -> prettytable::Row {
+        row![
+            self.label_name.0,
+            format!("{:.5}", self.threshold),
+            format!("{:.3}", self.precision),
+            format!("{:.3}", self.recall),
+            if let Some(precision) = self.compare_to_precision {
+                red_if_lower_green_otherwise(precision, self.precision)
+            } else {
+                "none".dimmed()
+            },
+            if let Some(recall) = self.compare_to_recall {
+                red_if_lower_green_otherwise(recall, self.recall)
+            } else {
+                "none".dimmed()
+            }
+        ]
+    }
+}
+
+pub fn get(
+    client: &Client,
+    args: &GetValidationArgs,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
+    let GetValidationArgs {
+        dataset,
+        model_version,
+        labels,
+        compare_to_model_version,
+        compare_to_dataset,
+    } = args;
+
+    let dataset_name = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?
+        .full_name();
+
+    info!("Getting validation for {}", dataset_name.0);
+    let validation = client.get_validation(&dataset_name, model_version)?;
+    let label_defs = &validation
+        .get_default_label_group()
+        .context("Dataset does not have a default label group")?
+        .label_defs;
+    let label_names: Vec<LabelName> = if labels.is_empty() {
+        label_defs
+            .iter()
+            .map(|label_def| label_def.name.clone())
+            .collect()
+    } else {
+        for label_name in labels {
+            if !label_defs
+                .iter()
+                .any(|label_def| label_def.name.0 == *label_name)
+            {
+                bail!(
+                    "Model version {} of dataset `{}` has no label `{}`",
+                    model_version.0,
+                    dataset_name.0,
+                    label_name
+                );
+            }
+        }
+        labels
+            .iter()
+            .map(|label_name| LabelName(label_name.clone()))
+            .collect()
+    };
+
+    let compare_config = get_compare_config(
+        client,
+        compare_to_model_version,
+        compare_to_dataset,
+        &dataset_name,
+    )?;
+
+    let (sender, receiver) = channel();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
+    pool.scoped(|scope| {
+        for label_name in &label_names {
+            let sender = sender.clone();
+            let compare_config = &compare_config;
+            let dataset_name = &dataset_name;
+            let concurrency = &concurrency;
+            scope.execute(move || {
+                let result = concurrency.run(|| {
+                    get_label_validation_points(
+                        client,
+                        label_name,
+                        dataset_name,
+                        model_version,
+                        compare_config,
+                    )
+                });
+                sender.send(result).expect("Could not send result");
+            });
+        }
+    });
+    drop(sender);
+
+    let mut points = Vec::new();
+    for result in receiver.iter() {
+        points.extend(result?);
+    }
+    // Keep the points of each curve in order of increasing threshold.
+    points.sort_by(|a, b| {
+        a.label_name
+            .0
+            .cmp(&b.label_name.0)
+            .then(a.threshold.cmp(&b.threshold))
+    });
+    printer.print_resources(&points)
+}
+
+fn get_label_validation_points(
+    client: &Client,
+    label_name: &LabelName,
+    dataset_name: &DatasetFullName,
+    model_version: &ModelVersion,
+    compare_config: &Option<CompareConfig>,
+) -> Result<Vec<ValidationPoint>> {
+    info!(
+        "Getting label validation for {} in dataset {}",
+        label_name.0, dataset_name.0
+    );
+    let label_validation = client.get_label_validation(label_name, dataset_name, model_version)?;
+
+    let compare_to = match compare_config {
+        Some(compare_config) if compare_config.get_label_def(label_name)?.is_some() => {
+            info!(
+                "Getting label validation for {} in dataset {}",
+                label_name.0, compare_config.dataset_name.0
+            );
+            let compare_to_label_validation = client.get_label_validation(
+                label_name,
+                &compare_config.dataset_name,
+                &compare_config.model_version,
+            )?;
+            Some((&compare_config.model_version, compare_to_label_validation))
+        }
+        _ => None,
+    };
+
+    validation_points(
+        label_name,
+        model_version,
+        &label_validation,
+        compare_to
+            .as_ref()
+            .map(|(model_version, label_validation)| (*model_version, label_validation)),
+    )
+}
+
+fn validation_points(
+    label_name: &LabelName,
+    model_version: &ModelVersion,
+    label_validation: &LabelValidation,
+    compare_to: Option<(&ModelVersion, &LabelValidation)>,
+) -> Result<Vec<ValidationPoint>> {
+    let LabelValidation {
+        thresholds,
+        precisions,
+        recalls,
+    } = label_validation;
+    if thresholds.len() != precisions.len() || thresholds.len() != recalls.len() {
+        bail!(
+            "The validation curve of label `{}` has {} thresholds, {} precisions and {} recalls",
+            label_name.0,
+            thresholds.len(),
+            precisions.len(),
+            recalls.len()
+        );
+    }
+
+    thresholds
+        .iter()
+        .zip(precisions)
+        .zip(recalls)
+        .map(|((&threshold, &precision), &recall)| {
+            let mut point = ValidationPoint {
+                label_name: label_name.clone(),
+                model_version: model_version.clone(),
+                threshold,
+                precision,
+                recall,
+                compare_to_model_version: None,
+                compare_to_precision: None,
+                compare_to_recall: None,
+                compare_to_precision_delta: None,
+                compare_to_recall_delta: None,
+            };
+            if let Some((compare_to_model_version, compare_to_label_validation)) = compare_to {
+                // A curve may not reach down to every threshold of the other one.
+                if let Ok(compare_to) = get_precision_and_recall_for_threshold(
+                    threshold,
+                    label_name,
+                    compare_to_label_validation,
+                ) {
+                    point.compare_to_model_version = Some(compare_to_model_version.clone());
+                    point.compare_to_precision = Some(compare_to.precision);
+                    point.compare_to_recall = Some(compare_to.recall);
+                    point.compare_to_precision_delta = Some(compare_to.precision - precision);
+                    point.compare_to_recall_delta = Some(compare_to.recall - recall);
+                }
+            }
+            Ok(point)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validation_points;
+    use ordered_float::NotNan;
+    use reinfer_client::{resources::validation::LabelValidation, LabelName, ModelVersion};
+
+    fn label_validation(
+        thresholds: &[f64],
+        precisions: &[f64],
+        recalls: &[f64],
+    ) -> LabelValidation {
+        let not_nans = |values: &[f64]| {
+            values
+                .iter()
+                .map(|value| NotNan::new(*value).unwrap())
+                .collect()
+        };
+        LabelValidation {
+            thresholds: not_nans(thresholds),
+            precisions: not_nans(precisions),
+            recalls: not_nans(recalls),
+        }
+    }
+
+    #[test]
+    fn test_validation_points() {
+        let label_name = LabelName("Billing".to_owned());
+        let current = label_validation(&[0.8, 0.5, 0.2], &[0.9, 0.8, 0.6], &[0.3, 0.6, 0.9]);
+        let compare_to = label_validation(&[0.9, 0.6], &[0.95, 0.85], &[0.4, 0.7]);
+
+        let points = validation_points(
+            &label_name,
+            &ModelVersion(3),
+            &current,
+            Some((&ModelVersion(4), &compare_to)),
+        )
+        .unwrap();
+        assert_eq!(points.len(), 3);
+
+        // At 0.8, the compared curve is at its 0.6 threshold point.
+        assert_eq!(points[0].compare_to_model_version, Some(ModelVersion(4)));
+        assert_eq!(
+            points[0].compare_to_precision,
+            Some(NotNan::new(0.85).unwrap())
+        );
+        let recall_delta = points[0].compare_to_recall_delta.unwrap().into_inner();
+        assert!((recall_delta - 0.4).abs() < 1e-9);
+
+        // The compared curve does not reach down to 0.5.
+        assert_eq!(points[1].compare_to_model_version, None);
+        assert_eq!(points[1].compare_to_recall_delta, None);
+
+        let uneven = label_validation(&[0.8, 0.5], &[0.9], &[0.3, 0.6]);
+        assert!(validation_points(&label_name, &ModelVersion(3), &uneven, None).is_err());
+    }
+}
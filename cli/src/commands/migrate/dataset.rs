@@ -0,0 +1,387 @@
+use anyhow::{bail, ensure, Context, Result};
+use log::{info, warn};
+use reinfer_client::{
+    resources::comment::HasAnnotations, AnnotatedComment, Client, Comment, CommentsIterTimerange,
+    DatasetFullName, DatasetIdentifier, NewComment, NewDataset, NewSource, ProjectName, Source,
+    SourceFullName,
+};
+use scoped_threadpool::Pool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use structopt::StructOpt;
+
+use crate::{
+    commands::{
+        create::annotations::{upload_batch_of_annotations, AnnotationStatistic, NewAnnotation},
+        ensure_uip_user_consents_to_ai_unit_charge,
+        export::project::{new_entity_def, new_label_group},
+    },
+    concurrency::AdaptiveConcurrency,
+};
+
+#[derive(Debug, StructOpt)]
+pub struct MigrateDatasetArgs {
+    #[structopt(name = "dataset")]
+    /// Dataset to migrate (name or id), in the context given by `--from-context`
+    dataset: DatasetIdentifier,
+
+    #[structopt(long = "from-context")]
+    /// Context to copy the dataset from
+    from_context: String,
+
+    #[structopt(long = "to-context")]
+    /// Context to copy the dataset to
+    to_context: String,
+
+    #[structopt(long = "to-project")]
+    /// Project in which to create the dataset and its sources. Defaults to the project of the
+    /// dataset being migrated.
+    to_project: Option<ProjectName>,
+
+    #[structopt(long = "batch-size", default_value = "128")]
+    /// Number of comments to batch in a single request.
+    batch_size: usize,
+
+    #[structopt(short = "n", long = "no-charge")]
+    /// Whether to attempt to bypass billing (internal only)
+    no_charge: bool,
+
+    #[structopt(short = "y", long = "yes")]
+    /// Consent to ai unit charge. Suppresses confirmation prompt.
+    yes: bool,
+
+    #[structopt(long)]
+    /// Continue a migration which stopped partway, into the dataset it created. Comments and
+    /// annotations which were already copied are copied again, which leaves them unchanged.
+    resume: bool,
+}
+
+#[derive(Debug, Default)]
+struct Statistics {
+    annotations: AtomicUsize,
+}
+
+impl AnnotationStatistic for Statistics {
+    fn add_annotation(&self) {
+        self.annotations.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+pub fn migrate(
+    client_for_context: impl Fn(&str) -> Result<Client>,
+    args: &MigrateDatasetArgs,
+    pool: &mut Pool,
+) -> Result<()> {
+    ensure!(args.batch_size > 0, "--batch-size must be greater than 0");
+    let from_client = client_for_context(&args.from_context)?;
+    let to_client = client_for_context(&args.to_context)?;
+    if !args.no_charge && !args.yes {
+        ensure_uip_user_consents_to_ai_unit_charge(to_client.base_url())?;
+    }
+
+    let dataset = from_client
+        .get_dataset(args.dataset.clone())
+        .with_context(|| format!("Unable to get dataset {}", args.dataset))?;
+    let owner = args
+        .to_project
+        .as_ref()
+        .map_or_else(|| dataset.owner.0.clone(), |project| project.0.clone());
+    let to_dataset_name = DatasetFullName(format!("{}/{}", owner, dataset.name.0));
+
+    let existing_dataset = to_client
+        .get_datasets()
+        .context("Operation to list datasets has failed.")?
+        .into_iter()
+        .find(|existing| existing.full_name() == to_dataset_name);
+    if existing_dataset.is_some() && !args.resume {
+        bail!(
+            "Dataset `{}` already exists in context `{}`, pass --resume to continue migrating \
+             into it",
+            to_dataset_name.0,
+            args.to_context
+        );
+    }
+
+    let mut source_pairs = Vec::with_capacity(dataset.source_ids.len());
+    for source_id in &dataset.source_ids {
+        let from_source = from_client
+            .get_source(source_id.clone())
+            .with_context(|| format!("Unable to get source {}", source_id.0))?;
+        let to_source = find_or_create_source(&to_client, &from_source, &owner)?;
+        source_pairs.push((from_source, to_source));
+    }
+
+    let entity_defs: Vec<_> = dataset
+        .entity_defs
+        .iter()
+        .cloned()
+        .map(new_entity_def)
+        .collect();
+    let label_groups: Vec<_> = dataset
+        .label_groups
+        .iter()
+        .cloned()
+        .map(new_label_group)
+        .collect();
+    let source_ids: Vec<_> = source_pairs
+        .iter()
+        .map(|(_, to_source)| to_source.id.clone())
+        .collect();
+    match existing_dataset {
+        Some(existing_dataset) => {
+            if let Some((_, to_source)) = source_pairs
+                .iter()
+                .find(|(_, to_source)| !existing_dataset.source_ids.contains(&to_source.id))
+            {
+                bail!(
+                    "Dataset `{}` does not contain source `{}`, so cannot resume migrating \
+                     dataset `{}` into it",
+                    to_dataset_name.0,
+                    to_source.full_name().0,
+                    dataset.full_name().0
+                );
+            }
+            info!("Resuming migration into dataset {}", to_dataset_name.0);
+        }
+        None => {
+            to_client
+                .create_dataset(
+                    &to_dataset_name,
+                    NewDataset {
+                        source_ids: &source_ids,
+                        title: Some(&dataset.title),
+                        description: Some(&dataset.description),
+                        has_sentiment: Some(dataset.has_sentiment),
+                        entity_defs: Some(&entity_defs),
+                        label_defs: None,
+                        label_groups: Some(&label_groups),
+                        model_family: Some(&dataset.model_family.0),
+                        copy_annotations_from: None,
+                    },
+                )
+                .context("Operation to create a dataset has failed.")?;
+            info!("Created dataset {}", to_dataset_name.0);
+        }
+    }
+
+    let statistics = Statistics::default();
+    let concurrency = AdaptiveConcurrency::new(&to_client, pool.thread_count() as usize);
+    source_pairs
+        .iter()
+        .try_for_each(|(from_source, to_source)| -> Result<()> {
+            let num_comments =
+                copy_comments(&from_client, &to_client, from_source, to_source, args)?;
+            info!(
+                "Copied {} comments from source {} to {}",
+                num_comments,
+                from_source.full_name().0,
+                to_source.full_name().0
+            );
+            copy_annotations(
+                &from_client,
+                &to_client,
+                &dataset.full_name(),
+                &to_dataset_name,
+                from_source,
+                to_source,
+                &statistics,
+                pool,
+                &concurrency,
+            )
+        })
+        .with_context(|| {
+            format!(
+                "Migration of dataset {} stopped partway, pass --resume to continue it",
+                to_dataset_name.0
+            )
+        })?;
+    info!(
+        "Successfully migrated dataset {} with {} annotations.",
+        to_dataset_name.0,
+        statistics.annotations.load(Ordering::SeqCst)
+    );
+    Ok(())
+}
+
+/// Reuse the source with the same name in the target project if there is one, so that sources
+/// shared by several datasets are only copied once.
+fn find_or_create_source(to_client: &Client, from_source: &Source, owner: &str) -> Result<Source> {
+    let to_source_name = SourceFullName(format!("{}/{}", owner, from_source.name.0));
+    if let Some(existing) = to_client
+        .get_sources()
+        .context("Operation to list sources has failed.")?
+        .into_iter()
+        .find(|source| source.full_name() == to_source_name)
+    {
+        warn!(
+            "Source {} already exists, comments will be added to it.",
+            to_source_name.0
+        );
+        return Ok(existing);
+    }
+    if from_source.bucket_id.is_some() {
+        warn!(
+            "Source {} is synced from a bucket, which will not be migrated.",
+            from_source.full_name().0
+        );
+    }
+
+    let source = to_client
+        .create_source(
+            &to_source_name,
+            NewSource {
+                title: Some(&from_source.title),
+                description: Some(&from_source.description),
+                language: Some(&from_source.language),
+                should_translate: Some(from_source.should_translate),
+                bucket_id: None,
+                sensitive_properties: None,
+                kind: Some(&from_source.kind),
+                transform_tag: from_source.transform_tag.as_ref(),
+            },
+        )
+        .context("Operation to create a source has failed.")?;
+    info!("Created source {}", to_source_name.0);
+    Ok(source)
+}
+
+fn copy_comments(
+    from_client: &Client,
+    to_client: &Client,
+    from_source: &Source,
+    to_source: &Source,
+    args: &MigrateDatasetArgs,
+) -> Result<usize> {
+    let from_source_name = from_source.full_name();
+    let to_source_name = to_source.full_name();
+    let mut num_comments = 0;
+    for page in from_client.get_comments_iter(
+        &from_source_name,
+        Some(args.batch_size),
+        CommentsIterTimerange::default(),
+    ) {
+        let comments: Vec<_> = page
+            .context("Operation to get comments has failed.")?
+            .into_iter()
+            .map(new_comment)
+            .collect();
+        to_client
+            .sync_comments(&to_source_name, &comments, args.no_charge)
+            .context("Could not upload batch of comments")?;
+        num_comments += comments.len();
+    }
+    Ok(num_comments)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn copy_annotations(
+    from_client: &Client,
+    to_client: &Client,
+    from_dataset_name: &DatasetFullName,
+    to_dataset_name: &DatasetFullName,
+    from_source: &Source,
+    to_source: &Source,
+    statistics: &Statistics,
+    pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+) -> Result<()> {
+    for page in from_client.get_labellings_iter(from_dataset_name, &from_source.id, false, None) {
+        let mut annotations = Vec::new();
+        let mut moon_forms = Vec::new();
+        for annotated_comment in page.context("Operation to get labellings has failed.")? {
+            let annotation = new_annotation(&annotated_comment)?;
+            if annotation.moon_forms.has_annotations() {
+                moon_forms.push(annotation.clone());
+            }
+            if annotation.labelling.has_annotations() || annotation.entities.has_annotations() {
+                annotations.push(annotation);
+            }
+        }
+
+        for (batch, use_moon_forms) in [(&mut annotations, false), (&mut moon_forms, true)] {
+            if !batch.is_empty() {
+                upload_batch_of_annotations(
+                    batch,
+                    to_client,
+                    to_source,
+                    statistics,
+                    to_dataset_name,
+                    use_moon_forms,
+                    pool,
+                    concurrency,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Annotations are converted the same way as when they are downloaded by `get comments` and
+/// uploaded by `create annotations`.
+fn new_annotation(annotated_comment: &AnnotatedComment) -> Result<NewAnnotation> {
+    serde_json::to_value(annotated_comment)
+        .and_then(serde_json::from_value)
+        .with_context(|| {
+            format!(
+                "Could not convert annotations of comment `{}`",
+                annotated_comment.comment.id.0
+            )
+        })
+}
+
+fn new_comment(comment: Comment) -> NewComment {
+    NewComment {
+        id: comment.id,
+        thread_id: comment.thread_id,
+        timestamp: comment.timestamp,
+        messages: comment.messages,
+        user_properties: comment.user_properties,
+        attachments: comment.attachments,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_annotation;
+    use reinfer_client::AnnotatedComment;
+    use serde_json::json;
+
+    #[test]
+    fn test_new_annotation() {
+        let annotated_comment: AnnotatedComment = serde_json::from_value(json!({
+            "comment": {
+                "id": "comment-1",
+                "uid": "abc.comment-1",
+                "timestamp": "2023-01-02T03:04:05Z",
+                "created_at": "2023-01-02T03:04:05Z",
+                "messages": [{"body": {"text": "Hello"}}]
+            },
+            "labelling": [{
+                "group": "default",
+                "assigned": [{"name": "Billing", "sentiment": "positive"}],
+                "dismissed": [{"name": "Refund", "sentiment": "negative"}]
+            }],
+            "entities": {
+                "assigned": [{"name": "order", "formatted_value": "123", "spans": []}],
+                "dismissed": []
+            }
+        }))
+        .unwrap();
+
+        let annotation = new_annotation(&annotated_comment).unwrap();
+
+        assert_eq!(annotation.comment.id.0, "comment-1");
+        assert_eq!(
+            serde_json::to_value(&annotation.labelling).unwrap(),
+            json!([{
+                "group": "default",
+                "assigned": [{"name": "Billing", "sentiment": "positive"}],
+                "dismissed": [{"name": "Refund", "sentiment": "negative"}]
+            }])
+        );
+        assert_eq!(
+            serde_json::to_value(&annotation.entities).unwrap(),
+            json!({"assigned": [{"name": "order", "formatted_value": "123", "spans": []}]})
+        );
+        assert!(annotation.moon_forms.is_none());
+    }
+}
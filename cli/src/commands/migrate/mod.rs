@@ -0,0 +1,27 @@
+mod dataset;
+
+use self::dataset::MigrateDatasetArgs;
+use anyhow::Result;
+use reinfer_client::Client;
+use scoped_threadpool::Pool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum MigrateArgs {
+    #[structopt(name = "dataset")]
+    /// Copy a dataset, its sources and their comments and annotations to another context
+    Dataset(MigrateDatasetArgs),
+}
+
+/// Run a migration, creating the client for each context it uses with `client_for_context`.
+pub fn run(
+    migrate_args: &MigrateArgs,
+    client_for_context: impl Fn(&str) -> Result<Client>,
+    pool: &mut Pool,
+) -> Result<()> {
+    match migrate_args {
+        MigrateArgs::Dataset(dataset_args) => {
+            dataset::migrate(client_for_context, dataset_args, pool)
+        }
+    }
+}
@@ -5,11 +5,16 @@ use once_cell::sync::Lazy;
 use reinfer_client::TransformTag;
 use url::Url;
 
+pub mod apply;
+pub mod clone;
 pub mod config;
 pub mod create;
 pub mod delete;
+pub mod export;
 pub mod get;
+pub mod migrate;
 pub mod parse;
+pub mod stream;
 pub mod update;
 
 pub fn ensure_uip_user_consents_to_ai_unit_charge(base_url: &Url) -> Result<()> {
@@ -10,9 +10,13 @@ use std::{
     sync::{mpsc::channel, Arc},
 };
 
-use crate::commands::{
-    ensure_uip_user_consents_to_ai_unit_charge,
-    parse::{get_files_in_directory, get_progress_bar, Statistics},
+use crate::{
+    args::ContextDefault,
+    commands::{
+        ensure_uip_user_consents_to_ai_unit_charge,
+        parse::{get_files_in_directory, get_progress_bar, Statistics},
+    },
+    concurrency::AdaptiveConcurrency,
 };
 use reinfer_client::{resources::email::AttachmentMetadata, BucketIdentifier, Client, NewEmail};
 use structopt::StructOpt;
@@ -26,7 +30,12 @@ pub struct ParseEmlArgs {
     /// Directory containing the emls
     directory: PathBuf,
 
-    #[structopt(short = "b", long = "bucket")]
+    #[structopt(
+        short = "b",
+        long = "bucket",
+        env = "REINFER_BUCKET",
+        context_default = "bucket"
+    )]
     /// Name of the bucket where the emails will be uploaded.
     bucket: BucketIdentifier,
 
@@ -61,6 +70,7 @@ pub fn parse(client: &Client, args: &ParseEmlArgs, pool: &mut Pool) -> Result<()
 
     let mut emails = Vec::new();
     let mut errors = Vec::new();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
 
     let mut send_if_needed = |emails: &mut Vec<NewEmail>, force_send: bool| -> Result<()> {
         let thread_count = pool.thread_count();
@@ -76,13 +86,15 @@ pub fn parse(client: &Client, args: &ParseEmlArgs, pool: &mut Pool) -> Result<()
         pool.scoped(|scope| {
             for chunk in chunks {
                 scope.execute(|| {
-                    let result = upload_batch_of_new_emails(
-                        client,
-                        &bucket.full_name(),
-                        chunk,
-                        *no_charge,
-                        &statistics,
-                    );
+                    let result = concurrency.run(|| {
+                        upload_batch_of_new_emails(
+                            client,
+                            &bucket.full_name(),
+                            chunk,
+                            *no_charge,
+                            &statistics,
+                        )
+                    });
 
                     if let Err(error) = result {
                         error_sender.send(error).expect("Could not send error");
@@ -1,4 +1,5 @@
 use crate::{
+    args::ContextDefault,
     commands::DEFAULT_TRANSFORM_TAG,
     parse::{get_files_in_directory, Statistics},
 };
@@ -55,7 +56,12 @@ pub struct ParseMsgArgs {
     /// Directory containing the msgs
     directory: PathBuf,
 
-    #[structopt(short = "s", long = "source")]
+    #[structopt(
+        short = "s",
+        long = "source",
+        env = "REINFER_SOURCE",
+        context_default = "source"
+    )]
     /// Source name or id
     source: SourceIdentifier,
 
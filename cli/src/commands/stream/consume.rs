@@ -0,0 +1,451 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use reinfer_client::{
+    resources::stream::StreamResult, Client, StreamBatch, StreamException, StreamExceptionMetadata,
+    StreamFullName, StreamSequenceId,
+};
+use reqwest::Url;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+
+use super::offsets::{replay_position, OffsetRecord, OffsetStore};
+
+#[derive(Debug, StructOpt)]
+pub struct ConsumeStreamArgs {
+    #[structopt(name = "stream")]
+    /// The full stream name `<owner>/<dataset>/<stream>`.
+    stream: StreamFullName,
+
+    #[structopt(long = "exec", required_unless = "webhook", conflicts_with = "webhook")]
+    /// Shell command to run for each batch, with the batch as JSON on its stdin. The batch is
+    /// delivered if the command exits successfully.
+    exec: Option<String>,
+
+    #[structopt(long = "webhook")]
+    /// URL to POST each batch to as JSON. The batch is delivered if the response status is 2xx.
+    webhook: Option<Url>,
+
+    #[structopt(long = "size", default_value = "16")]
+    /// The max number of comments to fetch per batch.
+    size: u32,
+
+    #[structopt(long = "poll-interval", default_value = "5")]
+    /// Seconds to wait before polling again when there are no new comments.
+    poll_interval: f64,
+
+    #[structopt(long = "until-empty")]
+    /// Stop once there are no new comments, instead of polling for more.
+    until_empty: bool,
+
+    #[structopt(long = "max-attempts", default_value = "3")]
+    /// Number of times to try delivering a batch, or a comment of a batch which could not be
+    /// delivered, before giving up.
+    max_attempts: u32,
+
+    #[structopt(long = "retry-wait", default_value = "1")]
+    /// Seconds to wait before retrying a failed delivery, doubled after each attempt.
+    retry_wait: f64,
+
+    #[structopt(long = "exception-type", default_value = "Delivery Failed")]
+    /// Type of the stream exceptions created for comments which could not be delivered.
+    exception_type: String,
+
+    #[structopt(long = "offset-store", parse(from_os_str))]
+    /// Path of a JSON lines file where to record every comment processed and every time the
+    /// stream is advanced, along with timestamps.
+    offset_store: Option<PathBuf>,
+
+    #[structopt(long = "replay-from")]
+    /// Reset the stream before consuming it, to replay the comments processed since this
+    /// timestamp according to the offset store. Without an offset store, the stream is reset
+    /// to the comments created since this timestamp.
+    replay_from: Option<DateTime<Utc>>,
+
+    #[structopt(long = "status-interval", default_value = "60")]
+    /// Seconds between reports of the comments delivered and filtered, of the comments in the
+    /// batch being delivered, and of how far behind the stream the consumer is.
+    status_interval: f64,
+}
+
+/// Where the batches of a stream are delivered.
+enum Sink {
+    Exec(String),
+    Webhook {
+        http_client: reqwest::blocking::Client,
+        url: Url,
+    },
+}
+
+impl Sink {
+    fn deliver(&self, body: &[u8]) -> Result<()> {
+        match self {
+            Sink::Exec(command) => {
+                let mut child = shell_command(command)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .with_context(|| format!("Could not run `{command}`"))?;
+                let written = child.stdin.take().expect("stdin is piped").write_all(body);
+                let status = child
+                    .wait()
+                    .with_context(|| format!("Could not wait for `{command}`"))?;
+                if !status.success() {
+                    bail!("`{}` failed with {}", command, status);
+                }
+                // Commands don't have to read the batch, in which case the pipe may be closed
+                // early.
+                match written {
+                    Err(error) if error.kind() != ErrorKind::BrokenPipe => {
+                        Err(error).context("Could not write batch to command")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Sink::Webhook { http_client, url } => {
+                http_client
+                    .post(url.clone())
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.to_vec())
+                    .send()
+                    .and_then(|response| response.error_for_status())
+                    .with_context(|| format!("Could not deliver batch to `{url}`"))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// What is sent to the sink: a whole batch, or a single comment of a batch which could not be
+/// delivered.
+#[derive(Serialize)]
+struct Delivery<'a> {
+    sequence_id: &'a StreamSequenceId,
+    results: &'a [StreamResult],
+}
+
+struct Consumer<'a> {
+    client: &'a Client,
+    stream: &'a StreamFullName,
+    sink: Sink,
+    max_attempts: u32,
+    retry_wait: Duration,
+    exception_type: &'a String,
+    offsets: Option<OffsetStore>,
+}
+
+#[derive(Debug, Default)]
+struct ConsumeStatistics {
+    delivered: usize,
+    filtered: usize,
+    exceptions: usize,
+}
+
+pub fn consume(client: &Client, args: &ConsumeStreamArgs) -> Result<()> {
+    let ConsumeStreamArgs {
+        stream,
+        exec,
+        webhook,
+        size,
+        poll_interval,
+        until_empty,
+        max_attempts,
+        retry_wait,
+        exception_type,
+        offset_store,
+        replay_from,
+        status_interval,
+    } = args;
+
+    let sink = match (exec, webhook) {
+        (Some(command), _) => Sink::Exec(command.clone()),
+        (None, Some(url)) => Sink::Webhook {
+            http_client: reqwest::blocking::Client::new(),
+            url: url.clone(),
+        },
+        (None, None) => return Err(anyhow!("One of --exec or --webhook is required")),
+    };
+    let mut consumer = Consumer {
+        client,
+        stream,
+        sink,
+        max_attempts: (*max_attempts).max(1),
+        retry_wait: Duration::from_secs_f64(*retry_wait),
+        exception_type,
+        offsets: offset_store.as_deref().map(OffsetStore::open).transpose()?,
+    };
+    if let Some(replay_from) = replay_from {
+        consumer.replay(*replay_from)?;
+    }
+
+    let status_interval = Duration::from_secs_f64(*status_interval);
+    let mut last_status = Instant::now();
+    let mut statistics = ConsumeStatistics::default();
+    loop {
+        let batch = client
+            .fetch_stream_comments(stream, *size)
+            .context("Operation to fetch stream comments failed.")?;
+        statistics.filtered += batch.filtered as usize;
+        if last_status.elapsed() >= status_interval {
+            report_status(&statistics, &batch);
+            last_status = Instant::now();
+        }
+
+        if batch.results.is_empty() {
+            if batch.filtered > 0 {
+                consumer.advance(&batch, Vec::new())?;
+                continue;
+            }
+            if *until_empty {
+                break;
+            }
+            thread::sleep(Duration::from_secs_f64(*poll_interval));
+            continue;
+        }
+
+        let failed = consumer.deliver_batch(&batch)?;
+        if !failed.is_empty() {
+            consumer.tag_exceptions(&failed)?;
+        }
+        let failed_uids: HashSet<_> = failed.iter().map(|result| &result.comment.uid).collect();
+        let processed_at = Utc::now();
+        let records = batch
+            .results
+            .iter()
+            .map(|result| {
+                let sequence_id = result.sequence_id.clone();
+                let comment_uid = result.comment.uid.clone();
+                let comment_created_at = result.comment.created_at;
+                if failed_uids.contains(&result.comment.uid) {
+                    OffsetRecord::Exception {
+                        sequence_id,
+                        comment_uid,
+                        comment_created_at,
+                        processed_at,
+                    }
+                } else {
+                    OffsetRecord::Delivered {
+                        sequence_id,
+                        comment_uid,
+                        comment_created_at,
+                        processed_at,
+                    }
+                }
+            })
+            .collect();
+        consumer.advance(&batch, records)?;
+        statistics.delivered += batch.results.len() - failed.len();
+        statistics.exceptions += failed.len();
+        debug!(
+            "Delivered {} comments up to sequence id {}.",
+            batch.results.len() - failed.len(),
+            batch.sequence_id.0
+        );
+    }
+
+    info!(
+        "Delivered {} comments, {} were filtered out and {} were tagged as exceptions.",
+        statistics.delivered, statistics.filtered, statistics.exceptions
+    );
+    Ok(())
+}
+
+/// Log how many comments have been processed and are in the batch about to be delivered, and how
+/// far behind the stream the consumer is according to the oldest comment of that batch. The
+/// stream doesn't say how many comments are left after the batch.
+fn report_status(statistics: &ConsumeStatistics, batch: &StreamBatch) {
+    let lag = match batch
+        .results
+        .iter()
+        .map(|result| result.comment.created_at)
+        .min()
+    {
+        Some(oldest) => format!("{} behind", format_lag(Utc::now() - oldest)),
+        None => "caught up".to_owned(),
+    };
+    info!(
+        "{} delivered, {} filtered, {} exceptions, {} in batch, {}.",
+        statistics.delivered,
+        statistics.filtered,
+        statistics.exceptions,
+        batch.results.len(),
+        lag
+    );
+}
+
+fn format_lag(lag: chrono::Duration) -> String {
+    let seconds = lag.num_seconds().max(0);
+    match (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    ) {
+        (0, 0, 0, seconds) => format!("{seconds}s"),
+        (0, 0, minutes, seconds) => format!("{minutes}m {seconds}s"),
+        (0, hours, minutes, _) => format!("{hours}h {minutes}m"),
+        (days, hours, _, _) => format!("{days}d {hours}h"),
+    }
+}
+
+impl Consumer<'_> {
+    /// Deliver a batch, falling back to delivering its comments one by one if the batch as a
+    /// whole keeps failing. Returns the comments which could not be delivered.
+    fn deliver_batch<'b>(&self, batch: &'b StreamBatch) -> Result<Vec<&'b StreamResult>> {
+        let delivery = Delivery {
+            sequence_id: &batch.sequence_id,
+            results: &batch.results,
+        };
+        let error = match self.deliver_with_retries(&delivery) {
+            Ok(()) => return Ok(Vec::new()),
+            Err(error) => error,
+        };
+        if batch.results.len() == 1 {
+            warn!("Could not deliver comment: {:#}", error);
+            return Ok(batch.results.iter().collect());
+        }
+
+        warn!(
+            "Could not deliver batch, delivering its comments one by one: {:#}",
+            error
+        );
+        let mut failed = Vec::new();
+        for result in &batch.results {
+            let delivery = Delivery {
+                sequence_id: &result.sequence_id,
+                results: std::slice::from_ref(result),
+            };
+            if let Err(error) = self.deliver_with_retries(&delivery) {
+                warn!(
+                    "Could not deliver comment `{}`: {:#}",
+                    result.comment.uid.0, error
+                );
+                failed.push(result);
+            }
+        }
+        Ok(failed)
+    }
+
+    fn deliver_with_retries(&self, delivery: &Delivery) -> Result<()> {
+        let body = serde_json::to_vec(delivery).context("Could not serialise batch.")?;
+        let mut wait = self.retry_wait;
+        for attempt in 1.. {
+            match self.sink.deliver(&body) {
+                Ok(()) => return Ok(()),
+                Err(error) if attempt < self.max_attempts => {
+                    debug!(
+                        "Delivery attempt {} of {} failed: {:#}",
+                        attempt, self.max_attempts, error
+                    );
+                    thread::sleep(wait);
+                    wait *= 2;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        unreachable!("delivery attempts are unbounded")
+    }
+
+    fn tag_exceptions(&self, failed: &[&StreamResult]) -> Result<()> {
+        let exceptions: Vec<_> = failed
+            .iter()
+            .map(|result| StreamException {
+                metadata: StreamExceptionMetadata {
+                    r#type: self.exception_type,
+                },
+                uid: &result.comment.uid,
+            })
+            .collect();
+        self.client
+            .tag_stream_exceptions(self.stream, &exceptions)
+            .context("Operation to tag stream exceptions has failed.")
+    }
+
+    /// Advance the stream past a batch, after recording its processed comments in the offset
+    /// store.
+    fn advance(&mut self, batch: &StreamBatch, records: Vec<OffsetRecord>) -> Result<()> {
+        if let Some(offsets) = &mut self.offsets {
+            offsets.append(&records)?;
+        }
+        self.client
+            .advance_stream(self.stream, batch.sequence_id.clone())
+            .context("Operation to advance stream for batch failed.")?;
+        if let Some(offsets) = &mut self.offsets {
+            offsets.append(&[OffsetRecord::Advanced {
+                sequence_id: batch.sequence_id.clone(),
+                processed_at: Utc::now(),
+            }])?;
+        }
+        Ok(())
+    }
+
+    fn replay(&mut self, since: DateTime<Utc>) -> Result<()> {
+        let to_comment_created_at = match &self.offsets {
+            Some(offsets) => replay_position(&offsets.read()?, since),
+            None => since,
+        };
+        self.client
+            .reset_stream(self.stream, to_comment_created_at)
+            .context("Operation to reset stream has failed.")?;
+        info!(
+            "Reset stream to replay comments created since {}.",
+            to_comment_created_at
+        );
+        if let Some(offsets) = &mut self.offsets {
+            offsets.append(&[OffsetRecord::Reset {
+                to_comment_created_at,
+                processed_at: Utc::now(),
+            }])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_lag, Sink};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exec_sink() {
+        let sink = Sink::Exec(r#"test "$(cat)" = '{"results":[]}'"#.to_owned());
+        sink.deliver(br#"{"results":[]}"#).unwrap();
+        assert!(sink.deliver(b"{}").is_err());
+
+        // A command which exits without reading a batch larger than the pipe buffer.
+        Sink::Exec("true".to_owned())
+            .deliver(&vec![b' '; 1 << 20])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_format_lag() {
+        assert_eq!(format_lag(chrono::Duration::seconds(-5)), "0s");
+        assert_eq!(format_lag(chrono::Duration::seconds(75)), "1m 15s");
+        assert_eq!(
+            format_lag(chrono::Duration::seconds(3 * 3600 + 125)),
+            "3h 2m"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+mod consume;
+mod offsets;
+pub mod thresholds;
+
+use self::{consume::ConsumeStreamArgs, thresholds::StreamThresholdsArgs};
+use crate::printer::Printer;
+use anyhow::Result;
+use reinfer_client::Client;
+use scoped_threadpool::Pool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub enum StreamArgs {
+    #[structopt(name = "consume")]
+    /// Deliver the comments of a stream to a command or a webhook, advancing the stream once
+    /// they have been delivered
+    Consume(ConsumeStreamArgs),
+
+    #[structopt(name = "thresholds")]
+    /// Recommend the label thresholds of a stream for a model version, given a target precision
+    /// or recall, and optionally update the stream with them
+    Thresholds(StreamThresholdsArgs),
+}
+
+pub fn run(
+    stream_args: &StreamArgs,
+    client: Client,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
+    match stream_args {
+        StreamArgs::Consume(consume_args) => consume::consume(&client, consume_args),
+        StreamArgs::Thresholds(thresholds_args) => {
+            thresholds::recommend(&client, thresholds_args, printer, pool)
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reinfer_client::{CommentUid, StreamSequenceId};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// An event of a stream being consumed, appended to the offset store as a JSON line.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum OffsetRecord {
+    /// A comment was delivered.
+    Delivered {
+        sequence_id: StreamSequenceId,
+        comment_uid: CommentUid,
+        comment_created_at: DateTime<Utc>,
+        processed_at: DateTime<Utc>,
+    },
+    /// A comment could not be delivered, and was tagged as a stream exception instead.
+    Exception {
+        sequence_id: StreamSequenceId,
+        comment_uid: CommentUid,
+        comment_created_at: DateTime<Utc>,
+        processed_at: DateTime<Utc>,
+    },
+    /// The stream was advanced past a batch.
+    Advanced {
+        sequence_id: StreamSequenceId,
+        processed_at: DateTime<Utc>,
+    },
+    /// The stream was reset to replay the comments created after a timestamp.
+    Reset {
+        to_comment_created_at: DateTime<Utc>,
+        processed_at: DateTime<Utc>,
+    },
+}
+
+/// Local, append only record of the comments processed by a stream consumer.
+///
+/// Records are synced to disk before the stream is advanced, so that every comment acknowledged
+/// on the server side can be found in the store.
+pub struct OffsetStore {
+    path: PathBuf,
+    file: File,
+}
+
+impl OffsetStore {
+    /// Open the store for appending, removing any record which was cut short by a crash so that
+    /// new records start on a line of their own.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create directory `{}`", parent.display()))?;
+        }
+        OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .and_then(|mut file| complete_len(&mut file).and_then(|len| file.set_len(len)))
+            .with_context(|| format!("Could not repair offset store `{}`", path.display()))?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Could not open offset store `{}`", path.display()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+        })
+    }
+
+    pub fn append(&mut self, records: &[OffsetRecord]) -> Result<()> {
+        let mut lines = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut lines, record).context("Could not serialise offset.")?;
+            lines.push(b'\n');
+        }
+        self.file
+            .write_all(&lines)
+            .and_then(|()| self.file.sync_data())
+            .with_context(|| format!("Could not write to offset store `{}`", self.path.display()))
+    }
+
+    pub fn read(&self) -> Result<Vec<OffsetRecord>> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Could not open offset store `{}`", self.path.display()))?;
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(index, line)| {
+                let line = line.with_context(|| {
+                    format!("Could not read offset store `{}`", self.path.display())
+                })?;
+                serde_json::from_str(&line).with_context(|| {
+                    format!(
+                        "Could not parse line {} of offset store `{}`",
+                        index + 1,
+                        self.path.display()
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// The length of a file up to the end of its last complete line.
+fn complete_len(file: &mut File) -> io::Result<u64> {
+    const CHUNK_SIZE: u64 = 4096;
+    let mut end = file.metadata()?.len();
+    let mut chunk = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        chunk.resize((end - start) as usize, 0);
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk)?;
+        if let Some(index) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(start + index as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// The creation timestamp to reset a stream to, in order to replay the comments which were
+/// processed since `since`. This is the creation timestamp of the oldest of those comments, or
+/// `since` itself if none were.
+pub fn replay_position(records: &[OffsetRecord], since: DateTime<Utc>) -> DateTime<Utc> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            OffsetRecord::Delivered {
+                comment_created_at,
+                processed_at,
+                ..
+            }
+            | OffsetRecord::Exception {
+                comment_created_at,
+                processed_at,
+                ..
+            } if *processed_at >= since => Some(*comment_created_at),
+            _ => None,
+        })
+        .min()
+        .unwrap_or(since)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{replay_position, OffsetRecord, OffsetStore};
+    use chrono::{DateTime, TimeZone, Utc};
+    use reinfer_client::{CommentUid, StreamSequenceId};
+    use std::{fs::OpenOptions, io::Write};
+
+    fn delivered(
+        uid: &str,
+        created_at: DateTime<Utc>,
+        processed_at: DateTime<Utc>,
+    ) -> OffsetRecord {
+        OffsetRecord::Delivered {
+            sequence_id: StreamSequenceId(format!("seq-{uid}")),
+            comment_uid: CommentUid(uid.to_owned()),
+            comment_created_at: created_at,
+            processed_at,
+        }
+    }
+
+    #[test]
+    fn test_offset_store_and_replay_position() {
+        let at = |hour| Utc.with_ymd_and_hms(2023, 1, 2, hour, 0, 0).unwrap();
+        let records = vec![
+            delivered("s.1", at(1), at(10)),
+            OffsetRecord::Advanced {
+                sequence_id: StreamSequenceId("b1".to_owned()),
+                processed_at: at(10),
+            },
+            delivered("s.3", at(3), at(11)),
+            delivered("s.2", at(2), at(12)),
+        ];
+
+        let path =
+            std::env::temp_dir().join(format!("reinfer-offsets-{}.jsonl", std::process::id()));
+        let mut store = OffsetStore::open(&path).unwrap();
+        store.append(&records[..2]).unwrap();
+        store.append(&records[2..]).unwrap();
+        assert_eq!(store.read().unwrap(), records);
+
+        // A record cut short by a crash is dropped when the store is opened again.
+        {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(b"{\"event\":\"delivered\",\"seq").unwrap();
+        }
+        let mut store = OffsetStore::open(&path).unwrap();
+        store.append(&records[3..]).unwrap();
+        let read = store.read().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, [&records[..], &records[3..]].concat());
+
+        // Replaying from 11:00 must go back to the oldest comment processed since then.
+        assert_eq!(replay_position(&records, at(11)), at(2));
+        assert_eq!(replay_position(&records, at(13)), at(13));
+    }
+}
@@ -0,0 +1,558 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use colored::Colorize;
+use dialoguer::Confirm;
+use log::{info, warn};
+use ordered_float::NotNan;
+use prettytable::row;
+use reinfer_client::{
+    resources::{stream::NewStream, validation::LabelValidation},
+    Client, LabelName, ModelVersion, StreamFullName, UpdateStream,
+};
+use scoped_threadpool::Pool;
+use serde::Serialize;
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc::channel,
+};
+use structopt::StructOpt;
+
+use crate::{
+    commands::{
+        export::project::new_stream,
+        get::streams::{
+            get_precision_and_recall_for_threshold, get_threshold_and_precision_for_recall,
+            get_threshold_and_recall_for_precision, PrecisionAndRecall,
+        },
+    },
+    concurrency::AdaptiveConcurrency,
+    printer::{DisplayTable, Printer},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct StreamThresholdsArgs {
+    #[structopt(name = "stream")]
+    /// The full stream name `<owner>/<dataset>/<stream>`.
+    stream: StreamFullName,
+
+    #[structopt(short = "v", long = "model-version")]
+    /// The model version to recommend thresholds for
+    model_version: ModelVersion,
+
+    #[structopt(
+        long = "precision",
+        conflicts_with = "recall",
+        parse(try_from_str = parse_target_value)
+    )]
+    /// Target precision of every label, picking the threshold with the highest recall which
+    /// reaches it
+    precision: Option<NotNan<f64>>,
+
+    #[structopt(long = "recall", parse(try_from_str = parse_target_value))]
+    /// Target recall of every label, picking the threshold with the highest precision which
+    /// reaches it
+    recall: Option<NotNan<f64>>,
+
+    #[structopt(long = "label-precision", number_of_values = 1)]
+    /// Target precision of a single label, as `<label>=<precision>`
+    label_precisions: Vec<LabelTarget>,
+
+    #[structopt(long = "label-recall", number_of_values = 1)]
+    /// Target recall of a single label, as `<label>=<recall>`
+    label_recalls: Vec<LabelTarget>,
+
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    /// Path where to write the updated stream as JSON, which can be passed to `create streams`
+    path: Option<PathBuf>,
+
+    #[structopt(long)]
+    /// Update the stream with the recommended thresholds and model version
+    apply: bool,
+
+    #[structopt(short = "y", long = "yes")]
+    /// Don't ask for confirmation before updating the stream
+    yes: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Precision,
+    Recall,
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        match string {
+            "precision" => Ok(Self::Precision),
+            "recall" => Ok(Self::Recall),
+            _ => Err(anyhow!(
+                "Expected `precision` or `recall`, got `{}`",
+                string
+            )),
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Precision => write!(formatter, "precision"),
+            Self::Recall => write!(formatter, "recall"),
+        }
+    }
+}
+
+/// The target value of a metric for a label, given as `<label>=<value>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelTarget {
+    label_name: LabelName,
+    value: NotNan<f64>,
+}
+
+impl FromStr for LabelTarget {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        let (label_name, value) = string
+            .rsplit_once('=')
+            .filter(|(label_name, _)| !label_name.trim().is_empty())
+            .ok_or_else(|| anyhow!("Expected a target as `<label>=<value>`, got `{}`", string))?;
+        Ok(Self {
+            label_name: LabelName(label_name.trim().to_owned()),
+            value: parse_target_value(value.trim())?,
+        })
+    }
+}
+
+fn parse_target_value(string: &str) -> Result<NotNan<f64>> {
+    string
+        .parse::<NotNan<f64>>()
+        .ok()
+        .filter(|value| (0.0..=1.0).contains(&value.into_inner()))
+        .ok_or_else(|| anyhow!("Expected a target between 0 and 1, got `{}`", string))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Target {
+    metric: Metric,
+    value: NotNan<f64>,
+}
+
+/// The threshold of a stream label which reaches a target under a model version.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThresholdRecommendation {
+    label_name: LabelName,
+    target: Target,
+    current_threshold: NotNan<f64>,
+    threshold: Option<NotNan<f64>>,
+    precision: Option<NotNan<f64>>,
+    recall: Option<NotNan<f64>>,
+}
+
+impl DisplayTable for ThresholdRecommendation {
+    fn to_table_headers() -> prettytable::Row {
+        row![
+            "Name",
+            "Target",
+            "Current threshold",
+            "Threshold",
+            "Precision",
+            "Recall"
+        ]
+    }
+
+    fn to_table_row(&self) -> prettytable::Row {
+        let format_optional = |value: Option<NotNan<f64>>| match value {
+            Some(value) => format!("{value:.3}").normal(),
+            None => "none".dimmed(),
+        };
+        row![
+            self.label_name.0,
+            format!("{} >= {:.3}", self.target.metric, self.target.value),
+            format!("{:.5}", self.current_threshold),
+            match self.threshold {
+                Some(threshold) => format!("{threshold:.5}").bold(),
+                None => "unreachable".red(),
+            },
+            format_optional(self.precision),
+            format_optional(self.recall)
+        ]
+    }
+}
+
+pub fn recommend(
+    client: &Client,
+    args: &StreamThresholdsArgs,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
+    let StreamThresholdsArgs {
+        stream: stream_name,
+        model_version,
+        precision,
+        recall,
+        label_precisions,
+        label_recalls,
+        path,
+        apply,
+        yes,
+    } = args;
+
+    let default_target = match (precision, recall) {
+        (Some(precision), _) => Some(Target {
+            metric: Metric::Precision,
+            value: *precision,
+        }),
+        (None, Some(recall)) => Some(Target {
+            metric: Metric::Recall,
+            value: *recall,
+        }),
+        (None, None) => None,
+    };
+    if default_target.is_none() && label_precisions.is_empty() && label_recalls.is_empty() {
+        bail!("No target given, pass --precision, --recall, --label-precision or --label-recall");
+    }
+    let label_targets: Vec<(&LabelName, Target)> = label_precisions
+        .iter()
+        .map(|target| (target, Metric::Precision))
+        .chain(label_recalls.iter().map(|target| (target, Metric::Recall)))
+        .map(|(target, metric)| {
+            (
+                &target.label_name,
+                Target {
+                    metric,
+                    value: target.value,
+                },
+            )
+        })
+        .collect();
+
+    let file = match path {
+        Some(path) => Some(
+            File::create(path)
+                .with_context(|| format!("Could not open file for writing `{}`", path.display()))
+                .map(BufWriter::new)?,
+        ),
+        None => None,
+    };
+
+    let stream = client
+        .get_stream(stream_name)
+        .context("Operation to get stream has failed.")?;
+    let mut new_stream = new_stream(stream);
+    let model = new_stream
+        .model
+        .as_ref()
+        .context("Cannot recommend thresholds for a stream without a model.")?;
+
+    // The label filter may be on a label without a threshold of its own, which has to be moved
+    // along with the model version all the same.
+    let stream_thresholds: Vec<(LabelName, NotNan<f64>)> = model
+        .label_thresholds
+        .iter()
+        .map(|label_threshold| {
+            (
+                LabelName(label_threshold.name.join(" > ")),
+                label_threshold.threshold,
+            )
+        })
+        .collect();
+    let filter_threshold = new_stream
+        .label_filter
+        .as_ref()
+        .filter(|label_filter| {
+            !stream_thresholds
+                .iter()
+                .any(|(label_name, _)| *label_name == label_filter.label)
+        })
+        .map(|label_filter| (label_filter.label.clone(), label_filter.threshold));
+    let stream_thresholds: Vec<(LabelName, NotNan<f64>)> = stream_thresholds
+        .into_iter()
+        .chain(filter_threshold)
+        .collect();
+
+    for (label_name, _) in &label_targets {
+        if !stream_thresholds
+            .iter()
+            .any(|(stream_label_name, _)| stream_label_name == *label_name)
+        {
+            bail!(
+                "Stream `{}` has no threshold for label `{}`",
+                stream_name.stream.0,
+                label_name.0
+            );
+        }
+    }
+
+    let targets: Vec<(LabelName, NotNan<f64>, Target)> = stream_thresholds
+        .into_iter()
+        .filter_map(|(label_name, current_threshold)| {
+            let target = label_targets
+                .iter()
+                .find(|(target_label_name, _)| **target_label_name == label_name)
+                .map(|(_, target)| *target)
+                .or(default_target);
+            if target.is_none()
+                && new_stream
+                    .label_filter
+                    .as_ref()
+                    .is_some_and(|label_filter| label_filter.label == label_name)
+            {
+                warn!(
+                    "No target for label `{}` of the label filter, keeping its threshold under \
+                     model version {}",
+                    label_name.0, model_version.0
+                );
+            }
+            Some((label_name, current_threshold, target?))
+        })
+        .collect();
+
+    let (sender, receiver) = channel();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
+    pool.scoped(|scope| {
+        for (label_name, current_threshold, target) in &targets {
+            let sender = sender.clone();
+            let concurrency = &concurrency;
+            scope.execute(move || {
+                let result = concurrency.run(|| {
+                    info!(
+                        "Getting label validation for {} in dataset {}",
+                        label_name.0, stream_name.dataset.0
+                    );
+                    let label_validation = client.get_label_validation(
+                        label_name,
+                        &stream_name.dataset,
+                        model_version,
+                    )?;
+                    recommend_threshold(label_name, *current_threshold, *target, &label_validation)
+                });
+                sender.send(result).expect("Could not send result");
+            });
+        }
+    });
+    drop(sender);
+    let mut recommendations = receiver.iter().collect::<Result<Vec<_>>>()?;
+    recommendations.sort_by(|a, b| a.label_name.0.cmp(&b.label_name.0));
+    printer.print_resources(&recommendations)?;
+
+    for recommendation in &recommendations {
+        if recommendation.threshold.is_none() {
+            warn!(
+                "No threshold of label `{}` reaches a {} of {}, keeping its threshold",
+                recommendation.label_name.0,
+                recommendation.target.metric,
+                recommendation.target.value
+            );
+        }
+    }
+    apply_recommendations(&mut new_stream, &recommendations);
+    new_stream.set_model_version(model_version);
+
+    if let (Some(path), Some(mut file)) = (path, file) {
+        serde_json::to_writer(&mut file, &new_stream)
+            .context("Could not serialise stream.")
+            .and_then(|()| writeln!(file).context("Could not write stream."))?;
+        info!(
+            "Wrote stream `{}` to `{}`",
+            new_stream.name.0,
+            path.display()
+        );
+    }
+
+    if !apply {
+        if path.is_none() {
+            info!("Pass --file to write the updated stream, or --apply to update it.");
+        }
+        return Ok(());
+    }
+    if !yes
+        && !Confirm::new()
+            .with_prompt(format!(
+                "Update the thresholds of stream `{}` and pin it to model version {}?",
+                stream_name.stream.0, model_version.0
+            ))
+            .interact()?
+    {
+        bail!("Stream update aborted by user");
+    }
+    client
+        .update_stream(
+            stream_name,
+            UpdateStream {
+                model: new_stream.model.as_ref(),
+                label_filter: new_stream.label_filter.as_ref(),
+                ..Default::default()
+            },
+        )
+        .context("Operation to update a stream has failed.")?;
+    info!("Stream `{}` updated successfully", stream_name.stream.0);
+    Ok(())
+}
+
+/// The threshold of a label which reaches a target on its validation curve: the one with the
+/// highest recall for a target precision, or the highest precision for a target recall.
+fn recommend_threshold(
+    label_name: &LabelName,
+    current_threshold: NotNan<f64>,
+    target: Target,
+    label_validation: &LabelValidation,
+) -> Result<ThresholdRecommendation> {
+    let LabelValidation {
+        thresholds,
+        precisions,
+        recalls,
+    } = label_validation;
+    if thresholds.len() != precisions.len() || thresholds.len() != recalls.len() {
+        bail!(
+            "The validation curve of label `{}` has {} thresholds, {} precisions and {} recalls",
+            label_name.0,
+            thresholds.len(),
+            precisions.len(),
+            recalls.len()
+        );
+    }
+    let values = match target.metric {
+        Metric::Precision => precisions,
+        Metric::Recall => recalls,
+    };
+    let threshold = if values.iter().any(|value| *value >= target.value) {
+        match target.metric {
+            Metric::Precision => {
+                get_threshold_and_recall_for_precision(target.value, label_name, label_validation)?
+                    .threshold
+            }
+            Metric::Recall => {
+                get_threshold_and_precision_for_recall(target.value, label_name, label_validation)?
+                    .threshold
+            }
+        }
+    } else {
+        None
+    };
+    let precision_and_recall = threshold
+        .map(|threshold| {
+            get_precision_and_recall_for_threshold(threshold, label_name, label_validation)
+        })
+        .transpose()?;
+
+    Ok(ThresholdRecommendation {
+        label_name: label_name.clone(),
+        target,
+        current_threshold,
+        threshold,
+        precision: precision_and_recall
+            .as_ref()
+            .map(|PrecisionAndRecall { precision, .. }| *precision),
+        recall: precision_and_recall.map(|PrecisionAndRecall { recall, .. }| recall),
+    })
+}
+
+/// Set the thresholds of a stream, and of its label filter, to the recommended ones.
+fn apply_recommendations(new_stream: &mut NewStream, recommendations: &[ThresholdRecommendation]) {
+    let recommended = |label_name: &str| {
+        recommendations
+            .iter()
+            .find(|recommendation| recommendation.label_name.0 == label_name)
+            .and_then(|recommendation| recommendation.threshold)
+    };
+    if let Some(model) = &mut new_stream.model {
+        for label_threshold in &mut model.label_thresholds {
+            if let Some(threshold) = recommended(&label_threshold.name.join(" > ")) {
+                label_threshold.threshold = threshold;
+            }
+        }
+    }
+    if let Some(label_filter) = &mut new_stream.label_filter {
+        if let Some(threshold) = recommended(&label_filter.label.0) {
+            label_filter.threshold = threshold;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{recommend_threshold, LabelTarget, Metric, Target};
+    use ordered_float::NotNan;
+    use reinfer_client::{resources::validation::LabelValidation, LabelName};
+
+    fn not_nans(values: &[f64]) -> Vec<NotNan<f64>> {
+        values
+            .iter()
+            .map(|value| NotNan::new(*value).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_recommend_threshold() {
+        let label_name = LabelName("Billing > Refund".to_owned());
+        let label_validation = LabelValidation {
+            thresholds: not_nans(&[0.9, 0.7, 0.5, 0.3, 0.1]),
+            precisions: not_nans(&[1.0, 0.95, 0.9, 0.8, 0.6]),
+            recalls: not_nans(&[0.2, 0.4, 0.6, 0.8, 0.95]),
+        };
+        let current = NotNan::new(0.5).unwrap();
+        let target = |metric, value| Target {
+            metric,
+            value: NotNan::new(value).unwrap(),
+        };
+
+        let precision = recommend_threshold(
+            &label_name,
+            current,
+            target(Metric::Precision, 0.85),
+            &label_validation,
+        )
+        .unwrap();
+        assert_eq!(precision.threshold, Some(NotNan::new(0.5).unwrap()));
+        assert_eq!(precision.recall, Some(NotNan::new(0.6).unwrap()));
+
+        let recall = recommend_threshold(
+            &label_name,
+            current,
+            target(Metric::Recall, 0.7),
+            &label_validation,
+        )
+        .unwrap();
+        assert_eq!(recall.threshold, Some(NotNan::new(0.3).unwrap()));
+        assert_eq!(recall.precision, Some(NotNan::new(0.8).unwrap()));
+
+        let unreachable = recommend_threshold(
+            &label_name,
+            current,
+            target(Metric::Recall, 0.99),
+            &label_validation,
+        )
+        .unwrap();
+        assert_eq!(unreachable.threshold, None);
+        assert_eq!(unreachable.precision, None);
+
+        let malformed = LabelValidation {
+            recalls: not_nans(&[0.2, 0.4]),
+            ..label_validation
+        };
+        assert!(recommend_threshold(
+            &label_name,
+            current,
+            target(Metric::Recall, 0.3),
+            &malformed
+        )
+        .is_err());
+
+        assert_eq!(
+            "Billing > Refund=0.9".parse::<LabelTarget>().unwrap(),
+            LabelTarget {
+                label_name,
+                value: NotNan::new(0.9).unwrap(),
+            }
+        );
+        assert!("Billing > Refund=1.5".parse::<LabelTarget>().is_err());
+        assert!("=0.5".parse::<LabelTarget>().is_err());
+    }
+}
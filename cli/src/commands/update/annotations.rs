@@ -0,0 +1,450 @@
+use anyhow::{anyhow, bail, Context, Error, Result};
+use colored::Colorize;
+use log::info;
+use reinfer_client::{
+    Client, CommentUid, DatasetFullName, DatasetIdentifier, Label, LabelGroupName, LabelName,
+    Labelling, NewLabelling, SourceId, SourceIdentifier, DEFAULT_LABEL_GROUP_NAME,
+};
+use scoped_threadpool::Pool;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+};
+use structopt::StructOpt;
+
+use crate::{
+    args::ContextDefault,
+    concurrency::AdaptiveConcurrency,
+    progress::{Options as ProgressOptions, Progress},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct UpdateAnnotationsArgs {
+    #[structopt(
+        short = "d",
+        long = "dataset",
+        env = "REINFER_DATASET",
+        context_default = "dataset"
+    )]
+    /// Dataset (name or id) whose annotations to update
+    dataset: DatasetIdentifier,
+
+    #[structopt(short = "s", long = "source")]
+    /// Only update the reviewed comments of this source (name or id), instead of those of all the
+    /// sources in the dataset
+    source: Option<SourceIdentifier>,
+
+    #[structopt(long = "group")]
+    /// Label group of the labels to update, the default group if not specified
+    group: Option<String>,
+
+    #[structopt(long = "rename", number_of_values = 1)]
+    /// Rename a label in the annotations, as `<old label>=<new label>`. Renaming several labels
+    /// to the same label merges them.
+    renames: Vec<LabelRename>,
+
+    #[structopt(long = "delete", number_of_values = 1)]
+    /// Remove a label from the annotations
+    deletes: Vec<String>,
+
+    #[structopt(long)]
+    /// Only count the comments which would be updated, without updating them
+    dry_run: bool,
+
+    #[structopt(long)]
+    /// Don't display a progress bar
+    no_progress: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelRename {
+    from: LabelName,
+    to: LabelName,
+}
+
+impl FromStr for LabelRename {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        match string.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => Ok(Self {
+                from: LabelName(from.trim().to_owned()),
+                to: LabelName(to.trim().to_owned()),
+            }),
+            _ => Err(anyhow!(
+                "Expected a rename as `<old label>=<new label>`, got `{}`",
+                string
+            )),
+        }
+    }
+}
+
+/// What to do with each label found in an annotation: rename it, or remove it if `None`.
+#[derive(Debug, Default)]
+struct LabelRules(HashMap<LabelName, Option<LabelName>>);
+
+impl LabelRules {
+    fn new(renames: &[LabelRename], deletes: &[String]) -> Result<Self> {
+        let mut rules = HashMap::new();
+        let all_rules = renames
+            .iter()
+            .map(|rename| (rename.from.clone(), Some(rename.to.clone())))
+            .chain(deletes.iter().map(|name| (LabelName(name.clone()), None)));
+        for (from, to) in all_rules {
+            match rules.get(&from) {
+                Some(previous) if previous != &to => {
+                    bail!("Conflicting rules for label `{}`", from.0)
+                }
+                _ => {
+                    rules.insert(from, to);
+                }
+            }
+        }
+        if rules.is_empty() {
+            bail!("Nothing to do, pass at least one of --rename or --delete");
+        }
+        Ok(Self(rules))
+    }
+
+    /// Apply the rules to one group of a labelling, returning the updated group if any label
+    /// matched.
+    ///
+    /// A label which ends up both assigned and dismissed, e.g. after a merge, is kept as assigned.
+    fn apply(&self, labelling: &Labelling) -> Option<NewLabelling> {
+        let mut matched = false;
+        let mut apply_to = |labels: &[Label], seen: &mut HashSet<LabelName>| {
+            labels
+                .iter()
+                .filter_map(|label| match self.0.get(&label.name) {
+                    None => Some(label.clone()),
+                    Some(rule) => {
+                        matched = true;
+                        rule.as_ref().map(|name| Label {
+                            name: name.clone(),
+                            ..label.clone()
+                        })
+                    }
+                })
+                .filter(|label| seen.insert(label.name.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut seen = HashSet::new();
+        let assigned = apply_to(&labelling.assigned, &mut seen);
+        let dismissed = apply_to(&labelling.dismissed, &mut seen);
+        matched.then(|| NewLabelling {
+            group: labelling.group.clone(),
+            assigned: Some(assigned),
+            dismissed: Some(dismissed),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Statistics {
+    reviewed: AtomicUsize,
+    matched: AtomicUsize,
+    updated: AtomicUsize,
+}
+
+impl Statistics {
+    fn add_reviewed(&self, num_reviewed: usize) {
+        self.reviewed.fetch_add(num_reviewed, Ordering::SeqCst);
+    }
+
+    fn add_matched(&self) {
+        self.matched.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn add_updated(&self) {
+        self.updated.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn num_reviewed(&self) -> usize {
+        self.reviewed.load(Ordering::SeqCst)
+    }
+
+    fn num_matched(&self) -> usize {
+        self.matched.load(Ordering::SeqCst)
+    }
+
+    fn num_updated(&self) -> usize {
+        self.updated.load(Ordering::SeqCst)
+    }
+}
+
+pub fn update(client: &Client, args: &UpdateAnnotationsArgs, pool: &mut Pool) -> Result<()> {
+    let UpdateAnnotationsArgs {
+        dataset,
+        source,
+        group,
+        renames,
+        deletes,
+        dry_run,
+        no_progress,
+    } = args;
+
+    let rules = LabelRules::new(renames, deletes)?;
+    let group = group
+        .clone()
+        .map_or_else(|| DEFAULT_LABEL_GROUP_NAME.clone(), LabelGroupName);
+    let dataset = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?;
+    let dataset_name = dataset.full_name();
+    let source_ids = match source {
+        Some(source) => vec![
+            client
+                .get_source(source.clone())
+                .with_context(|| format!("Unable to get source {source}"))?
+                .id,
+        ],
+        None => dataset.source_ids.clone(),
+    };
+
+    let statistics = Arc::new(Statistics::default());
+    let mut rule_counts = BTreeMap::new();
+    let concurrency = AdaptiveConcurrency::new(client, pool.thread_count() as usize);
+    let mut progress = if *no_progress {
+        None
+    } else {
+        Some(progress_bar(&statistics, *dry_run))
+    };
+    for source_id in &source_ids {
+        update_source_annotations(
+            client,
+            &dataset_name,
+            source_id,
+            &group,
+            &rules,
+            *dry_run,
+            &statistics,
+            &mut rule_counts,
+            pool,
+            &concurrency,
+        )?;
+    }
+    if let Some(progress) = progress.as_mut() {
+        progress.done();
+    }
+
+    for (label, count) in &rule_counts {
+        let action = match &rules.0[&LabelName(label.clone())] {
+            Some(to) => format!("rename `{}` to `{}`", label, to.0),
+            None => format!("delete `{}`", label),
+        };
+        info!("{}: {} comments", action, count);
+    }
+    if *dry_run {
+        info!(
+            "{} of {} reviewed comments would be updated.",
+            statistics.num_matched(),
+            statistics.num_reviewed()
+        );
+    } else {
+        info!(
+            "Successfully updated {} of {} reviewed comments.",
+            statistics.num_updated(),
+            statistics.num_reviewed()
+        );
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_source_annotations(
+    client: &Client,
+    dataset_name: &DatasetFullName,
+    source_id: &SourceId,
+    group: &LabelGroupName,
+    rules: &LabelRules,
+    dry_run: bool,
+    statistics: &Statistics,
+    rule_counts: &mut BTreeMap<String, usize>,
+    pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+) -> Result<()> {
+    for page in client.get_labellings_iter(dataset_name, source_id, false, None) {
+        let page = page.context("Operation to get labellings has failed.")?;
+        statistics.add_reviewed(page.len());
+
+        let mut updates = Vec::new();
+        for comment in page {
+            let Some(labellings) = comment.labelling else {
+                continue;
+            };
+            let Some(updated) = labellings
+                .iter()
+                .filter(|labelling| &labelling.group == group)
+                .find_map(|labelling| rules.apply(labelling))
+            else {
+                continue;
+            };
+
+            for label in labellings
+                .iter()
+                .filter(|labelling| &labelling.group == group)
+                .flat_map(|labelling| labelling.assigned.iter().chain(&labelling.dismissed))
+                .filter(|label| rules.0.contains_key(&label.name))
+                .map(|label| &label.name)
+                .collect::<HashSet<_>>()
+            {
+                *rule_counts.entry(label.0.clone()).or_default() += 1;
+            }
+            statistics.add_matched();
+
+            // Other groups are sent unchanged, so that they are left as they are.
+            let new_labellings: Vec<_> = labellings
+                .into_iter()
+                .map(|labelling| {
+                    if &labelling.group == group {
+                        updated.clone()
+                    } else {
+                        NewLabelling {
+                            group: labelling.group,
+                            assigned: Some(labelling.assigned),
+                            dismissed: Some(labelling.dismissed),
+                        }
+                    }
+                })
+                .collect();
+            updates.push((comment.comment.uid, new_labellings));
+        }
+
+        if !dry_run && !updates.is_empty() {
+            upload_labellings(
+                client,
+                dataset_name,
+                &updates,
+                statistics,
+                pool,
+                concurrency,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn upload_labellings(
+    client: &Client,
+    dataset_name: &DatasetFullName,
+    updates: &[(CommentUid, Vec<NewLabelling>)],
+    statistics: &Statistics,
+    pool: &mut Pool,
+    concurrency: &AdaptiveConcurrency,
+) -> Result<()> {
+    let (error_sender, error_receiver) = channel();
+
+    pool.scoped(|scope| {
+        for (comment_uid, labellings) in updates {
+            let error_sender = error_sender.clone();
+            scope.execute(move || {
+                let result = concurrency
+                    .run(|| {
+                        client.update_labelling(
+                            dataset_name,
+                            comment_uid,
+                            Some(labellings),
+                            None,
+                            None,
+                        )
+                    })
+                    .with_context(|| {
+                        format!("Could not update labelling for comment `{}`", comment_uid.0)
+                    });
+                match result {
+                    Ok(_) => statistics.add_updated(),
+                    Err(error) => error_sender.send(error).expect("Could not send error"),
+                }
+            });
+        }
+    });
+
+    match error_receiver.try_recv() {
+        Ok(error) => Err(error),
+        Err(_) => Ok(()),
+    }
+}
+
+fn progress_bar(statistics: &Arc<Statistics>, dry_run: bool) -> Progress {
+    Progress::new(
+        move |statistics: &Statistics| {
+            let num_done = if dry_run {
+                statistics.num_matched()
+            } else {
+                statistics.num_updated()
+            };
+            (
+                statistics.num_reviewed() as u64,
+                format!(
+                    "{} {} {} {}",
+                    statistics.num_reviewed(),
+                    "reviewed".dimmed(),
+                    num_done,
+                    if dry_run { "matched" } else { "updated" }.dimmed(),
+                ),
+            )
+        },
+        statistics,
+        None,
+        ProgressOptions { bytes_units: false },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LabelRename, LabelRules};
+    use reinfer_client::{Labelling, NewLabelling};
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_label_rules() {
+        let rules = LabelRules::new(
+            &[
+                "Billing > Refund=Refunds".parse::<LabelRename>().unwrap(),
+                "Billing > Returns = Refunds".parse().unwrap(),
+            ],
+            &["Spam".to_owned()],
+        )
+        .unwrap();
+
+        let labelling: Labelling = serde_json::from_value(json!({
+            "group": "default",
+            "assigned": [
+                {"name": "Billing > Refund", "sentiment": "positive"},
+                {"name": "Urgent", "sentiment": "negative"}
+            ],
+            "dismissed": [
+                {"name": "Billing > Returns", "sentiment": "positive"},
+                {"name": "Spam", "sentiment": "positive"}
+            ]
+        }))
+        .unwrap();
+        let expected: NewLabelling = serde_json::from_value(json!({
+            "group": "default",
+            "assigned": [
+                {"name": "Refunds", "sentiment": "positive"},
+                {"name": "Urgent", "sentiment": "negative"}
+            ],
+            "dismissed": []
+        }))
+        .unwrap();
+        assert_eq!(rules.apply(&labelling), Some(expected));
+
+        let untouched: Labelling = serde_json::from_value(json!({
+            "group": "default",
+            "assigned": [{"name": "Urgent", "sentiment": "positive"}]
+        }))
+        .unwrap();
+        assert_eq!(rules.apply(&untouched), None);
+
+        assert!("Refunds".parse::<LabelRename>().is_err());
+        assert!(LabelRules::new(&["Spam=Junk".parse().unwrap()], &["Spam".to_owned()]).is_err());
+        assert!(LabelRules::new(&[], &[]).is_err());
+    }
+}
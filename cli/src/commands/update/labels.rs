@@ -0,0 +1,640 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use dialoguer::Confirm;
+use log::{info, warn};
+use reinfer_client::{
+    resources::comment::Labelling, Client, Dataset, DatasetFullName, DatasetIdentifier, EntityName,
+    LabelGroup, LabelGroupName, LabelName, NewEntityDef, NewLabelDef, UpdateEntityDef,
+    UpdateLabelDef, DEFAULT_LABEL_GROUP_NAME,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+use crate::args::ContextDefault;
+
+#[derive(Debug, StructOpt)]
+pub struct UpdateLabelsArgs {
+    #[structopt(name = "dataset", env = "REINFER_DATASET", context_default = "dataset")]
+    /// Name or id of the dataset
+    dataset: DatasetIdentifier,
+
+    #[structopt(short = "f", long = "file", parse(from_os_str))]
+    /// Path to the YAML or JSON taxonomy file, as written by `re export taxonomy`
+    path: PathBuf,
+
+    #[structopt(long)]
+    /// Only print the changes which would be made, without making them
+    dry_run: bool,
+
+    #[structopt(long)]
+    /// Also delete labels which have reviewed annotations
+    force: bool,
+
+    #[structopt(short = "y", long = "yes")]
+    /// Don't ask for confirmation before deleting labels and entities
+    yes: bool,
+}
+
+/// The label and entity defs of a dataset.
+///
+/// Labels of the listed groups which are missing from the taxonomy are deleted, and other groups
+/// are left alone. Entities are only managed if `entity_defs` is present.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Taxonomy {
+    #[serde(default)]
+    pub label_groups: Vec<TaxonomyLabelGroup>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity_defs: Option<Vec<NewEntityDef>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TaxonomyLabelGroup {
+    #[serde(default = "default_label_group_name")]
+    pub name: LabelGroupName,
+    #[serde(default)]
+    pub label_defs: Vec<TaxonomyLabelDef>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TaxonomyLabelDef {
+    #[serde(flatten)]
+    pub label_def: NewLabelDef,
+    /// The current name of a label to rename, keeping its annotations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<LabelName>,
+}
+
+fn default_label_group_name() -> LabelGroupName {
+    DEFAULT_LABEL_GROUP_NAME.clone()
+}
+
+impl Taxonomy {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not open file `{}`", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Could not parse taxonomy `{}`", path.display()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum TaxonomyChange<'a> {
+    CreateLabels {
+        group: &'a LabelGroupName,
+        label_defs: Vec<NewLabelDef>,
+    },
+    RenameLabel {
+        group: &'a LabelGroupName,
+        from: &'a LabelName,
+        to: &'a LabelName,
+    },
+    UpdateLabel {
+        group: &'a LabelGroupName,
+        label_def: &'a NewLabelDef,
+        fields: Vec<&'static str>,
+    },
+    DeleteLabel {
+        group: &'a LabelGroupName,
+        name: &'a LabelName,
+    },
+    CreateEntity(&'a NewEntityDef),
+    UpdateEntity {
+        entity_def: &'a NewEntityDef,
+        fields: Vec<&'static str>,
+    },
+    DeleteEntity(&'a EntityName),
+}
+
+pub fn update(client: &Client, args: &UpdateLabelsArgs) -> Result<()> {
+    let UpdateLabelsArgs {
+        dataset,
+        path,
+        dry_run,
+        force,
+        yes,
+    } = args;
+
+    let taxonomy = Taxonomy::from_file(path)?;
+    let dataset = client
+        .get_dataset(dataset.clone())
+        .context("Operation to get dataset has failed.")?;
+    let dataset_name = dataset.full_name();
+    let changes = diff_taxonomy(&taxonomy, &dataset)?;
+
+    let num_reviewed = if changes
+        .iter()
+        .any(|change| matches!(change, TaxonomyChange::DeleteLabel { .. }))
+    {
+        num_reviewed_annotations(client, &dataset, &changes)?
+    } else {
+        HashMap::new()
+    };
+
+    let mut num_protected = 0;
+    for change in &changes {
+        let reviewed = match change {
+            TaxonomyChange::DeleteLabel { group, name } => num_reviewed
+                .get(&((*group).clone(), (*name).clone()))
+                .copied()
+                .unwrap_or(0),
+            _ => 0,
+        };
+        if reviewed > 0 {
+            num_protected += 1;
+        }
+        print_change(change, reviewed);
+    }
+
+    if changes.is_empty() {
+        info!(
+            "The taxonomy of dataset `{}` is up to date.",
+            dataset_name.0
+        );
+        return Ok(());
+    }
+    if *dry_run {
+        info!("{} changes would be made.", changes.len());
+        return Ok(());
+    }
+    if num_protected > 0 && !force {
+        bail!(
+            "Refusing to delete {} labels with reviewed annotations, pass --force to delete them anyway",
+            num_protected
+        );
+    }
+    let num_deletions = changes
+        .iter()
+        .filter(|change| {
+            matches!(
+                change,
+                TaxonomyChange::DeleteLabel { .. } | TaxonomyChange::DeleteEntity(_)
+            )
+        })
+        .count();
+    if num_deletions > 0
+        && !yes
+        && !Confirm::new()
+            .with_prompt(format!(
+                "Delete {num_deletions} labels and entities from `{}`, along with their annotations?",
+                dataset_name.0
+            ))
+            .interact()?
+    {
+        bail!("Taxonomy update aborted by user");
+    }
+
+    for change in &changes {
+        apply_change(client, &dataset_name, change)?;
+    }
+    info!("{} changes made successfully.", changes.len());
+    Ok(())
+}
+
+/// The changes to make to the labels and entities of a dataset for them to match a taxonomy.
+/// Renames come first, so that new labels can reuse the old names.
+fn diff_taxonomy<'a>(
+    taxonomy: &'a Taxonomy,
+    dataset: &'a Dataset,
+) -> Result<Vec<TaxonomyChange<'a>>> {
+    let mut renames = Vec::new();
+    let mut others = Vec::new();
+
+    for group in &taxonomy.label_groups {
+        let existing: &[_] = dataset
+            .label_groups
+            .iter()
+            .find(|existing| existing.name == group.name)
+            .map_or(&[], |existing: &LabelGroup| &existing.label_defs);
+        let find_existing = |name: &LabelName| existing.iter().find(|label| &label.name == name);
+
+        let mut created = Vec::new();
+        for TaxonomyLabelDef {
+            label_def,
+            renamed_from,
+        } in &group.label_defs
+        {
+            let current = match (find_existing(&label_def.name), renamed_from) {
+                (Some(_), Some(renamed_from))
+                    if *renamed_from != label_def.name && find_existing(renamed_from).is_some() =>
+                {
+                    bail!(
+                        "Cannot rename label `{}` to `{}` in label group `{}`, as both exist",
+                        renamed_from.0,
+                        label_def.name.0,
+                        group.name.0
+                    );
+                }
+                (Some(current), _) => Some(current),
+                (None, Some(renamed_from)) => {
+                    let current = find_existing(renamed_from).with_context(|| {
+                        format!(
+                            "Cannot rename label `{}` to `{}`, as it is not in label group `{}`",
+                            renamed_from.0, label_def.name.0, group.name.0
+                        )
+                    })?;
+                    renames.push(TaxonomyChange::RenameLabel {
+                        group: &group.name,
+                        from: renamed_from,
+                        to: &label_def.name,
+                    });
+                    Some(current)
+                }
+                (None, None) => None,
+            };
+
+            match current {
+                Some(current) => {
+                    let mut fields = Vec::new();
+                    if differs(&label_def.title, &current.title) {
+                        fields.push("title");
+                    }
+                    if differs(&label_def.description, &current.description) {
+                        fields.push("description");
+                    }
+                    if !fields.is_empty() {
+                        others.push(TaxonomyChange::UpdateLabel {
+                            group: &group.name,
+                            label_def,
+                            fields,
+                        });
+                    }
+                }
+                None => created.push(label_def.clone()),
+            }
+        }
+        if !created.is_empty() {
+            others.push(TaxonomyChange::CreateLabels {
+                group: &group.name,
+                label_defs: created,
+            });
+        }
+
+        for current in existing {
+            let kept = group.label_defs.iter().any(|label| {
+                label.label_def.name == current.name
+                    || label.renamed_from.as_ref() == Some(&current.name)
+            });
+            if !kept {
+                others.push(TaxonomyChange::DeleteLabel {
+                    group: &group.name,
+                    name: &current.name,
+                });
+            }
+        }
+    }
+
+    if let Some(entity_defs) = &taxonomy.entity_defs {
+        for entity_def in entity_defs {
+            match dataset
+                .entity_defs
+                .iter()
+                .find(|existing| existing.name == entity_def.name)
+            {
+                Some(current) => {
+                    let mut fields = Vec::new();
+                    if entity_def.title != current.title {
+                        fields.push("title");
+                    }
+                    if entity_def.inherits_from != current.inherits_from {
+                        fields.push("inherits_from");
+                    }
+                    if entity_def.trainable != current.trainable {
+                        fields.push("trainable");
+                    }
+                    if !fields.is_empty() {
+                        others.push(TaxonomyChange::UpdateEntity { entity_def, fields });
+                    }
+                }
+                None => others.push(TaxonomyChange::CreateEntity(entity_def)),
+            }
+        }
+        for current in &dataset.entity_defs {
+            if !entity_defs
+                .iter()
+                .any(|entity_def| entity_def.name == current.name)
+            {
+                others.push(TaxonomyChange::DeleteEntity(&current.name));
+            }
+        }
+    }
+
+    renames.extend(others);
+    Ok(renames)
+}
+
+/// Whether a field is set in the taxonomy to something other than its actual value.
+fn differs(desired: &Option<String>, actual: &str) -> bool {
+    desired.as_deref().is_some_and(|desired| desired != actual)
+}
+
+/// The number of reviewed comments in which each of the labels to delete is either assigned or
+/// dismissed, by label group and name.
+fn num_reviewed_annotations(
+    client: &Client,
+    dataset: &Dataset,
+    changes: &[TaxonomyChange],
+) -> Result<HashMap<(LabelGroupName, LabelName), u64>> {
+    let mut counts: HashMap<(LabelGroupName, LabelName), u64> = changes
+        .iter()
+        .filter_map(|change| match change {
+            TaxonomyChange::DeleteLabel { group, name } => {
+                Some((((*group).clone(), (*name).clone()), 0))
+            }
+            _ => None,
+        })
+        .collect();
+    let dataset_name = dataset.full_name();
+    for source_id in &dataset.source_ids {
+        for page in client.get_labellings_iter(&dataset_name, source_id, false, None) {
+            let page = page.context("Operation to get labellings has failed.")?;
+            for comment in page {
+                count_annotations(
+                    comment.labelling.as_deref().unwrap_or_default(),
+                    &mut counts,
+                );
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Count a reviewed comment once for each label it has an annotation for.
+fn count_annotations(
+    labellings: &[Labelling],
+    counts: &mut HashMap<(LabelGroupName, LabelName), u64>,
+) {
+    let annotated: HashSet<(&LabelGroupName, &LabelName)> = labellings
+        .iter()
+        .flat_map(|labelling| {
+            labelling
+                .assigned
+                .iter()
+                .chain(&labelling.dismissed)
+                .map(move |label| (&labelling.group, &label.name))
+        })
+        .collect();
+    for (group, name) in annotated {
+        if let Some(count) = counts.get_mut(&(group.clone(), name.clone())) {
+            *count += 1;
+        }
+    }
+}
+
+fn print_change(change: &TaxonomyChange, num_reviewed: u64) {
+    let line = match change {
+        TaxonomyChange::CreateLabels { group, label_defs } => label_defs
+            .iter()
+            .map(|label_def| format!("+ create label {}", label_name(group, &label_def.name)))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .green(),
+        TaxonomyChange::RenameLabel { group, from, to } => {
+            format!("> rename label {} to `{}`", label_name(group, from), to.0).cyan()
+        }
+        TaxonomyChange::UpdateLabel {
+            group,
+            label_def,
+            fields,
+        } => format!(
+            "~ update label {} ({})",
+            label_name(group, &label_def.name),
+            fields.join(", ")
+        )
+        .yellow(),
+        TaxonomyChange::DeleteLabel { group, name } if num_reviewed > 0 => format!(
+            "- delete label {} ({} reviewed annotations)",
+            label_name(group, name),
+            num_reviewed
+        )
+        .red()
+        .bold(),
+        TaxonomyChange::DeleteLabel { group, name } => {
+            format!("- delete label {}", label_name(group, name)).red()
+        }
+        TaxonomyChange::CreateEntity(entity_def) => {
+            format!("+ create entity `{}`", entity_def.name.0).green()
+        }
+        TaxonomyChange::UpdateEntity { entity_def, fields } => format!(
+            "~ update entity `{}` ({})",
+            entity_def.name.0,
+            fields.join(", ")
+        )
+        .yellow(),
+        TaxonomyChange::DeleteEntity(name) => format!("- delete entity `{}`", name.0).red(),
+    };
+    println!("{line}");
+}
+
+fn label_name(group: &LabelGroupName, name: &LabelName) -> String {
+    if group == &*DEFAULT_LABEL_GROUP_NAME {
+        format!("`{}`", name.0)
+    } else {
+        format!("`{}` in group `{}`", name.0, group.0)
+    }
+}
+
+fn apply_change(
+    client: &Client,
+    dataset_name: &DatasetFullName,
+    change: &TaxonomyChange,
+) -> Result<()> {
+    match change {
+        TaxonomyChange::CreateLabels { group, label_defs } => client
+            .create_label_defs(dataset_name, group, label_defs)
+            .context("Operation to create labels has failed."),
+        TaxonomyChange::RenameLabel { group, from, to } => client
+            .update_label_def(
+                dataset_name,
+                group,
+                from,
+                UpdateLabelDef {
+                    name: Some(to),
+                    ..Default::default()
+                },
+            )
+            .with_context(|| format!("Operation to rename label `{}` has failed.", from.0)),
+        TaxonomyChange::UpdateLabel {
+            group, label_def, ..
+        } => client
+            .update_label_def(
+                dataset_name,
+                group,
+                &label_def.name,
+                UpdateLabelDef {
+                    name: None,
+                    title: label_def.title.as_deref(),
+                    description: label_def.description.as_deref(),
+                },
+            )
+            .with_context(|| {
+                format!(
+                    "Operation to update label `{}` has failed.",
+                    label_def.name.0
+                )
+            }),
+        TaxonomyChange::DeleteLabel { group, name } => {
+            warn!("Deleting label `{}`.", name.0);
+            client
+                .delete_label_def(dataset_name, group, name)
+                .with_context(|| format!("Operation to delete label `{}` has failed.", name.0))
+        }
+        TaxonomyChange::CreateEntity(entity_def) => client
+            .create_entity_def(dataset_name, entity_def)
+            .with_context(|| {
+                format!(
+                    "Operation to create entity `{}` has failed.",
+                    entity_def.name.0
+                )
+            }),
+        TaxonomyChange::UpdateEntity { entity_def, .. } => client
+            .update_entity_def(
+                dataset_name,
+                &entity_def.name,
+                UpdateEntityDef {
+                    inherits_from: Some(&entity_def.inherits_from),
+                    title: Some(&entity_def.title),
+                    trainable: Some(entity_def.trainable),
+                },
+            )
+            .with_context(|| {
+                format!(
+                    "Operation to update entity `{}` has failed.",
+                    entity_def.name.0
+                )
+            }),
+        TaxonomyChange::DeleteEntity(name) => {
+            warn!("Deleting entity `{}`.", name.0);
+            client
+                .delete_entity_def(dataset_name, name)
+                .with_context(|| format!("Operation to delete entity `{}` has failed.", name.0))
+        }
+    }
+}
+
+/// Group label defs by name, to make taxonomies exported from a dataset easier to read.
+pub fn sorted_label_groups(
+    label_groups: impl IntoIterator<Item = (LabelGroupName, Vec<NewLabelDef>)>,
+) -> Vec<TaxonomyLabelGroup> {
+    let mut groups: BTreeMap<String, TaxonomyLabelGroup> = BTreeMap::new();
+    for (name, label_defs) in label_groups {
+        let mut label_defs: Vec<_> = label_defs
+            .into_iter()
+            .map(|label_def| TaxonomyLabelDef {
+                label_def,
+                renamed_from: None,
+            })
+            .collect();
+        label_defs.sort_by(|lhs, rhs| lhs.label_def.name.0.cmp(&rhs.label_def.name.0));
+        groups.insert(name.0.clone(), TaxonomyLabelGroup { name, label_defs });
+    }
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_annotations, diff_taxonomy, Taxonomy, TaxonomyChange};
+    use reinfer_client::{resources::comment::Labelling, LabelGroupName, LabelName};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_diff_taxonomy() {
+        let taxonomy: Taxonomy = serde_yaml::from_str(
+            r#"
+label_groups:
+  - label_defs:
+      - name: Billing
+        title: Billing issues
+      - name: Refunds
+        renamed_from: Refund
+      - name: Complaint
+"#,
+        )
+        .unwrap();
+        let dataset = serde_json::from_value(json!({
+            "id": "d1", "name": "triage", "owner": "acme", "title": "Triage", "description": "",
+            "created": "2023-01-02T03:04:05Z", "last_modified": "2023-01-02T03:04:05Z",
+            "model_family": "english", "source_ids": [], "has_sentiment": false,
+            "entity_defs": [{
+                "color": 0, "id": "e1", "inherits_from": [], "name": "org", "title": "Org",
+                "trainable": true
+            }],
+            "label_defs": [],
+            "label_groups": [{"name": "default", "label_defs": [
+                {"name": "Billing", "title": "Billing"},
+                {"name": "Refund"},
+                {"name": "Spam"}
+            ]}]
+        }))
+        .unwrap();
+
+        let group = LabelGroupName("default".to_owned());
+        let changes = diff_taxonomy(&taxonomy, &dataset).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                TaxonomyChange::RenameLabel {
+                    group: &group,
+                    from: &LabelName("Refund".to_owned()),
+                    to: &LabelName("Refunds".to_owned()),
+                },
+                TaxonomyChange::UpdateLabel {
+                    group: &group,
+                    label_def: &taxonomy.label_groups[0].label_defs[0].label_def,
+                    fields: vec!["title"],
+                },
+                TaxonomyChange::CreateLabels {
+                    group: &group,
+                    label_defs: vec![taxonomy.label_groups[0].label_defs[2].label_def.clone()],
+                },
+                TaxonomyChange::DeleteLabel {
+                    group: &group,
+                    name: &LabelName("Spam".to_owned()),
+                },
+            ]
+        );
+
+        // Renaming a label which doesn't exist is an error.
+        let taxonomy: Taxonomy = serde_yaml::from_str(
+            "label_groups: [{label_defs: [{name: Refunds, renamed_from: Missing}]}]",
+        )
+        .unwrap();
+        assert!(diff_taxonomy(&taxonomy, &dataset).is_err());
+
+        // So is renaming a label to one which already exists.
+        let taxonomy: Taxonomy = serde_yaml::from_str(
+            "label_groups: [{label_defs: [{name: Billing, renamed_from: Refund}, {name: Spam}]}]",
+        )
+        .unwrap();
+        assert!(diff_taxonomy(&taxonomy, &dataset).is_err());
+    }
+
+    #[test]
+    fn test_count_annotations() {
+        let key = |group: &str, name: &str| {
+            (LabelGroupName(group.to_owned()), LabelName(name.to_owned()))
+        };
+        let mut counts: HashMap<_, _> =
+            [(key("default", "Billing"), 0), (key("other", "Billing"), 0)]
+                .into_iter()
+                .collect();
+        let billing = json!([{"name": "Billing", "sentiment": "positive"}]);
+        let labellings: Vec<Labelling> = serde_json::from_value(json!([
+            {"group": "default", "assigned": billing, "dismissed": billing},
+            {"group": "extra", "dismissed": billing}
+        ]))
+        .unwrap();
+        count_annotations(&labellings, &mut counts);
+        count_annotations(&labellings[..1], &mut counts);
+        count_annotations(&[], &mut counts);
+
+        assert_eq!(counts[&key("default", "Billing")], 2);
+        assert_eq!(counts[&key("other", "Billing")], 0);
+        assert_eq!(counts.len(), 2);
+    }
+}
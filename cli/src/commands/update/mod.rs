@@ -1,15 +1,20 @@
+mod annotations;
 mod dataset;
+pub mod labels;
 mod project;
 mod source;
+mod stream;
 mod users;
 
 use self::{
-    dataset::UpdateDatasetArgs, project::UpdateProjectArgs, source::UpdateSourceArgs,
+    annotations::UpdateAnnotationsArgs, dataset::UpdateDatasetArgs, labels::UpdateLabelsArgs,
+    project::UpdateProjectArgs, source::UpdateSourceArgs, stream::UpdateStreamArgs,
     users::UpdateUsersArgs,
 };
 use crate::printer::Printer;
 use anyhow::Result;
 use reinfer_client::Client;
+use scoped_threadpool::Pool;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -22,20 +27,42 @@ pub enum UpdateArgs {
     /// Update an existing dataset
     Dataset(UpdateDatasetArgs),
 
+    #[structopt(name = "annotations")]
+    /// Rename, merge or remove labels in the reviewed comments of a dataset
+    Annotations(UpdateAnnotationsArgs),
+
+    #[structopt(name = "labels")]
+    /// Update the labels and entities of a dataset to match a taxonomy file
+    Labels(UpdateLabelsArgs),
+
     #[structopt(name = "project")]
     /// Update an existing project
     Project(UpdateProjectArgs),
 
+    #[structopt(name = "stream")]
+    /// Update an existing stream, optionally pinning it to another model version
+    Stream(UpdateStreamArgs),
+
     #[structopt(name = "users")]
     /// Update existing users
     Users(UpdateUsersArgs),
 }
 
-pub fn run(update_args: &UpdateArgs, client: Client, printer: &Printer) -> Result<()> {
+pub fn run(
+    update_args: &UpdateArgs,
+    client: Client,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
     match update_args {
         UpdateArgs::Source(source_args) => source::update(&client, source_args, printer),
         UpdateArgs::Dataset(dataset_args) => dataset::update(&client, dataset_args, printer),
+        UpdateArgs::Annotations(annotations_args) => {
+            annotations::update(&client, annotations_args, pool)
+        }
+        UpdateArgs::Labels(labels_args) => labels::update(&client, labels_args),
         UpdateArgs::Project(project_args) => project::update(&client, project_args, printer),
+        UpdateArgs::Stream(stream_args) => stream::update(&client, stream_args, printer, pool),
         UpdateArgs::Users(users_args) => users::update(&client, users_args),
     }
 }
@@ -0,0 +1,263 @@
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+use dialoguer::Confirm;
+use log::{info, warn};
+use ordered_float::NotNan;
+use reinfer_client::{
+    resources::stream::StreamModel, Client, ModelVersion, StreamFullName, UpdateStream,
+};
+use scoped_threadpool::Pool;
+use structopt::StructOpt;
+
+use crate::{
+    commands::{
+        export::project::new_stream,
+        get::streams::{get_compare_config, stream_stats, StreamStat},
+        stream::thresholds::Metric,
+    },
+    printer::Printer,
+};
+
+#[derive(Debug, StructOpt)]
+pub struct UpdateStreamArgs {
+    #[structopt(name = "stream")]
+    /// The full stream name `<owner>/<dataset>/<stream>`.
+    stream: StreamFullName,
+
+    #[structopt(long = "title")]
+    /// Set the title of the stream
+    title: Option<String>,
+
+    #[structopt(long = "description")]
+    /// Set the description of the stream
+    description: Option<String>,
+
+    #[structopt(long = "model-version")]
+    /// Pin the stream to another model version of its dataset
+    model_version: Option<ModelVersion>,
+
+    #[structopt(long = "maintain", requires = "model-version")]
+    /// Move the label thresholds to keep the current `precision` or `recall` under the new
+    /// model version, instead of keeping the thresholds as they are
+    maintain: Option<Metric>,
+
+    #[structopt(long)]
+    /// Only print the changes which would be made, without making them
+    dry_run: bool,
+
+    #[structopt(short = "y", long = "yes")]
+    /// Don't ask for confirmation before changing the model version
+    yes: bool,
+}
+
+/// A label threshold of a stream moved to maintain its precision or recall.
+#[derive(Debug, Clone, PartialEq)]
+struct ThresholdChange {
+    label_name: String,
+    from: NotNan<f64>,
+    to: NotNan<f64>,
+}
+
+pub fn update(
+    client: &Client,
+    args: &UpdateStreamArgs,
+    printer: &Printer,
+    pool: &mut Pool,
+) -> Result<()> {
+    let UpdateStreamArgs {
+        stream: stream_name,
+        title,
+        description,
+        model_version,
+        maintain,
+        dry_run,
+        yes,
+    } = args;
+
+    if title.is_none() && description.is_none() && model_version.is_none() {
+        bail!("Nothing to update, pass at least one of --title, --description or --model-version");
+    }
+
+    let stream = client
+        .get_stream(stream_name)
+        .context("Operation to get stream has failed.")?;
+    let mut new_stream = new_stream(stream);
+
+    if let Some(model_version) = model_version {
+        let model = new_stream
+            .model
+            .as_mut()
+            .context("Cannot change the model version of a stream without a model.")?;
+        info!(
+            "Comparing model version {} of stream `{}` with model version {}",
+            model.version.0, stream_name.stream.0, model_version.0
+        );
+        let compare_config = get_compare_config(
+            client,
+            &Some(model_version.clone()),
+            &None,
+            &stream_name.dataset,
+        )?;
+        let stats = stream_stats(client, stream_name, model, &compare_config, pool)?;
+        printer.print_resources(&stats)?;
+
+        if let Some(maintain) = maintain {
+            for change in maintain_thresholds(model, &stats, *maintain) {
+                println!(
+                    "{} {}: {} -> {}",
+                    "~".yellow().bold(),
+                    change.label_name,
+                    change.from,
+                    change.to.to_string().bold()
+                );
+            }
+        }
+        new_stream.set_model_version(model_version);
+    }
+
+    if *dry_run {
+        info!(
+            "Dry run, stream `{}` was not updated.",
+            stream_name.stream.0
+        );
+        return Ok(());
+    }
+    if let Some(model_version) = model_version {
+        if !yes
+            && !Confirm::new()
+                .with_prompt(format!(
+                    "Pin stream `{}` to model version {}?",
+                    stream_name.stream.0, model_version.0
+                ))
+                .interact()?
+        {
+            bail!("Stream update aborted by user");
+        }
+    }
+
+    let stream = client
+        .update_stream(
+            stream_name,
+            UpdateStream {
+                title: title.as_deref(),
+                description: description.as_deref(),
+                model: model_version.as_ref().and(new_stream.model.as_ref()),
+                label_filter: model_version.as_ref().and(new_stream.label_filter.as_ref()),
+                ..Default::default()
+            },
+        )
+        .context("Operation to update a stream has failed.")?;
+    info!(
+        "Stream `{}` [id: {}] updated successfully",
+        stream.name.0, stream.id.0,
+    );
+    printer.print_resources(&[stream])?;
+    Ok(())
+}
+
+/// Move the thresholds of a stream model to those which maintain a metric under the compared
+/// model version. Labels without such a threshold keep theirs.
+fn maintain_thresholds(
+    model: &mut StreamModel,
+    stats: &[StreamStat],
+    metric: Metric,
+) -> Vec<ThresholdChange> {
+    let mut changes = Vec::new();
+    for label_threshold in &mut model.label_thresholds {
+        let label_name = label_threshold.name.join(" > ");
+        let maintained = stats
+            .iter()
+            .find(|stat| stat.label_name.0 == label_name)
+            .and_then(|stat| match metric {
+                Metric::Precision => stat.maintain_precision_threshold,
+                Metric::Recall => stat.maintain_recall_threshold,
+            });
+        match maintained {
+            Some(threshold) if threshold != label_threshold.threshold => {
+                changes.push(ThresholdChange {
+                    label_name,
+                    from: label_threshold.threshold,
+                    to: threshold,
+                });
+                label_threshold.threshold = threshold;
+            }
+            Some(_) => {}
+            None => warn!("Keeping the threshold of label `{}`", label_name),
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{maintain_thresholds, ThresholdChange};
+    use crate::commands::{get::streams::StreamStat, stream::thresholds::Metric};
+    use ordered_float::NotNan;
+    use reinfer_client::{
+        resources::stream::{StreamLabelThreshold, StreamModel},
+        LabelName, ModelVersion,
+    };
+
+    fn not_nan(value: f64) -> NotNan<f64> {
+        NotNan::new(value).unwrap()
+    }
+
+    fn stat(label_name: &str, maintain_recall_threshold: Option<f64>) -> StreamStat {
+        StreamStat {
+            label_name: LabelName(label_name.to_owned()),
+            model_version: ModelVersion(3),
+            threshold: not_nan(0.5),
+            precision: not_nan(0.9),
+            recall: not_nan(0.8),
+            compare_to_model_version: Some(ModelVersion(4)),
+            compare_to_precision: None,
+            compare_to_recall: None,
+            compare_to_precision_delta: None,
+            compare_to_recall_delta: None,
+            maintain_recall_precision: None,
+            maintain_recall_threshold: maintain_recall_threshold.map(not_nan),
+            maintain_precision_recall: None,
+            maintain_precision_threshold: None,
+        }
+    }
+
+    #[test]
+    fn test_maintain_thresholds() {
+        let threshold = |name: &[&str]| StreamLabelThreshold {
+            name: name.iter().map(|part| part.to_string()).collect(),
+            threshold: not_nan(0.5),
+        };
+        let mut model = StreamModel {
+            version: ModelVersion(3),
+            label_thresholds: vec![
+                threshold(&["Billing", "Refund"]),
+                threshold(&["Complaint"]),
+                threshold(&["Other"]),
+            ],
+        };
+        let stats = vec![
+            stat("Billing > Refund", Some(0.25)),
+            stat("Complaint", Some(0.5)),
+            stat("Other", None),
+        ];
+
+        let changes = maintain_thresholds(&mut model, &stats, Metric::Recall);
+        assert_eq!(
+            changes,
+            vec![ThresholdChange {
+                label_name: "Billing > Refund".to_owned(),
+                from: not_nan(0.5),
+                to: not_nan(0.25),
+            }]
+        );
+        let thresholds: Vec<f64> = model
+            .label_thresholds
+            .iter()
+            .map(|label_threshold| *label_threshold.threshold)
+            .collect();
+        assert_eq!(thresholds, vec![0.25, 0.5, 0.5]);
+
+        // Without compared thresholds for precision, nothing moves.
+        assert!(maintain_thresholds(&mut model, &stats, Metric::Precision).is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use log::{debug, warn};
+use reinfer_client::Client;
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Requests slower than this multiple of the fastest request seen so far are considered a sign
+/// that the server is struggling, and do not count towards increasing the concurrency.
+const LATENCY_TOLERANCE: u32 = 2;
+
+/// Limits the number of requests in flight from the thread pool used by bulk operations.
+///
+/// The limit starts at the size of the pool and is halved whenever the server responds with a
+/// 429 or a `Retry-After` header. It then grows back by one each time as many requests as the
+/// current limit complete without being slowed down.
+pub struct AdaptiveConcurrency<'a> {
+    client: &'a Client,
+    max_limit: usize,
+    state: Mutex<State>,
+    available: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+    min_latency: Option<Duration>,
+    fast_requests: usize,
+    throttled_responses: u64,
+}
+
+impl<'a> AdaptiveConcurrency<'a> {
+    pub fn new(client: &'a Client, max_limit: usize) -> Self {
+        let max_limit = max_limit.max(1);
+        Self {
+            client,
+            max_limit,
+            state: Mutex::new(State {
+                limit: max_limit,
+                in_flight: 0,
+                min_latency: None,
+                fast_requests: 0,
+                throttled_responses: client.throttled_responses(),
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Run `request` once fewer requests than the current limit are in flight.
+    pub fn run<T>(&self, request: impl FnOnce() -> T) -> T {
+        {
+            let mut state = self
+                .available
+                .wait_while(self.lock(), |state| state.in_flight >= state.limit)
+                .expect("concurrency lock poisoned");
+            state.in_flight += 1;
+        }
+
+        let start = Instant::now();
+        let result = request();
+        let latency = start.elapsed();
+
+        let mut state = self.lock();
+        state.in_flight -= 1;
+        state.update(latency, self.client.throttled_responses(), self.max_limit);
+        drop(state);
+        self.available.notify_all();
+        result
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("concurrency lock poisoned")
+    }
+}
+
+impl State {
+    fn update(&mut self, latency: Duration, throttled_responses: u64, max_limit: usize) {
+        if throttled_responses > self.throttled_responses {
+            // Only back off once for all the requests which were throttled concurrently.
+            self.throttled_responses = throttled_responses;
+            self.fast_requests = 0;
+            if self.limit > 1 {
+                self.limit /= 2;
+                warn!(
+                    "The server asked to slow down, reducing concurrency to {}.",
+                    self.limit
+                );
+            }
+            return;
+        }
+
+        let min_latency = *self.min_latency.get_or_insert(latency);
+        if latency < min_latency {
+            self.min_latency = Some(latency);
+        }
+        if latency > min_latency * LATENCY_TOLERANCE {
+            return;
+        }
+
+        self.fast_requests += 1;
+        if self.fast_requests >= self.limit && self.limit < max_limit {
+            self.fast_requests = 0;
+            self.limit += 1;
+            debug!("Increasing concurrency to {}.", self.limit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use std::time::Duration;
+
+    #[test]
+    fn test_update_backs_off_and_recovers() {
+        let mut state = State {
+            limit: 8,
+            in_flight: 0,
+            min_latency: None,
+            fast_requests: 0,
+            throttled_responses: 0,
+        };
+        let fast = Duration::from_millis(100);
+        let slow = Duration::from_millis(500);
+
+        // Several requests throttled at the same time only halve the limit once.
+        state.update(fast, 3, 8);
+        state.update(fast, 3, 8);
+        assert_eq!(state.limit, 4);
+        state.update(fast, 4, 8);
+        assert_eq!(state.limit, 2);
+
+        // Slow requests don't increase the limit.
+        for _ in 0..10 {
+            state.update(slow, 4, 8);
+        }
+        assert_eq!(state.limit, 2);
+
+        // Each `limit` fast requests increase it by one, up to the maximum.
+        for _ in 0..2 {
+            state.update(fast, 4, 8);
+        }
+        assert_eq!(state.limit, 3);
+        for _ in 0..3 {
+            state.update(fast, 4, 8);
+        }
+        assert_eq!(state.limit, 4);
+        for _ in 0..100 {
+            state.update(fast, 4, 8);
+        }
+        assert_eq!(state.limit, 8);
+    }
+}
@@ -1,12 +1,16 @@
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use log::debug;
+use reinfer_client::{BucketIdentifier, DatasetIdentifier, ProjectName, SourceIdentifier};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+
+use crate::{credentials::CredentialStoreConfig, printer::OutputFormat};
 use std::{
     fs::File,
     io::{BufReader, BufWriter},
-    path::Path,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
@@ -15,6 +19,9 @@ pub struct ReinferConfig {
     contexts: Vec<ContextConfig>,
     #[serde(default = "default_true")]
     pub context_is_required: bool,
+    /// Where to store context tokens, if not in this file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_store: Option<CredentialStoreConfig>,
 }
 
 fn default_true() -> bool {
@@ -86,8 +93,119 @@ pub struct ContextConfig {
     pub name: String,
     pub endpoint: Url,
     pub token: Option<String>,
+    /// File from which to read the token on every invocation, e.g. a mounted secret. Takes
+    /// precedence over `token` and the credential store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
     pub accept_invalid_certificates: bool,
     pub proxy: Option<Url>,
+    /// Maximum number of requests per second to send to the endpoint, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+    /// Defaults for the arguments of commands run in this context.
+    #[serde(default, skip_serializing_if = "ContextDefaults::is_empty")]
+    pub defaults: ContextDefaults,
+}
+
+/// The keys of the context defaults which can be set with `re config set`.
+pub const CONTEXT_DEFAULT_KEYS: [&str; 8] = [
+    "project",
+    "source",
+    "dataset",
+    "bucket",
+    "output",
+    "num-threads",
+    "max-retries",
+    "retry-wait",
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ContextDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dataset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_threads: Option<u32>,
+    /// Number of times to retry failed requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retry_count: Option<u8>,
+    /// Seconds to wait before the first retry, doubled for each following one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_base_wait: Option<f64>,
+}
+
+impl ContextDefaults {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Set the default for one of `CONTEXT_DEFAULT_KEYS`, or remove it if there is no value.
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> Result<()> {
+        match key {
+            "project" => {
+                self.project = value.map(check_parses::<ProjectName>).transpose()?;
+            }
+            "source" => {
+                self.source = value.map(check_parses::<SourceIdentifier>).transpose()?;
+            }
+            "dataset" => {
+                self.dataset = value.map(check_parses::<DatasetIdentifier>).transpose()?;
+            }
+            "bucket" => {
+                self.bucket = value.map(check_parses::<BucketIdentifier>).transpose()?;
+            }
+            "output" => {
+                self.output = value.map(check_parses::<OutputFormat>).transpose()?;
+            }
+            "num-threads" => {
+                self.num_threads = value.map(str::parse).transpose()?;
+                ensure!(
+                    self.num_threads != Some(0),
+                    "The number of threads must be greater than 0"
+                );
+            }
+            "max-retries" => {
+                self.max_retry_count = value.map(str::parse).transpose()?;
+            }
+            "retry-wait" => {
+                self.retry_base_wait = value.map(str::parse).transpose()?;
+                ensure!(
+                    self.retry_base_wait.is_none_or(|wait| wait > 0.0),
+                    "The retry wait must be greater than 0"
+                );
+            }
+            _ => return Err(anyhow!("Unknown context default `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// The default of a command argument, for the keys of `CONTEXT_DEFAULT_KEYS` which name one.
+    pub fn argument(&self, key: &str) -> Option<&str> {
+        match key {
+            "project" => self.project.as_deref(),
+            "source" => self.source.as_deref(),
+            "dataset" => self.dataset.as_deref(),
+            "bucket" => self.bucket.as_deref(),
+            "output" => self.output.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn check_parses<T>(value: &str) -> Result<String>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    T::from_str(value).map_err(|error| anyhow!("Invalid value `{}`: {}", value, error))?;
+    Ok(value.to_owned())
 }
 
 pub fn read_reinfer_config(path: impl AsRef<Path>) -> Result<ReinferConfig> {
@@ -114,4 +232,32 @@ pub fn write_reinfer_config(path: impl AsRef<Path>, config: &ReinferConfig) -> R
             path.as_ref().display()
         )
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContextDefaults;
+
+    #[test]
+    fn test_set_context_defaults() {
+        let mut defaults = ContextDefaults::default();
+        defaults.set("source", Some("acme/emails")).unwrap();
+        defaults.set("output", Some("json")).unwrap();
+        defaults.set("max-retries", Some("5")).unwrap();
+        assert_eq!(defaults.source.as_deref(), Some("acme/emails"));
+        assert_eq!(defaults.max_retry_count, Some(5));
+        assert_eq!(defaults.argument("source"), Some("acme/emails"));
+        assert_eq!(defaults.argument("output"), Some("json"));
+        assert_eq!(defaults.argument("dataset"), None);
+
+        assert!(defaults.set("source", Some("emails")).is_err());
+        assert!(defaults.set("output", Some("xml")).is_err());
+        assert!(defaults.set("num-threads", Some("0")).is_err());
+
+        defaults.set("source", None).unwrap();
+        defaults.set("output", None).unwrap();
+        defaults.set("max-retries", None).unwrap();
+        defaults.set("num-threads", None).unwrap();
+        assert!(defaults.is_empty());
+    }
+}
@@ -0,0 +1,492 @@
+//! Storage of context tokens outside of the config file, in the credential store configured with
+//! `re config set-credential-store`. Tokens which are still in the config file are used as a
+//! fallback for contexts which have no token in the store.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use dialoguer::Password;
+use once_cell::sync::OnceCell;
+use openssl::{
+    hash::MessageDigest,
+    pkcs5::pbkdf2_hmac,
+    rand::rand_bytes,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::config::{ContextConfig, ReinferConfig};
+
+/// Environment variable from which to read the passphrase of an encrypted token file, instead of
+/// prompting for it.
+pub const PASSPHRASE_ENV_VARIABLE_NAME: &str = "REINFER_CREDENTIALS_PASSPHRASE";
+
+const KEY_DERIVATION_ITERATIONS: usize = 100_000;
+const KEY_LENGTH: usize = 32;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum CredentialStoreConfig {
+    /// An external command implementing the credential helper protocol.
+    Helper { command: String },
+    /// A file of tokens encrypted with a key derived from a passphrase.
+    EncryptedFile { path: PathBuf },
+}
+
+pub trait CredentialStore {
+    /// Get the token of a context, if the store has one.
+    fn get(&self, context: &ContextConfig) -> Result<Option<String>>;
+
+    fn store(&self, context: &ContextConfig, token: &str) -> Result<()>;
+
+    fn erase(&self, context: &ContextConfig) -> Result<()>;
+}
+
+pub fn open_store(config: &CredentialStoreConfig) -> Box<dyn CredentialStore> {
+    match config {
+        CredentialStoreConfig::Helper { command } => Box::new(HelperStore {
+            command: command.clone(),
+        }),
+        CredentialStoreConfig::EncryptedFile { path } => {
+            Box::new(EncryptedFileStore { path: path.clone() })
+        }
+    }
+}
+
+/// The token of a context, from its token file if it has one, then from the credential store, or
+/// else from the config file.
+pub fn context_token(config: &ReinferConfig, context: &ContextConfig) -> Result<Option<String>> {
+    if let Some(token_file) = &context.token_file {
+        let token = fs::read_to_string(token_file).with_context(|| {
+            format!(
+                "Could not read the token of context `{}` from `{}`",
+                context.name,
+                token_file.display()
+            )
+        })?;
+        return Ok(Some(token.trim().to_owned()));
+    }
+    if let Some(store_config) = &config.credential_store {
+        if let Some(token) = open_store(store_config).get(context)? {
+            return Ok(Some(token));
+        }
+    }
+    Ok(context.token.clone())
+}
+
+/// Runs `<command> get|store|erase` through the shell, like git credential helpers.
+///
+/// The context is written on stdin as `key=value` lines followed by an empty line, with the keys
+/// `context`, `endpoint` and, when storing, `token`. For `get`, the helper prints a `token=<token>`
+/// line on stdout, or nothing if it has no token for the context.
+struct HelperStore {
+    command: String,
+}
+
+impl HelperStore {
+    fn run(&self, action: &str, context: &ContextConfig, token: Option<&str>) -> Result<String> {
+        let mut child = shell_command(&self.command, action)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| format!("Could not run credential helper `{}`", self.command))?;
+
+        let mut input = format!("context={}\nendpoint={}\n", context.name, context.endpoint);
+        if let Some(token) = token {
+            input.push_str(&format!("token={token}\n"));
+        }
+        input.push('\n');
+        let written = child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(input.as_bytes());
+        // Helpers don't have to read their input, in which case the pipe may be closed early.
+        match written {
+            Err(error) if error.kind() != ErrorKind::BrokenPipe => {
+                return Err(error).with_context(|| {
+                    format!("Could not write to credential helper `{}`", self.command)
+                })
+            }
+            _ => {}
+        }
+
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("Could not run credential helper `{}`", self.command))?;
+        if !output.status.success() {
+            bail!(
+                "Credential helper `{} {}` failed with {}",
+                self.command,
+                action,
+                output.status
+            );
+        }
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("Credential helper `{}` printed invalid UTF-8", self.command))
+    }
+}
+
+impl CredentialStore for HelperStore {
+    fn get(&self, context: &ContextConfig) -> Result<Option<String>> {
+        Ok(self
+            .run("get", context, None)?
+            .lines()
+            .find_map(|line| line.strip_prefix("token="))
+            .filter(|token| !token.is_empty())
+            .map(str::to_owned))
+    }
+
+    fn store(&self, context: &ContextConfig, token: &str) -> Result<()> {
+        self.run("store", context, Some(token)).map(|_| ())
+    }
+
+    fn erase(&self, context: &ContextConfig) -> Result<()> {
+        self.run("erase", context, None).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str, action: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command)
+        .arg(action);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str, action: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(format!("{command} {action}"));
+    shell
+}
+
+/// Stores tokens encrypted with AES-256-GCM, using a key derived from a passphrase with
+/// PBKDF2-HMAC-SHA256. The passphrase is read from `REINFER_CREDENTIALS_PASSPHRASE` if set, and
+/// prompted for otherwise.
+struct EncryptedFileStore {
+    path: PathBuf,
+}
+
+impl EncryptedFileStore {
+    fn passphrase(&self, is_new_file: bool) -> Result<&'static str> {
+        static PASSPHRASE: OnceCell<String> = OnceCell::new();
+        PASSPHRASE
+            .get_or_try_init(|| {
+                if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VARIABLE_NAME) {
+                    return Ok(passphrase);
+                }
+                let mut prompt = Password::new();
+                prompt.with_prompt(format!("Passphrase for `{}`", self.path.display()));
+                if is_new_file {
+                    prompt.with_confirmation("Confirm passphrase", "Passphrases don't match");
+                }
+                prompt.interact().context("Could not read passphrase")
+            })
+            .map(String::as_str)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn get(&self, context: &ContextConfig) -> Result<Option<String>> {
+        match TokenFile::read(&self.path)? {
+            Some(file) if file.tokens.contains_key(&context.name) => {
+                file.decrypt(&context.name, self.passphrase(false)?)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn store(&self, context: &ContextConfig, token: &str) -> Result<()> {
+        let existing_file = TokenFile::read(&self.path)?;
+        let is_new_file = existing_file.is_none();
+        let mut file = existing_file.unwrap_or_else(TokenFile::new);
+        let passphrase = self.passphrase(is_new_file)?;
+        file.check_passphrase(passphrase)?;
+        file.encrypt(&context.name, token, passphrase)?;
+        file.write(&self.path)
+    }
+
+    fn erase(&self, context: &ContextConfig) -> Result<()> {
+        if let Some(mut file) = TokenFile::read(&self.path)? {
+            if file.tokens.remove(&context.name).is_some() {
+                file.write(&self.path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct TokenFile {
+    salt: String,
+    tokens: BTreeMap<String, EncryptedToken>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct EncryptedToken {
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+impl TokenFile {
+    fn new() -> Self {
+        let mut salt = [0; SALT_LENGTH];
+        rand_bytes(&mut salt).expect("Could not generate random salt");
+        Self {
+            salt: BASE64.encode(salt),
+            tokens: BTreeMap::new(),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read(path)
+            .with_context(|| format!("Could not read token file `{}`", path.display()))?;
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .with_context(|| format!("Could not parse token file `{}`", path.display()))
+    }
+
+    /// Replace the token file, writing to a temporary file first so that a failure midway
+    /// doesn't lose the tokens of every context.
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        match fs::remove_file(&temp_path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => {
+                return Err(error).with_context(|| {
+                    format!("Could not remove temporary file `{}`", temp_path.display())
+                })
+            }
+            _ => {}
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options
+            .open(&temp_path)
+            .with_context(|| format!("Could not create token file `{}`", temp_path.display()))?;
+        let written = self
+            .write_to(file)
+            .with_context(|| format!("Could not write token file `{}`", temp_path.display()))
+            .and_then(|()| {
+                fs::rename(&temp_path, path)
+                    .with_context(|| format!("Could not replace token file `{}`", path.display()))
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+
+    fn write_to(&self, file: File) -> Result<()> {
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.into_inner()?.sync_all()?;
+        Ok(())
+    }
+
+    fn key(&self, passphrase: &str) -> Result<[u8; KEY_LENGTH]> {
+        let salt = BASE64
+            .decode(&self.salt)
+            .context("Invalid salt in token file")?;
+        let mut key = [0; KEY_LENGTH];
+        pbkdf2_hmac(
+            passphrase.as_bytes(),
+            &salt,
+            KEY_DERIVATION_ITERATIONS,
+            MessageDigest::sha256(),
+            &mut key,
+        )
+        .context("Could not derive key from passphrase")?;
+        Ok(key)
+    }
+
+    /// Tokens are all encrypted with the same key, so check that the passphrase decrypts an
+    /// existing token before encrypting a new one with it.
+    fn check_passphrase(&self, passphrase: &str) -> Result<()> {
+        match self.tokens.keys().next() {
+            Some(name) => self.decrypt(name, passphrase).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn encrypt(&mut self, name: &str, token: &str, passphrase: &str) -> Result<()> {
+        let key = self.key(passphrase)?;
+        let mut nonce = [0; NONCE_LENGTH];
+        rand_bytes(&mut nonce).context("Could not generate random nonce")?;
+        let mut tag = [0; TAG_LENGTH];
+        // The context name is authenticated so that tokens cannot be swapped between contexts.
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &key,
+            Some(&nonce),
+            name.as_bytes(),
+            token.as_bytes(),
+            &mut tag,
+        )
+        .context("Could not encrypt token")?;
+        self.tokens.insert(
+            name.to_owned(),
+            EncryptedToken {
+                nonce: BASE64.encode(nonce),
+                ciphertext: BASE64.encode(ciphertext),
+                tag: BASE64.encode(tag),
+            },
+        );
+        Ok(())
+    }
+
+    fn decrypt(&self, name: &str, passphrase: &str) -> Result<Option<String>> {
+        let Some(encrypted) = self.tokens.get(name) else {
+            return Ok(None);
+        };
+        let decode = |value: &str| {
+            BASE64
+                .decode(value)
+                .with_context(|| format!("Invalid encrypted token for context `{name}`"))
+        };
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key(passphrase)?,
+            Some(&decode(&encrypted.nonce)?),
+            name.as_bytes(),
+            &decode(&encrypted.ciphertext)?,
+            &decode(&encrypted.tag)?,
+        )
+        .map_err(|_| {
+            anyhow!("Could not decrypt the token of context `{name}`, is the passphrase correct?")
+        })?;
+        String::from_utf8(plaintext)
+            .map(Some)
+            .context("Decrypted token is not valid UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{context_token, CredentialStore, HelperStore, TokenFile};
+    use crate::config::{ContextConfig, ReinferConfig};
+    use std::fs;
+
+    fn context(name: &str) -> ContextConfig {
+        ContextConfig {
+            name: name.to_owned(),
+            endpoint: "https://reinfer.io".parse().unwrap(),
+            token: None,
+            token_file: None,
+            accept_invalid_certificates: false,
+            proxy: None,
+            requests_per_second: None,
+            defaults: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_token_file_round_trip() {
+        let mut file = TokenFile::new();
+        file.check_passphrase("hunter2").unwrap();
+        file.encrypt("prod", "secret-token", "hunter2").unwrap();
+
+        let file: TokenFile = serde_json::from_value(serde_json::to_value(&file).unwrap()).unwrap();
+        assert_eq!(
+            file.decrypt("prod", "hunter2").unwrap().as_deref(),
+            Some("secret-token")
+        );
+        assert_eq!(file.decrypt("staging", "hunter2").unwrap(), None);
+        assert!(file.decrypt("prod", "hunter3").is_err());
+        assert!(file.check_passphrase("hunter3").is_err());
+
+        let path = std::env::temp_dir().join(format!("reinfer-tokens-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        file.write(&path).unwrap();
+        let written = TokenFile::read(&path).unwrap().unwrap();
+        assert_eq!(
+            written.decrypt("prod", "hunter2").unwrap().as_deref(),
+            Some("secret-token")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_helper_store() {
+        let store = HelperStore {
+            command: r#"helper() { read -r line; [ "$1" = get ] && echo "token=${line#context=}-token"; true; }; helper"#
+                .to_owned(),
+        };
+        assert_eq!(
+            store.get(&context("prod")).unwrap().as_deref(),
+            Some("prod-token")
+        );
+        store.store(&context("prod"), "token").unwrap();
+
+        let failing = HelperStore {
+            command: "false".to_owned(),
+        };
+        assert!(failing.get(&context("prod")).is_err());
+    }
+
+    #[test]
+    fn test_context_token_reads_token_file() {
+        let config = ReinferConfig::default();
+        let path = std::env::temp_dir().join(format!("reinfer-token-{}", uuid::Uuid::new_v4()));
+        let mut context = context("prod");
+        context.token = Some("config-token".to_owned());
+        assert_eq!(
+            context_token(&config, &context).unwrap().as_deref(),
+            Some("config-token")
+        );
+
+        // The file is read again every time, so that rotated tokens are picked up.
+        context.token_file = Some(path.clone());
+        fs::write(&path, "first-token\n").unwrap();
+        assert_eq!(
+            context_token(&config, &context).unwrap().as_deref(),
+            Some("first-token")
+        );
+        fs::write(&path, "second-token").unwrap();
+        assert_eq!(
+            context_token(&config, &context).unwrap().as_deref(),
+            Some("second-token")
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert!(context_token(&config, &context).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use anyhow::Error;
+use log::error;
+use serde_json::json;
+use std::fmt;
+
+use crate::args::ErrorFormat;
+
+/// Exit codes of `re`, appended to the help message.
+pub const EXIT_CODES_HELP: &str = "EXIT CODES:
+    0    Success
+    1    Any error without a more specific exit code
+    2    Unauthorized, the API token is missing, invalid or expired
+    3    Permission denied
+    4    Resource not found
+    5    Conflict with the current state of a resource, e.g. it already exists
+    6    Quota exceeded or rate limited
+    7    The API could not be reached
+    8    Partial failure, some of the input could not be uploaded";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Failure = 1,
+    Unauthorized = 2,
+    Forbidden = 3,
+    NotFound = 4,
+    Conflict = 5,
+    Throttled = 6,
+    Unreachable = 7,
+    PartialFailure = 8,
+}
+
+impl ExitCode {
+    /// Classify an error by the first cause in its chain which has a specific exit code.
+    pub fn for_error(error: &Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| {
+                if cause.is::<PartialFailure>() {
+                    return Some(ExitCode::PartialFailure);
+                }
+                match cause.downcast_ref::<reinfer_client::Error>()? {
+                    reinfer_client::Error::Unauthorized { .. } => Some(ExitCode::Unauthorized),
+                    reinfer_client::Error::Forbidden { .. } => Some(ExitCode::Forbidden),
+                    reinfer_client::Error::NotFound { .. } => Some(ExitCode::NotFound),
+                    reinfer_client::Error::Conflict { .. } => Some(ExitCode::Conflict),
+                    reinfer_client::Error::QuotaExceeded { .. }
+                    | reinfer_client::Error::RateLimited { .. } => Some(ExitCode::Throttled),
+                    reinfer_client::Error::ReqwestError { source, .. }
+                        if source.is_connect() || source.is_timeout() =>
+                    {
+                        Some(ExitCode::Unreachable)
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or(ExitCode::Failure)
+    }
+
+    fn kind(self) -> &'static str {
+        match self {
+            ExitCode::Failure => "failure",
+            ExitCode::Unauthorized => "unauthorized",
+            ExitCode::Forbidden => "forbidden",
+            ExitCode::NotFound => "not_found",
+            ExitCode::Conflict => "conflict",
+            ExitCode::Throttled => "throttled",
+            ExitCode::Unreachable => "unreachable",
+            ExitCode::PartialFailure => "partial_failure",
+        }
+    }
+}
+
+/// Error for operations which completed, but could not process all of their input.
+#[derive(Debug)]
+pub struct PartialFailure(pub String);
+
+impl fmt::Display for PartialFailure {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PartialFailure {}
+
+/// Print an error on stderr in the requested format, returning the exit code to use.
+pub fn report_error(error: &Error, format: ErrorFormat) -> ExitCode {
+    let exit_code = ExitCode::for_error(error);
+    let api_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reinfer_client::Error>());
+    let request_id = api_error.and_then(reinfer_client::Error::request_id);
+
+    match format {
+        ErrorFormat::Text => {
+            error!("An error occurred:");
+            for cause in error.chain() {
+                error!(" |- {}", cause);
+            }
+            if let Some(request_id) = request_id {
+                error!("Request id: {}", request_id);
+            }
+        }
+        ErrorFormat::Json => {
+            let report = json!({
+                "message": error.to_string(),
+                "chain": error.chain().map(ToString::to_string).collect::<Vec<_>>(),
+                "kind": exit_code.kind(),
+                "exit_code": exit_code as i32,
+                "status": api_error
+                    .and_then(reinfer_client::Error::status_code)
+                    .map(|status_code| status_code.as_u16()),
+                "request_id": request_id,
+            });
+            eprintln!("{report}");
+        }
+    }
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExitCode, PartialFailure};
+    use anyhow::{anyhow, Context};
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_exit_code_for_error() {
+        let not_found: anyhow::Result<()> = Err(reinfer_client::Error::NotFound {
+            message: "No such source".to_owned(),
+            request_id: None,
+        })
+        .context("Unable to get source");
+        assert_eq!(
+            ExitCode::for_error(&not_found.unwrap_err()),
+            ExitCode::NotFound
+        );
+
+        let quota = anyhow::Error::new(reinfer_client::Error::QuotaExceeded {
+            status_code: StatusCode::PAYMENT_REQUIRED,
+            message: String::new(),
+            request_id: None,
+        });
+        assert_eq!(ExitCode::for_error(&quota), ExitCode::Throttled);
+
+        let partial = anyhow::Error::new(PartialFailure("2 lines failed".to_owned()));
+        assert_eq!(ExitCode::for_error(&partial), ExitCode::PartialFailure);
+
+        assert_eq!(ExitCode::for_error(&anyhow!("Oops")), ExitCode::Failure);
+    }
+}
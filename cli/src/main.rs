@@ -2,35 +2,47 @@
 #![deny(clippy::all)]
 mod args;
 mod commands;
+mod concurrency;
 mod config;
+mod credentials;
+mod errors;
 mod printer;
 mod progress;
 mod thousands;
 mod utils;
 
-use anyhow::{anyhow, Context, Result};
-use log::{error, warn};
+use anyhow::{anyhow, ensure, Context, Result};
+use log::warn;
 use reinfer_client::{
+    rate_limit::RateLimitConfig,
     retry::{RetryConfig, RetryStrategy},
     Client, Config as ClientConfig, Token, DEFAULT_ENDPOINT,
 };
+use reqwest::Url;
 use scoped_threadpool::Pool;
 use std::{env, fs, io, path::PathBuf, process};
 use structopt::{clap::Shell as ClapShell, StructOpt};
 
 use crate::{
-    args::{Args, Command, Shell},
-    commands::{config as config_command, create, delete, get, parse, update},
-    config::ReinferConfig,
+    args::{Args, Command, GlobalArgs, PreArgs, Shell},
+    commands::{
+        apply, clone, config as config_command, create, delete, export, get, migrate, parse,
+        stream, update,
+    },
+    config::{ContextConfig, ReinferConfig},
     printer::Printer,
 };
 
 const NUM_THREADS_ENV_VARIABLE_NAME: &str = "REINFER_CLI_NUM_THREADS";
+const DEFAULT_NUM_THREADS: u32 = 32;
+const CONTEXT_ENV_VARIABLE_NAME: &str = "REINFER_CONTEXT";
+const ENDPOINT_ENV_VARIABLE_NAME: &str = "REINFER_ENDPOINT";
+const TOKEN_ENV_VARIABLE_NAME: &str = "REINFER_TOKEN";
 
 fn run(args: Args) -> Result<()> {
-    let config_path = find_configuration(&args)?;
+    let config_path = find_configuration(&args.global)?;
     let config = config::read_reinfer_config(&config_path)?;
-    let printer = Printer::new(args.output);
+    let printer = Printer::new(args.global.output.clone(), args.global.fields.clone())?;
 
     let number_of_threads = if let Ok(num_threads_env_var_str) =
         env::var(NUM_THREADS_ENV_VARIABLE_NAME)
@@ -39,7 +51,15 @@ fn run(args: Args) -> Result<()> {
                 .parse::<u32>()
                 .unwrap_or_else(|_| panic!("Environment variable {NUM_THREADS_ENV_VARIABLE_NAME} is not a u32: '{num_threads_env_var_str}'"))
     } else {
-        args.num_threads
+        args.global
+            .num_threads
+            .or_else(|| {
+                selected_context(&args.global, &config)
+                    .ok()
+                    .flatten()
+                    .and_then(|context| context.defaults.num_threads)
+            })
+            .unwrap_or(DEFAULT_NUM_THREADS)
     };
 
     let mut pool = Pool::new(number_of_threads);
@@ -59,49 +79,112 @@ fn run(args: Args) -> Result<()> {
         }
         Command::Get { get_args } => get::run(
             get_args,
-            client_from_args(&args, &config)?,
+            client_from_args(&args.global, &config)?,
             &printer,
             &mut pool,
         ),
         Command::Delete { delete_args } => {
-            delete::run(delete_args, client_from_args(&args, &config)?)
+            delete::run(delete_args, client_from_args(&args.global, &config)?)
         }
         Command::Create { create_args } => create::run(
             create_args,
-            client_from_args(&args, &config)?,
+            client_from_args(&args.global, &config)?,
             &printer,
             &mut pool,
         ),
-        Command::Update { update_args } => {
-            update::run(update_args, client_from_args(&args, &config)?, &printer)
+        Command::Update { update_args } => update::run(
+            update_args,
+            client_from_args(&args.global, &config)?,
+            &printer,
+            &mut pool,
+        ),
+        Command::Parse { parse_args } => parse::run(
+            parse_args,
+            client_from_args(&args.global, &config)?,
+            &mut pool,
+        ),
+        Command::Apply { apply_args } => {
+            apply::run(apply_args, client_from_args(&args.global, &config)?)
+        }
+        Command::Export { export_args } => {
+            export::run(export_args, client_from_args(&args.global, &config)?)
         }
-        Command::Parse { parse_args } => {
-            parse::run(parse_args, client_from_args(&args, &config)?, &mut pool)
+        Command::Clone { clone_args } => {
+            clone::run(clone_args, client_from_args(&args.global, &config)?)
         }
+        Command::Stream { stream_args } => stream::run(
+            stream_args,
+            client_from_args(&args.global, &config)?,
+            &printer,
+            &mut pool,
+        ),
+        Command::Migrate { migrate_args } => migrate::run(
+            migrate_args,
+            |context_name| client_from_context_name(&args.global, &config, context_name),
+            &mut pool,
+        ),
     }
 }
 
-fn client_from_args(args: &Args, config: &ReinferConfig) -> Result<Client> {
-    let current_context = if let Some(context_name) = args.context.as_ref() {
-        let context = config.get_context(context_name);
+/// The context given with `--context` or `REINFER_CONTEXT`, or else the current context.
+fn selected_context<'a>(
+    args: &GlobalArgs,
+    config: &'a ReinferConfig,
+) -> Result<Option<&'a ContextConfig>> {
+    if let Some(context_name) = context_name(args) {
+        let context = config.get_context(&context_name);
         if context.is_none() {
             return Err(anyhow!("Unknown context `{}`.", context_name));
         };
-        context
+        Ok(context)
     } else {
-        config.get_current_context()
-    };
+        Ok(config.get_current_context())
+    }
+}
 
-    let endpoint = args
-        .endpoint
-        .clone()
+fn client_from_args(args: &GlobalArgs, config: &ReinferConfig) -> Result<Client> {
+    let current_context = selected_context(args, config)?;
+
+    let client = client_from_context(args, config, current_context)?;
+
+    check_if_context_is_a_required_field(config, &client, args)?;
+
+    Ok(client)
+}
+
+/// Create a client for a context given by name, for commands which use more than one context.
+fn client_from_context_name(
+    args: &GlobalArgs,
+    config: &ReinferConfig,
+    context_name: &str,
+) -> Result<Client> {
+    if endpoint_override(args)?.is_some() || token_override(args).is_some() {
+        return Err(anyhow!(
+            "`--endpoint`, `--token`, {} and {} cannot be used with commands which take several contexts.",
+            ENDPOINT_ENV_VARIABLE_NAME,
+            TOKEN_ENV_VARIABLE_NAME
+        ));
+    }
+    let context = config
+        .get_context(context_name)
+        .ok_or_else(|| anyhow!("Unknown context `{}`.", context_name))?;
+    client_from_context(args, config, Some(context))
+}
+
+fn client_from_context(
+    args: &GlobalArgs,
+    config: &ReinferConfig,
+    current_context: Option<&ContextConfig>,
+) -> Result<Client> {
+    let endpoint = endpoint_override(args)?
         .or_else(|| current_context.map(|context| context.endpoint.clone()))
         .unwrap_or_else(|| DEFAULT_ENDPOINT.clone());
 
-    let args_or_config_token = args
-        .token
-        .clone()
-        .or_else(|| current_context.and_then(|context| context.token.clone()));
+    let args_or_config_token = match (token_override(args), current_context) {
+        (Some(token), _) => Some(token),
+        (None, Some(context)) => credentials::context_token(config, context)?,
+        (None, None) => None,
+    };
 
     let token = Token(if let Some(token) = args_or_config_token {
         token
@@ -127,26 +210,43 @@ fn client_from_args(args: &Args, config: &ReinferConfig) -> Result<Client> {
         .or_else(|| current_context.and_then(|context| context.proxy.clone()));
 
     // Retry everything but the very first request.
-    // Retry wait schedule is [5s, 10s, 20s, fail]. (Plus the time for each attempt to timeout.)
+    // Retry waits are random up to [5s, 10s, 20s, fail], or as long as the server asks for with
+    // `Retry-After`. (Plus the time for each attempt to timeout.)
+    // The number of retries and the first wait can be changed with `re config set`.
+    let defaults = current_context.map(|context| &context.defaults);
     let retry_config = RetryConfig {
         strategy: RetryStrategy::Always,
-        max_retry_count: 3,
-        base_wait: std::time::Duration::from_secs_f64(5.0),
+        max_retry_count: defaults
+            .and_then(|defaults| defaults.max_retry_count)
+            .unwrap_or(3),
+        base_wait: std::time::Duration::from_secs_f64(
+            defaults
+                .and_then(|defaults| defaults.retry_base_wait)
+                .unwrap_or(5.0),
+        ),
         backoff_factor: 2.0,
     };
 
-    let client = Client::new(ClientConfig {
+    let requests_per_second = current_context.and_then(|context| context.requests_per_second);
+    if let Some(requests_per_second) = requests_per_second {
+        ensure!(
+            requests_per_second > 0.0,
+            "The requests per second of a context must be greater than 0, got {}",
+            requests_per_second
+        );
+    }
+
+    Client::new(ClientConfig {
         endpoint,
         token,
         accept_invalid_certificates,
         proxy,
         retry_config: Some(retry_config),
+        rate_limit_config: requests_per_second.map(|requests_per_second| RateLimitConfig {
+            requests_per_second,
+        }),
     })
-    .context("Failed to initialise the HTTP client.")?;
-
-    check_if_context_is_a_required_field(config, &client, args)?;
-
-    Ok(client)
+    .context("Failed to initialise the HTTP client.")
 }
 
 const DOMAINS_THAT_REQUIRE_CONTEXT: [&str; 2] = ["uipath.com", "reinfer.io"];
@@ -154,9 +254,9 @@ const DOMAINS_THAT_REQUIRE_CONTEXT: [&str; 2] = ["uipath.com", "reinfer.io"];
 fn check_if_context_is_a_required_field(
     config: &ReinferConfig,
     client: &Client,
-    args: &Args,
+    args: &GlobalArgs,
 ) -> Result<()> {
-    let context_is_none = args.context.is_none() && args.endpoint.is_none();
+    let context_is_none = context_name(args).is_none() && endpoint_override(args)?.is_none();
 
     if config.context_is_required && context_is_none {
         return Err(anyhow!(
@@ -179,7 +279,42 @@ fn check_if_context_is_a_required_field(
     Ok(())
 }
 
-fn find_configuration(args: &Args) -> Result<PathBuf> {
+/// Read an environment variable, treating an empty value as unset.
+fn non_empty_env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// The context given with `--context`, or else with the `REINFER_CONTEXT` environment variable.
+fn context_name(args: &GlobalArgs) -> Option<String> {
+    args.context
+        .clone()
+        .or_else(|| non_empty_env_var(CONTEXT_ENV_VARIABLE_NAME))
+}
+
+/// The endpoint given with `--endpoint`, or else with the `REINFER_ENDPOINT` environment variable.
+fn endpoint_override(args: &GlobalArgs) -> Result<Option<Url>> {
+    if let Some(endpoint) = &args.endpoint {
+        return Ok(Some(endpoint.clone()));
+    }
+    non_empty_env_var(ENDPOINT_ENV_VARIABLE_NAME)
+        .map(|endpoint| {
+            Url::parse(&endpoint).with_context(|| {
+                format!(
+                    "Environment variable {ENDPOINT_ENV_VARIABLE_NAME} is not a valid URL: '{endpoint}'"
+                )
+            })
+        })
+        .transpose()
+}
+
+/// The token given with `--token`, or else with the `REINFER_TOKEN` environment variable.
+fn token_override(args: &GlobalArgs) -> Option<String> {
+    args.token
+        .clone()
+        .or_else(|| non_empty_env_var(TOKEN_ENV_VARIABLE_NAME))
+}
+
+fn find_configuration(args: &GlobalArgs) -> Result<PathBuf> {
     let config_path = if let Some(config_path) = args.config.clone() {
         if !config_path.exists() {
             warn!(
@@ -204,21 +339,33 @@ fn find_configuration(args: &Args) -> Result<PathBuf> {
     Ok(config_path)
 }
 
+/// Parse the arguments of `re`, once the defaults of the selected context which command
+/// arguments fall back to are known. Errors finding the context are left for `run` to report.
+fn parse_args() -> Args {
+    if let Ok(PreArgs { global, .. }) = PreArgs::from_iter_safe(env::args_os()) {
+        if let Ok(config) = find_configuration(&global).and_then(config::read_reinfer_config) {
+            if let Ok(Some(context)) = selected_context(&global, &config) {
+                args::set_context_defaults(context.defaults.clone());
+            }
+        }
+    }
+    Args::from_args()
+}
+
 fn main() {
-    let args = Args::from_args();
-    utils::init_env_logger(args.verbose);
+    let args = parse_args();
+    utils::init_env_logger(args.global.verbose);
+
+    let error_format = args.global.error_format;
 
     if let Err(error) = run(args) {
-        error!("An error occurred:");
-        for cause in error.chain() {
-            error!(" |- {}", cause);
-        }
+        let exit_code = errors::report_error(&error, error_format);
 
         #[cfg(feature = "backtrace")]
         {
-            error!("{}", error.backtrace());
+            log::error!("{}", error.backtrace());
         }
 
-        process::exit(1);
+        process::exit(exit_code as i32);
     }
 }
\ No newline at end of file
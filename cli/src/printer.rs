@@ -1,18 +1,22 @@
 
 use super::thousands::Thousands;
 use colored::Colorize;
-use prettytable::{format, row, Row, Table};
+use prettytable::{format, row, Cell, Row, Table};
 use reinfer_client::{
     resources::{
         audit::PrintableAuditEvent, bucket_statistics::Statistics as BucketStatistics,
         dataset::DatasetAndStats, integration::Integration, quota::Quota,
     },
-    Bucket, CommentStatistics, Dataset, Project, Source, Stream, User,
+    Bucket, CommentStatistics, Dataset, EntityDef, LabelGroupName, LabelName, Project, Source,
+    Stream, User,
 };
 use serde::{Serialize, Serializer};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use serde_json_path::JsonPath;
 
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use std::{
+    collections::HashMap,
     io::{self, Write},
     str::FromStr,
 };
@@ -32,23 +36,273 @@ where
     Ok(())
 }
 
-#[derive(Copy, Clone, Default, Debug)]
+pub fn print_resources_as_pretty_json<Resource>(
+    resources: impl IntoIterator<Item = Resource>,
+    mut writer: impl Write,
+) -> Result<()>
+where
+    Resource: Serialize,
+{
+    for resource in resources {
+        serde_json::to_writer_pretty(&mut writer, &resource)
+            .context("Could not serialise resource.")
+            .and_then(|_| writeln!(writer).context("Failed to write JSON resource to writer."))?;
+    }
+    Ok(())
+}
+
+/// Print resources as YAML documents, separated by `---`.
+pub fn print_resources_as_yaml<Resource>(
+    resources: impl IntoIterator<Item = Resource>,
+    mut writer: impl Write,
+) -> Result<()>
+where
+    Resource: Serialize,
+{
+    for (index, resource) in resources.into_iter().enumerate() {
+        if index > 0 {
+            writeln!(writer, "---").context("Failed to write YAML resource to writer.")?;
+        }
+        serde_yaml::to_writer(&mut writer, &resource).context("Could not serialise resource.")?;
+    }
+    Ok(())
+}
+
+/// Print resources as CSV, with one row per resource.
+///
+/// Nested objects are flattened into columns named by their dot separated path, other values
+/// which are not strings (e.g. arrays) are written as JSON. Columns are ordered by first
+/// appearance, and the fields of each resource are in alphabetical order.
+pub fn print_resources_as_csv<Resource>(
+    resources: impl IntoIterator<Item = Resource>,
+    writer: impl Write,
+) -> Result<()>
+where
+    Resource: Serialize,
+{
+    let mut headers = Vec::new();
+    let mut rows = Vec::new();
+    for resource in resources {
+        let value = serde_json::to_value(&resource).context("Could not serialise resource.")?;
+        let mut row = HashMap::new();
+        flatten_json_value(String::new(), value, &mut row, &mut headers);
+        rows.push(row);
+    }
+
+    let mut writer = csv::Writer::from_writer(writer);
+    writer
+        .write_record(&headers)
+        .context("Failed to write CSV header.")?;
+    for mut row in rows {
+        writer
+            .write_record(
+                headers
+                    .iter()
+                    .map(|header| row.remove(header).unwrap_or_default()),
+            )
+            .context("Failed to write CSV row.")?;
+    }
+    writer.flush().context("Failed to flush output.")
+}
+
+fn flatten_json_value(
+    key: String,
+    value: JsonValue,
+    row: &mut HashMap<String, String>,
+    headers: &mut Vec<String>,
+) {
+    let cell = match value {
+        JsonValue::Object(object) if !object.is_empty() => {
+            for (field, value) in object {
+                let field_key = if key.is_empty() {
+                    field
+                } else {
+                    format!("{key}.{field}")
+                };
+                flatten_json_value(field_key, value, row, headers);
+            }
+            return;
+        }
+        JsonValue::Null => String::new(),
+        JsonValue::String(string) => string,
+        value => value.to_string(),
+    };
+    let key = if key.is_empty() {
+        "value".to_owned()
+    } else {
+        key
+    };
+    if !headers.contains(&key) {
+        headers.push(key.clone());
+    }
+    row.insert(key, cell);
+}
+
+/// Print the values matched by a JSONPath expression in each resource, one per line.
+pub fn print_resources_with_json_path<Resource>(
+    resources: impl IntoIterator<Item = Resource>,
+    path: &JsonPath,
+    mut writer: impl Write,
+) -> Result<()>
+where
+    Resource: Serialize,
+{
+    for resource in resources {
+        let value = serde_json::to_value(&resource).context("Could not serialise resource.")?;
+        for node in path.query(&value).all() {
+            writeln!(writer, "{}", json_value_to_cell(node))
+                .context("Failed to write JSONPath result to writer.")?;
+        }
+    }
+    Ok(())
+}
+
+/// Print resources as a table with one column per JSONPath expression, using the first value
+/// each expression matches.
+pub fn print_resources_as_custom_columns<Resource>(
+    resources: impl IntoIterator<Item = Resource>,
+    columns: &[CustomColumn],
+) -> Result<()>
+where
+    Resource: Serialize,
+{
+    let mut table = new_table();
+    table.set_titles(Row::new(
+        columns
+            .iter()
+            .map(|column| Cell::new(&column.name).style_spec("bFg"))
+            .collect(),
+    ));
+    for resource in resources {
+        let value = serde_json::to_value(&resource).context("Could not serialise resource.")?;
+        table.add_row(Row::new(
+            columns
+                .iter()
+                .map(|column| {
+                    Cell::new(
+                        &column
+                            .path
+                            .query(&value)
+                            .first()
+                            .map(json_value_to_cell)
+                            .unwrap_or_else(|| "<none>".to_owned()),
+                    )
+                })
+                .collect(),
+        ));
+    }
+    table.printstd();
+    Ok(())
+}
+
+fn json_value_to_cell(value: &JsonValue) -> String {
+    match value {
+        JsonValue::Null => String::new(),
+        JsonValue::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Keep only the given dot separated fields of a serialised resource, e.g. `owner.name`.
+fn project_fields(value: &JsonValue, fields: &[String]) -> JsonValue {
+    let mut projected = JsonMap::new();
+    'fields: for field in fields {
+        let parts: Vec<&str> = field.split('.').collect();
+        let Some(field_value) = parts.iter().try_fold(value, |value, part| value.get(part)) else {
+            continue;
+        };
+
+        let (last, parents) = parts.split_last().expect("split always returns a part");
+        let mut target = &mut projected;
+        for part in parents {
+            let entry = target
+                .entry(part.to_string())
+                .or_insert_with(|| JsonValue::Object(JsonMap::new()));
+            match entry {
+                JsonValue::Object(object) => target = object,
+                // A parent field was already projected as a whole.
+                _ => continue 'fields,
+            }
+        }
+        target.insert(last.to_string(), field_value.clone());
+    }
+    JsonValue::Object(projected)
+}
+
+/// Parse a JSONPath expression, also accepting the kubectl style `{.field}` and `.field` forms.
+fn parse_json_path(expression: &str) -> Result<JsonPath> {
+    let trimmed = expression.trim();
+    let trimmed = trimmed
+        .strip_prefix('{')
+        .and_then(|trimmed| trimmed.strip_suffix('}'))
+        .unwrap_or(trimmed);
+    let full_expression = if trimmed.starts_with('$') {
+        trimmed.to_owned()
+    } else if trimmed.starts_with('.') || trimmed.starts_with('[') {
+        format!("${trimmed}")
+    } else {
+        format!("$.{trimmed}")
+    };
+    JsonPath::parse(&full_expression)
+        .map_err(|error| anyhow!("invalid JSONPath expression `{}`: {}", expression, error))
+}
+
+#[derive(Clone, Debug)]
+pub struct CustomColumn {
+    name: String,
+    path: JsonPath,
+}
+
+impl FromStr for CustomColumn {
+    type Err = Error;
+
+    fn from_str(string: &str) -> Result<Self> {
+        let (name, expression) = string
+            .split_once(':')
+            .ok_or_else(|| anyhow!("custom column `{}` must be of the form NAME:PATH", string))?;
+        Ok(Self {
+            name: name.to_owned(),
+            path: parse_json_path(expression)?,
+        })
+    }
+}
+
+#[derive(Clone, Default, Debug)]
 pub enum OutputFormat {
+    /// Pretty printed JSON.
     Json,
+    /// One JSON object per line.
+    Jsonl,
+    Yaml,
+    Csv,
     #[default]
     Table,
+    JsonPath(JsonPath),
+    CustomColumns(Vec<CustomColumn>),
 }
 
 impl FromStr for OutputFormat {
     type Err = Error;
 
     fn from_str(string: &str) -> Result<Self> {
-        if string == "table" {
-            Ok(OutputFormat::Table)
-        } else if string == "json" {
-            Ok(OutputFormat::Json)
-        } else {
-            Err(anyhow!("{}", string))
+        if let Some(expression) = string.strip_prefix("jsonpath=") {
+            return Ok(OutputFormat::JsonPath(parse_json_path(expression)?));
+        }
+        if let Some(columns) = string.strip_prefix("custom-columns=") {
+            return Ok(OutputFormat::CustomColumns(
+                columns
+                    .split(',')
+                    .map(CustomColumn::from_str)
+                    .collect::<Result<_>>()?,
+            ));
+        }
+        match string {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "yaml" => Ok(OutputFormat::Yaml),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(anyhow!("{}", string)),
         }
     }
 }
@@ -299,6 +553,33 @@ impl DisplayTable for Stream {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct PrintableLabelDef {
+    pub group: LabelGroupName,
+    pub name: LabelName,
+    pub title: String,
+    pub description: String,
+}
+impl DisplayTable for PrintableLabelDef {
+    fn to_table_headers() -> Row {
+        row![bFg => "Group", "Name", "Title", "Description"]
+    }
+
+    fn to_table_row(&self) -> Row {
+        row![self.group.0, self.name.0, self.title, self.description]
+    }
+}
+
+impl DisplayTable for EntityDef {
+    fn to_table_headers() -> Row {
+        row![bFg => "Name", "ID", "Title", "Trainable"]
+    }
+
+    fn to_table_row(&self) -> Row {
+        row![self.name.0, self.id.0, self.title, self.trainable]
+    }
+}
+
 impl DisplayTable for User {
     fn to_table_headers() -> Row {
         row![bFg => "Name", "Email", "ID", "Created (UTC)", "Global Permissions"]
@@ -411,11 +692,20 @@ fn print_table<T: IntoTable>(resources: T) {
 #[derive(Default, Debug)]
 pub struct Printer {
     output: OutputFormat,
+    fields: Vec<String>,
 }
 
 impl Printer {
-    pub fn new(output: OutputFormat) -> Self {
-        Self { output }
+    pub fn new(output: OutputFormat, fields: Vec<String>) -> Result<Self> {
+        if !fields.is_empty()
+            && matches!(
+                output,
+                OutputFormat::Table | OutputFormat::JsonPath(_) | OutputFormat::CustomColumns(_)
+            )
+        {
+            bail!("The `fields` option can only be used with json, jsonl, yaml or csv output.")
+        }
+        Ok(Self { output, fields })
     }
 
     pub fn print_resources<T, Resource>(&self, resources: T) -> Result<()>
@@ -423,10 +713,121 @@ impl Printer {
         T: IntoIterator<Item = Resource> + IntoTable,
         Resource: Serialize,
     {
-        match self.output {
+        match &self.output {
             OutputFormat::Table => print_table(resources),
-            OutputFormat::Json => print_resources_as_json(resources, io::stdout().lock())?,
+            OutputFormat::JsonPath(path) => {
+                print_resources_with_json_path(resources, path, io::stdout().lock())?
+            }
+            OutputFormat::CustomColumns(columns) => {
+                print_resources_as_custom_columns(resources, columns)?
+            }
+            _ if !self.fields.is_empty() => {
+                let projected = resources
+                    .into_iter()
+                    .map(|resource| {
+                        serde_json::to_value(&resource)
+                            .context("Could not serialise resource.")
+                            .map(|value| project_fields(&value, &self.fields))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                self.print_serialisable(projected)?
+            }
+            _ => self.print_serialisable(resources)?,
         };
         Ok(())
     }
+
+    fn print_serialisable<Resource: Serialize>(
+        &self,
+        resources: impl IntoIterator<Item = Resource>,
+    ) -> Result<()> {
+        match self.output {
+            OutputFormat::Json => print_resources_as_pretty_json(resources, io::stdout().lock()),
+            OutputFormat::Jsonl => print_resources_as_json(resources, io::stdout().lock()),
+            OutputFormat::Yaml => print_resources_as_yaml(resources, io::stdout().lock()),
+            OutputFormat::Csv => print_resources_as_csv(resources, io::stdout().lock()),
+            OutputFormat::Table | OutputFormat::JsonPath(_) | OutputFormat::CustomColumns(_) => {
+                unreachable!("handled by `print_resources`")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_json_path, print_resources_as_csv, print_resources_with_json_path, project_fields,
+        CustomColumn,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_print_resources_as_csv() {
+        let resources = vec![
+            json!({"name": "a", "owner": {"id": "1", "name": "x"}, "tags": ["t1", "t2"]}),
+            json!({"name": "b", "extra": null, "owner": {"id": "2"}}),
+        ];
+
+        let mut output = Vec::new();
+        print_resources_as_csv(resources, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            concat!(
+                "name,owner.id,owner.name,tags,extra\n",
+                "a,1,x,\"[\"\"t1\"\",\"\"t2\"\"]\",\n",
+                "b,2,,,\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_json_path() {
+        let value = json!({"owner": {"name": "x"}, "tags": ["a", "b"]});
+        for expression in ["owner.name", ".owner.name", "{.owner.name}", "$.owner.name"] {
+            assert_eq!(
+                parse_json_path(expression).unwrap().query(&value).all(),
+                vec![&json!("x")]
+            );
+        }
+        assert!(parse_json_path("owner[").is_err());
+        assert!("NAME".parse::<CustomColumn>().is_err());
+        assert!("NAME:.owner.name".parse::<CustomColumn>().is_ok());
+    }
+
+    #[test]
+    fn test_print_resources_with_json_path() {
+        let resources = vec![
+            json!({"name": "a", "tags": ["t1", "t2"]}),
+            json!({"name": "b", "tags": []}),
+        ];
+
+        let mut output = Vec::new();
+        print_resources_with_json_path(
+            resources,
+            &parse_json_path("{.tags[*]}").unwrap(),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "t1\nt2\n");
+    }
+
+    #[test]
+    fn test_project_fields() {
+        let value = json!({"id": "1", "owner": {"id": "2", "name": "x"}, "tags": ["t"]});
+
+        assert_eq!(
+            project_fields(
+                &value,
+                &[
+                    "id".to_owned(),
+                    "owner.name".to_owned(),
+                    "missing".to_owned(),
+                    "owner.name.nested".to_owned(),
+                ]
+            ),
+            json!({"id": "1", "owner": {"name": "x"}})
+        );
+    }
 }
\ No newline at end of file
@@ -8,6 +8,7 @@ use std::{
     env,
     io::{self, Write},
     ops::Deref,
+    path::{self, Path, PathBuf},
 };
 
 pub fn init_env_logger(verbose: bool) {
@@ -76,6 +77,13 @@ pub fn read_token_from_stdin() -> Result<Option<String>> {
     Ok(if !input.is_empty() { Some(input) } else { None })
 }
 
+/// The absolute path of a path given on the command line, to save it in the config file which is
+/// read from any working directory.
+pub fn absolute_path(path: &Path) -> Result<PathBuf> {
+    path::absolute(path)
+        .with_context(|| format!("Could not get the absolute path of `{}`", path.display()))
+}
+
 pub static LOG_PREFIX_DEBUG: Lazy<ColoredString> = Lazy::new(|| "D".normal());
 pub static LOG_PREFIX_INFO: Lazy<ColoredString> = Lazy::new(|| "I".green());
 pub static LOG_PREFIX_WARN: Lazy<ColoredString> = Lazy::new(|| "W".yellow().bold());